@@ -0,0 +1,294 @@
+//! Build script helper that generates typed handles of ferrite variables from EPICS database files.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     ferrite_build::Builder::new("Pvs")
+//!         .file("db/app.db")
+//!         .write("pvs.rs")
+//!         .unwrap();
+//! }
+//!
+//! // main.rs
+//! include!(concat!(env!("OUT_DIR"), "/pvs.rs"));
+//! ```
+
+use derive_more::{Display, Error, From};
+use ferrite_core::{
+    db::{self, LoadError, Macros, Record, UnsupportedRecordError},
+    variable::Type,
+};
+use std::{
+    collections::HashSet,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Display, Error, From)]
+pub enum Error {
+    Io(io::Error),
+    Load(LoadError),
+    Unsupported(UnsupportedRecordError),
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    Database(PathBuf),
+    Substitutions(PathBuf),
+}
+
+/// Generator of struct with a typed variable field for each ferrite record.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    struct_name: String,
+    sources: Vec<Source>,
+    macros: Macros,
+    dtyp: String,
+}
+
+impl Builder {
+    pub fn new(struct_name: &str) -> Self {
+        Self {
+            struct_name: struct_name.into(),
+            sources: Vec::new(),
+            macros: Macros::new(),
+            dtyp: db::DTYP.into(),
+        }
+    }
+
+    /// Add `.db` or `.template` file to read records from.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.sources.push(Source::Database(path.as_ref().into()));
+        self
+    }
+    /// Add `.substitutions` file to read records from.
+    pub fn substitutions<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.sources
+            .push(Source::Substitutions(path.as_ref().into()));
+        self
+    }
+    /// Define macro used in database files.
+    ///
+    /// Define prefix macro as empty to get names relative to the prefix.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.macros.insert(name.into(), value.into());
+        self
+    }
+    /// Override device type of ferrite records.
+    pub fn dtyp(mut self, dtyp: &str) -> Self {
+        self.dtyp = dtyp.into();
+        self
+    }
+
+    fn records(&self) -> Result<Vec<Record>, Error> {
+        let mut records = Vec::new();
+        for source in &self.sources {
+            let db = match source {
+                Source::Database(path) => db::load(path, &self.macros)?,
+                Source::Substitutions(path) => db::load_substitutions(path, &self.macros)?,
+            };
+            records.extend(
+                db.records
+                    .into_iter()
+                    .filter(|record| record.field("DTYP") == Some(self.dtyp.as_str())),
+            );
+        }
+        Ok(records)
+    }
+
+    /// Generate Rust source code.
+    pub fn generate(&self) -> Result<String, Error> {
+        let mut fields = Vec::new();
+        let mut idents = HashSet::new();
+        for record in self.records()? {
+            let (info, _) = db::record_info(&record).ok_or_else(|| UnsupportedRecordError {
+                name: record.name.clone(),
+                type_: record.type_.clone(),
+            })?;
+            let mut ident = field_ident(&record.name);
+            while !idents.insert(ident.clone()) {
+                ident.push('_');
+            }
+            let value = if info.max_len == 0 {
+                rust_type(info.type_).to_string()
+            } else {
+                format!("[{}]", rust_type(info.type_))
+            };
+            fields.push(Field {
+                ident,
+                value,
+                record,
+            });
+        }
+        Ok(format!(
+            "{}",
+            Code {
+                name: &self.struct_name,
+                fields: &fields
+            }
+        ))
+    }
+
+    /// Generate source code and write it to `file_name` in `OUT_DIR`.
+    ///
+    /// Should be called from build script, it is re-run when sources or templates they refer to are changed.
+    pub fn write(&self, file_name: &str) -> Result<(), Error> {
+        for source in &self.sources {
+            match source {
+                Source::Database(path) => rerun_if_changed(path),
+                Source::Substitutions(path) => {
+                    rerun_if_changed(path);
+                    for template in db::substitutions_templates(path, &self.macros)? {
+                        rerun_if_changed(&template);
+                    }
+                }
+            }
+        }
+        let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR is not set, call from build script");
+        fs::write(Path::new(&out_dir).join(file_name), self.generate()?)?;
+        Ok(())
+    }
+}
+
+fn rerun_if_changed(path: &Path) {
+    println!("cargo:rerun-if-changed={}", path.display());
+}
+
+struct Field {
+    ident: String,
+    value: String,
+    record: Record,
+}
+
+struct Code<'a> {
+    name: &'a str,
+    fields: &'a [Field],
+}
+
+impl fmt::Display for Code<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "/// Ferrite variables generated from EPICS database.")?;
+        writeln!(f, "pub struct {} {{", self.name)?;
+        for field in self.fields {
+            writeln!(
+                f,
+                "    /// `{}` ({})",
+                field.record.name, field.record.type_
+            )?;
+            writeln!(
+                f,
+                "    pub {}: ::ferrite_core::TypedVariable<{}>,",
+                field.ident, field.value
+            )?;
+        }
+        writeln!(f, "}}")?;
+        writeln!(f)?;
+        writeln!(f, "impl {} {{", self.name)?;
+        writeln!(f, "    /// Take all variables from registry.")?;
+        writeln!(f, "    pub fn take(")?;
+        writeln!(f, "        registry: &mut ::ferrite_core::Registry,")?;
+        writeln!(
+            f,
+            "    ) -> ::core::result::Result<Self, ::ferrite_core::registry::GetDowncastError> {{"
+        )?;
+        writeln!(f, "        ::core::result::Result::Ok(Self {{")?;
+        for field in self.fields {
+            writeln!(
+                f,
+                "            {}: registry.remove_downcast({:?})?,",
+                field.ident, field.record.name
+            )?;
+        }
+        writeln!(f, "        }})")?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "}}")
+    }
+}
+
+fn rust_type(type_: Type) -> &'static str {
+    match type_ {
+        Type::U8 => "u8",
+        Type::I8 => "i8",
+        Type::U16 => "u16",
+        Type::I16 => "i16",
+        Type::U32 => "u32",
+        Type::I32 => "i32",
+        Type::U64 => "u64",
+        Type::I64 => "i64",
+        Type::F32 => "f32",
+        Type::F64 => "f64",
+    }
+}
+
+/// Convert record name to valid snake case identifier.
+fn field_ident(name: &str) -> String {
+    let mut ident = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            ident.push(c.to_ascii_lowercase());
+        } else if !ident.is_empty() && !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+    while ident.ends_with('_') {
+        ident.pop();
+    }
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if is_keyword(&ident) {
+        ident.push('_');
+    }
+    ident
+}
+
+fn is_keyword(ident: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+        "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+        "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+        "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+        "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+    KEYWORDS.contains(&ident)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_idents() {
+        assert_eq!(field_ident("$(P)Temp:Set-Point"), "p_temp_set_point");
+        assert_eq!(field_ident("1:A"), "_1_a");
+        assert_eq!(field_ident("::"), "_");
+        assert_eq!(field_ident("TYPE"), "type_");
+        assert_eq!(field_ident("ABSTRACT"), "abstract_");
+        assert_eq!(field_ident("Become"), "become_");
+    }
+
+    #[test]
+    fn generate() {
+        let path = env::temp_dir().join(format!("ferrite-build-{}.db", std::process::id()));
+        fs::write(
+            &path,
+            concat!(
+                "record(ai, \"$(P)TEMP\") { field(DTYP, \"Ferrite\") }\n",
+                "record(aao, \"$(P)DATA\") { field(DTYP, \"Ferrite\") field(FTVL, \"SHORT\") field(NELM, \"4\") }\n",
+                "record(ao, \"$(P)temp\") { field(DTYP, \"Ferrite\") }\n",
+                "record(ai, \"$(P)SOFT\") {}\n",
+            ),
+        )
+        .unwrap();
+        let code = Builder::new("Pvs").file(&path).define("P", "").generate();
+        fs::remove_file(&path).unwrap();
+        let code = code.unwrap();
+        assert!(code.contains("pub struct Pvs {"));
+        assert!(code.contains("pub temp: ::ferrite_core::TypedVariable<f64>,"));
+        assert!(code.contains("pub data: ::ferrite_core::TypedVariable<[i16]>,"));
+        assert!(code.contains("pub temp_: ::ferrite_core::TypedVariable<f64>,"));
+        assert!(code.contains("temp_: registry.remove_downcast(\"temp\")?,"));
+        assert!(code.contains("::core::result::Result::Ok(Self {"));
+        assert!(!code.contains("soft"));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::Context;
+use std::{cmp::Reverse, thread};
+
+/// Application that receives only variables which names start with `prefix`.
+///
+/// Allows to link several independent applications into a single IOC, see [`apps!`](`crate::apps`).
+#[derive(Clone, Copy, Debug)]
+pub struct App {
+    pub name: &'static str,
+    pub prefix: &'static str,
+    pub main: fn(Context),
+}
+
+/// Split variables between `apps` and run each app in a separate thread.
+///
+/// Variable is routed to the app with the longest matching prefix.
+/// Blocks until all apps are finished.
+pub fn run(mut ctx: Context, apps: &[App]) {
+    let mut apps = apps.to_vec();
+    apps.sort_by_key(|app| Reverse(app.prefix.len()));
+
+    let contexts: Vec<_> = apps
+        .iter()
+        .map(|app| {
+            let mut registry = ctx
+                .registry
+                .remove_filter(|name| name.starts_with(app.prefix));
+            registry.set_owner(app.name);
+            Context {
+                registry,
+                diagnostics: ctx.diagnostics.clone(),
+            }
+        })
+        .collect();
+    for name in ctx.registry.keys() {
+        log::warn!("PV '{}' is not routed to any app", name);
+    }
+
+    let handles: Vec<_> = apps
+        .into_iter()
+        .zip(contexts)
+        .map(|(app, ctx)| {
+            thread::Builder::new()
+                .name(app.name.into())
+                .spawn(move || (app.main)(ctx))
+                .unwrap()
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::FakeVar,
+        variable::{Direction, Type},
+        Diagnostics, Registry,
+    };
+
+    #[test]
+    fn longest_prefix() {
+        let mut registry = Registry::default();
+        for name in ["APP:X", "APP:SUB:Y", "OTHER:Z"] {
+            let var = FakeVar::new(name, Type::I32, 0, Direction::Input).leak();
+            registry.insert(name.into(), var.var());
+        }
+        let ctx = Context {
+            registry,
+            diagnostics: Diagnostics::default(),
+        };
+        run(
+            ctx,
+            &[
+                App {
+                    name: "app",
+                    prefix: "APP:",
+                    main: |ctx| {
+                        assert_eq!(ctx.registry.owner(), "app");
+                        assert_eq!(ctx.registry.keys().collect::<Vec<_>>(), ["APP:X"]);
+                    },
+                },
+                App {
+                    name: "sub",
+                    prefix: "APP:SUB:",
+                    main: |ctx| {
+                        assert_eq!(ctx.registry.owner(), "sub");
+                        assert_eq!(ctx.registry.keys().collect::<Vec<_>>(), ["APP:SUB:Y"]);
+                    },
+                },
+            ],
+        );
+    }
+}
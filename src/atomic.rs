@@ -63,35 +63,72 @@ impl<T: Type + Atom> AtomicVariable<T> {
         self.value.load()
     }
 
-    pub fn store(self: &Arc<Self>, value: T) {
-        self.value.store(value);
+    fn updated(self: &Arc<Self>) {
         self.update.store(true, Ordering::Release);
         self.notify(&mut self.variable.lock());
     }
+
+    pub fn store(self: &Arc<Self>, value: T) {
+        self.value.store(value);
+        self.updated();
+    }
     pub fn swap(self: &Arc<Self>, value: T) -> T {
         let old = self.value.swap(value);
-        self.update.store(true, Ordering::Release);
-        self.notify(&mut self.variable.lock());
+        self.updated();
         old
     }
     pub fn compare_exchange(self: &Arc<Self>, current: T, new: T) -> Result<T, T> {
         let res = self.value.compare_exchange(current, new);
         if res.is_ok() {
-            self.update.store(true, Ordering::Release);
-            self.notify(&mut self.variable.lock());
+            self.updated();
         }
         res
     }
     pub fn fetch_update<F: FnMut(T) -> Option<T>>(self: &Arc<Self>, f: F) -> Result<T, T> {
         let res = self.value.fetch_update(f);
         if res.is_ok() {
-            self.update.store(true, Ordering::Release);
-            self.notify(&mut self.variable.lock());
+            self.updated();
         }
         res
     }
 }
 
+impl<T: Type + AtomArithmetic> AtomicVariable<T> {
+    pub fn fetch_add(self: &Arc<Self>, value: T) -> T {
+        let old = T::fetch_add(&self.value, value);
+        self.updated();
+        old
+    }
+    pub fn fetch_sub(self: &Arc<Self>, value: T) -> T {
+        let old = T::fetch_sub(&self.value, value);
+        self.updated();
+        old
+    }
+    pub fn fetch_max(self: &Arc<Self>, value: T) -> T {
+        let old = T::fetch_max(&self.value, value);
+        self.updated();
+        old
+    }
+    pub fn fetch_min(self: &Arc<Self>, value: T) -> T {
+        let old = T::fetch_min(&self.value, value);
+        self.updated();
+        old
+    }
+}
+
+impl<T: Type + AtomBitwise> AtomicVariable<T> {
+    pub fn fetch_or(self: &Arc<Self>, value: T) -> T {
+        let old = T::fetch_or(&self.value, value);
+        self.updated();
+        old
+    }
+    pub fn fetch_and(self: &Arc<Self>, value: T) -> T {
+        let old = T::fetch_and(&self.value, value);
+        self.updated();
+        old
+    }
+}
+
 impl<T: Type + Atom> ArcWake for AtomicVariable<T> {
     fn wake_by_ref(this: &Arc<Self>) {
         // Variable is already locked when waker is called.
@@ -105,3 +142,220 @@ impl<T: Type + Atom> AsRef<AsyncAtomic<T>> for AtomicVariable<T> {
         &self.value
     }
 }
+
+/// Arithmetic operations on [`AsyncAtomic`].
+///
+/// Integers use native atomic instructions, floats are updated in a CAS loop.
+pub trait AtomArithmetic: Atom + Sized {
+    fn fetch_add(atomic: &AsyncAtomic<Self>, value: Self) -> Self;
+    fn fetch_sub(atomic: &AsyncAtomic<Self>, value: Self) -> Self;
+    fn fetch_max(atomic: &AsyncAtomic<Self>, value: Self) -> Self;
+    fn fetch_min(atomic: &AsyncAtomic<Self>, value: Self) -> Self;
+}
+
+/// Bitwise operations on [`AsyncAtomic`].
+pub trait AtomBitwise: Atom + Sized {
+    fn fetch_or(atomic: &AsyncAtomic<Self>, value: Self) -> Self;
+    fn fetch_and(atomic: &AsyncAtomic<Self>, value: Self) -> Self;
+}
+
+macro_rules! impl_atom_int {
+    ($($t:ty),* $(,)?) => {$(
+        impl AtomArithmetic for $t {
+            fn fetch_add(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_add(value)
+            }
+            fn fetch_sub(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_sub(value)
+            }
+            fn fetch_max(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_max(value)
+            }
+            fn fetch_min(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_min(value)
+            }
+        }
+        impl AtomBitwise for $t {
+            fn fetch_or(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_or(value)
+            }
+            fn fetch_and(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_and(value)
+            }
+        }
+    )*};
+}
+
+impl_atom_int!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+macro_rules! impl_atom_float {
+    ($($t:ty),* $(,)?) => {$(
+        impl AtomArithmetic for $t {
+            fn fetch_add(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_update(|x| Some(x + value)).unwrap()
+            }
+            fn fetch_sub(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_update(|x| Some(x - value)).unwrap()
+            }
+            fn fetch_max(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_update(|x| Some(x.max(value))).unwrap()
+            }
+            fn fetch_min(atomic: &AsyncAtomic<Self>, value: Self) -> Self {
+                atomic.fetch_update(|x| Some(x.min(value))).unwrap()
+            }
+        }
+    )*};
+}
+
+impl_atom_float!(f32, f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamic::{DynValue, Scalar},
+        testing::FakeVar,
+        variable::{Direction, Type as VarType},
+        Downcast,
+    };
+    use std::{
+        fmt::Debug,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    type Op<T> = fn(&Arc<AtomicVariable<T>>, T) -> T;
+
+    /// Atomic variable backed by an input record that is processed on request.
+    fn atomic<T: Scalar + Atom + Default>(
+        name: &str,
+        type_: VarType,
+    ) -> (&'static FakeVar, Arc<AtomicVariable<T>>) {
+        let fake = FakeVar::new(name, type_, 0, Direction::Input)
+            .auto_process()
+            .leak();
+        let var = Downcast::<TypedVariable<T>>::downcast(fake.var()).unwrap();
+        (fake, AtomicVariable::new(var))
+    }
+
+    /// Wait until `count` updates are committed and `value` is written to record.
+    fn committed<T: Scalar + Atom>(fake: &'static FakeVar, var: &AtomicVariable<T>, count: usize)
+    where
+        DynValue: From<T>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while fake.commits.lock().unwrap().len() < count || fake.load() != var.load().into() {
+            assert!(Instant::now() < deadline, "Timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(fake.commits.lock().unwrap().len(), count);
+        assert_eq!(fake.requests.load(Ordering::SeqCst), count);
+        assert!(!var.update.load(Ordering::Acquire));
+    }
+
+    /// Apply each `(op, arg, old, new)` and check that it is committed as [`AtomicVariable::store`] is.
+    fn check<T: Scalar + Atom + Default + PartialEq + Debug>(
+        name: &str,
+        type_: VarType,
+        initial: T,
+        ops: &[(Op<T>, T, T, T)],
+    ) where
+        DynValue: From<T>,
+    {
+        let (fake, var) = atomic::<T>(name, type_);
+        var.store(initial);
+        committed(fake, &var, 1);
+        for (index, (op, arg, old, new)) in ops.iter().enumerate() {
+            assert_eq!(op(&var, *arg), *old);
+            assert_eq!(var.load(), *new);
+            committed(fake, &var, index + 2);
+        }
+    }
+
+    #[test]
+    fn integers() {
+        check::<i32>(
+            "ATOMIC:I32",
+            VarType::I32,
+            0,
+            &[
+                (AtomicVariable::fetch_add, 5, 0, 5),
+                (AtomicVariable::fetch_sub, 7, 5, -2),
+                (AtomicVariable::fetch_max, 3, -2, 3),
+                (AtomicVariable::fetch_max, 1, 3, 3),
+                (AtomicVariable::fetch_min, -4, 3, -4),
+                (AtomicVariable::fetch_and, 0xff, -4, 0xfc),
+                (AtomicVariable::fetch_or, 0x300, 0xfc, 0x3fc),
+            ],
+        );
+    }
+
+    #[test]
+    fn wrapping() {
+        check::<u8>(
+            "ATOMIC:U8",
+            VarType::U8,
+            250,
+            &[
+                (AtomicVariable::fetch_add, 10, 250, 4),
+                (AtomicVariable::fetch_sub, 5, 4, 255),
+            ],
+        );
+        check::<i16>(
+            "ATOMIC:I16",
+            VarType::I16,
+            i16::MIN,
+            &[(AtomicVariable::fetch_sub, 1, i16::MIN, i16::MAX)],
+        );
+    }
+
+    #[test]
+    fn floats() {
+        check::<f64>(
+            "ATOMIC:F64",
+            VarType::F64,
+            0.0,
+            &[
+                (AtomicVariable::fetch_add, 1.5, 0.0, 1.5),
+                (AtomicVariable::fetch_sub, 4.0, 1.5, -2.5),
+                (AtomicVariable::fetch_max, 1.0, -2.5, 1.0),
+                (AtomicVariable::fetch_max, -1.0, 1.0, 1.0),
+                (AtomicVariable::fetch_min, -0.5, 1.0, -0.5),
+            ],
+        );
+        check::<f32>(
+            "ATOMIC:F32",
+            VarType::F32,
+            f32::MAX,
+            &[
+                (AtomicVariable::fetch_add, f32::MAX, f32::MAX, f32::INFINITY),
+                (AtomicVariable::fetch_min, 0.25, f32::INFINITY, 0.25),
+            ],
+        );
+    }
+
+    #[test]
+    fn concurrent() {
+        let (fake, var) = atomic::<f64>("ATOMIC:CONCURRENT", VarType::F64);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let var = var.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        var.fetch_add(0.5);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        // No update is lost in CAS loop.
+        assert_eq!(var.load(), 2000.0);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while fake.load() != DynValue::F64(2000.0) {
+            assert!(Instant::now() < deadline, "Timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
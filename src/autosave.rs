@@ -0,0 +1,453 @@
+//! Saving values of persistent variables and restoring them on startup.
+//!
+//! File format is line-based: `NAME TYPE VALUE...`, array types are suffixed with `[]`.
+
+use crate::{
+    dynamic::{dispatch_type, Conversion, DynValue, DynVariable, Scalar},
+    manifest::Manifest,
+    pattern::glob_match,
+    registry::{shared, Observer},
+    variable::Type,
+    Registry, TypedVariable,
+};
+use derive_more::{Display, Error, From};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Write as _},
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Saved values by full variable names.
+pub type Values = BTreeMap<String, DynValue>;
+
+#[derive(Debug, Display, Error, From)]
+pub enum AutosaveError {
+    Io(io::Error),
+    #[display(fmt = "{}:{}: {}", "path.display()", "line", "message")]
+    #[from(ignore)]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        #[error(not(source))]
+        message: String,
+    },
+}
+
+/// Save/restore of persistent variables.
+///
+/// Variables are selected by full names, either explicitly, by glob pattern or from [`Manifest`].
+#[derive(Clone, Debug)]
+pub struct Autosave {
+    path: PathBuf,
+    period: Duration,
+    names: BTreeSet<String>,
+    patterns: Vec<String>,
+}
+
+impl Autosave {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().into(),
+            period: Duration::from_secs(10),
+            names: BTreeSet::new(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Interval between snapshots, 10 seconds by default.
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+    pub fn name(mut self, name: &str) -> Self {
+        self.names.insert(name.into());
+        self
+    }
+    /// Select all variables matching glob `pattern`.
+    pub fn matching(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+    /// Select entries of `manifest` marked as [`persistent`](`crate::manifest::Entry::persistent`).
+    pub fn manifest(mut self, manifest: &Manifest) -> Self {
+        for entry in manifest.entries().iter().filter(|entry| entry.persistent) {
+            self.names.insert(entry.name.clone());
+        }
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn is_persistent(&self, name: &str) -> bool {
+        self.names.contains(name)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| glob_match(pattern, name))
+    }
+
+    /// Read saved values, empty if file doesn't exist yet.
+    pub fn load(&self) -> Result<Values, AutosaveError> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Values::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut values = Values::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = parse_line(line).map_err(|message| AutosaveError::Parse {
+                path: self.path.clone(),
+                line: index + 1,
+                message,
+            })?;
+            values.insert(name, value);
+        }
+        Ok(values)
+    }
+
+    /// Write values to file.
+    ///
+    /// *File is replaced atomically and synced to disk, so it's never left partially written.*
+    pub fn save(&self, values: &Values) -> Result<(), AutosaveError> {
+        let mut text = String::from("# Ferrite autosave\n");
+        for (name, value) in values {
+            format_line(&mut text, name, value);
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp, &self.path)?;
+        sync_parent(&self.path)?;
+        Ok(())
+    }
+
+    /// Write saved values to persistent variables of `registry`.
+    ///
+    /// Should be called before device tasks are started.
+    /// Variables stay in `registry`, it is borrowed mutably so that no one else accesses them meanwhile.
+    /// Returns number of restored variables.
+    pub async fn restore(&self, registry: &mut Registry) -> Result<usize, AutosaveError> {
+        let values = self.load()?;
+        let mut count = 0;
+        for (name, var) in registry.iter() {
+            let name = registry.full_name(name);
+            let value = match values.get(&name) {
+                Some(value) if self.is_persistent(&name) => value,
+                _ => continue,
+            };
+            // Temporary handle is dropped before `registry` is released.
+            // Variable can't be claimed meanwhile because it is held by `registry`.
+            let mut var: DynVariable =
+                unsafe { TypedVariable::new_unchecked(var.clone_unchecked()) };
+            match var.request().await.write(value, Conversion::LOSSLESS) {
+                Ok(commit) => {
+                    commit.await;
+                    count += 1;
+                }
+                Err(err) => log::warn!("PV '{}': Cannot restore saved value: {}", name, err),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Periodically save values of all persistent variables in a separate thread.
+    ///
+    /// *Variables that are being processed at the moment of snapshot keep previously saved value.*
+    pub fn spawn(self) -> AutosaveHandle {
+        let observers: Vec<(String, Observer<DynValue>)> = shared()
+            .names()
+            .into_iter()
+            .filter(|name| self.is_persistent(name))
+            .filter_map(|name| {
+                let observer = shared().get_downcast(&name).ok()?;
+                Some((name, observer))
+            })
+            .collect();
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = thread::Builder::new()
+            .name("autosave".into())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    let mut values = self.load().unwrap_or_else(|err| {
+                        log::error!("Autosave: {}", err);
+                        Values::new()
+                    });
+                    loop {
+                        let (flag, cvar) = &*stop;
+                        let stopped = *cvar
+                            .wait_timeout_while(flag.lock().unwrap(), self.period, |stopped| {
+                                !*stopped
+                            })
+                            .unwrap()
+                            .0;
+                        self.update(&observers, &mut values);
+                        if stopped {
+                            break;
+                        }
+                    }
+                }
+            })
+            .unwrap();
+        AutosaveHandle { stop, thread }
+    }
+
+    /// Load current values and save them if any has changed.
+    fn update(&self, observers: &[(String, Observer<DynValue>)], values: &mut Values) {
+        let mut changed = false;
+        for (name, observer) in observers {
+            if let Some(value) = observer.load() {
+                if values.get(name) != Some(&value) {
+                    values.insert(name.clone(), value);
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            if let Err(err) = self.save(values) {
+                log::error!("Autosave: {}", err);
+            }
+        }
+    }
+}
+
+/// Handle of the thread started by [`Autosave::spawn`].
+///
+/// *Dropping the handle leaves the thread running.*
+pub struct AutosaveHandle {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl AutosaveHandle {
+    /// Save current values for the last time and wait for the thread to finish.
+    pub fn stop(self) {
+        let (flag, cvar) = &*self.stop;
+        *flag.lock().unwrap() = true;
+        cvar.notify_all();
+        if self.thread.join().is_err() {
+            log::error!("Autosave: Thread panicked");
+        }
+    }
+}
+
+/// Make rename of the file durable.
+fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn type_name(type_: Type) -> String {
+    format!("{:?}", type_)
+}
+
+fn format_line(text: &mut String, name: &str, value: &DynValue) {
+    fn elements<T: Scalar + Display>(value: &DynValue) -> Vec<String> {
+        let elements = match value {
+            DynValue::Array(_) => value.get_array::<T>(Conversion::LOSSLESS).unwrap(),
+            _ => vec![value.get_scalar::<T>(Conversion::LOSSLESS).unwrap()],
+        };
+        elements.into_iter().map(|x| x.to_string()).collect()
+    }
+    let suffix = if value.is_array() { "[]" } else { "" };
+    write!(text, "{} {}{}", name, type_name(value.type_()), suffix).unwrap();
+    for element in dispatch_type!(value.type_(), elements, value) {
+        write!(text, " {}", element).unwrap();
+    }
+    text.push('\n');
+}
+
+fn parse_line(line: &str) -> Result<(String, DynValue), String> {
+    fn parse<T: Scalar + FromStr>(tokens: &[&str], array: bool) -> Result<DynValue, String> {
+        let elements = tokens
+            .iter()
+            .map(|token| {
+                token
+                    .parse::<T>()
+                    .map_err(|_| format!("Invalid {:?} value '{}'", T::TYPE, token))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if array {
+            Ok(DynValue::Array(T::into_dyn_array(elements)))
+        } else if let [element] = elements[..] {
+            Ok(element.into_dyn())
+        } else {
+            Err(format!("Expected single value, got {}", elements.len()))
+        }
+    }
+    let mut tokens = line.split_whitespace();
+    let name = tokens.next().unwrap();
+    let type_token = tokens.next().ok_or("Missing type")?;
+    let (type_token, array) = match type_token.strip_suffix("[]") {
+        Some(type_token) => (type_token, true),
+        None => (type_token, false),
+    };
+    let type_ = Type::ALL
+        .into_iter()
+        .find(|type_| type_name(*type_) == type_token)
+        .ok_or_else(|| format!("Unknown type '{}'", type_token))?;
+    let tokens: Vec<_> = tokens.collect();
+    let value = dispatch_type!(type_, parse, &tokens, array)?;
+    Ok((name.into(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dynamic::DynArray, testing::FakeVar, variable::Direction};
+    use futures::executor::block_on;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ferrite-{}-{}.sav", name, std::process::id()))
+    }
+
+    #[test]
+    fn lines() {
+        let values = [
+            ("A", DynValue::from(-1.5f64)),
+            ("B", DynValue::from(42u16)),
+            ("C", DynValue::from(vec![1i8, -2, 3])),
+            ("D", DynValue::Array(DynArray::F32(Vec::new()))),
+        ];
+        let mut text = String::new();
+        for (name, value) in &values {
+            format_line(&mut text, name, value);
+        }
+        assert_eq!(text, "A F64 -1.5\nB U16 42\nC I8[] 1 -2 3\nD F32[]\n");
+        for (line, (name, value)) in text.lines().zip(&values) {
+            assert_eq!(parse_line(line).unwrap(), (name.to_string(), value.clone()));
+        }
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(parse_line("A").unwrap_err(), "Missing type");
+        assert_eq!(parse_line("A F16 1").unwrap_err(), "Unknown type 'F16'");
+        assert_eq!(
+            parse_line("A U8 256").unwrap_err(),
+            "Invalid U8 value '256'"
+        );
+        assert_eq!(
+            parse_line("A U8 1 2").unwrap_err(),
+            "Expected single value, got 2"
+        );
+        assert!(parse_line("A U8").is_err());
+    }
+
+    #[test]
+    fn save_load() {
+        let autosave = Autosave::new(temp_path("save-load"));
+        assert!(autosave.load().unwrap().is_empty());
+        let values: Values = [
+            ("X:A".to_string(), DynValue::from(1i32)),
+            ("X:B".to_string(), DynValue::from(vec![0.5f64, 2.0])),
+        ]
+        .into();
+        autosave.save(&values).unwrap();
+        assert_eq!(autosave.load().unwrap(), values);
+
+        fs::write(autosave.path(), "# comment\n\nX:A I32 1\nX:B U8 x\n").unwrap();
+        let err = autosave.load().unwrap_err();
+        fs::remove_file(autosave.path()).unwrap();
+        assert!(matches!(err, AutosaveError::Parse { line: 4, .. }));
+    }
+
+    #[test]
+    fn persistent() {
+        let manifest = Manifest::new()
+            .scalar::<f64>("M:A", Direction::Output)
+            .persistent()
+            .scalar::<f64>("M:B", Direction::Output);
+        let autosave = Autosave::new("unused")
+            .name("N:A")
+            .matching("P:*:SP")
+            .manifest(&manifest);
+        assert!(autosave.is_persistent("N:A"));
+        assert!(autosave.is_persistent("P:1:SP"));
+        assert!(autosave.is_persistent("M:A"));
+        assert!(!autosave.is_persistent("M:B"));
+        assert!(!autosave.is_persistent("P:1:RB"));
+    }
+
+    #[test]
+    fn restore() {
+        let autosave = Autosave::new(temp_path("restore")).matching("RESTORE:*");
+        autosave
+            .save(
+                &[
+                    ("RESTORE:A".to_string(), DynValue::from(2.5f64)),
+                    ("RESTORE:B".to_string(), DynValue::from(300u16)),
+                    ("OTHER".to_string(), DynValue::from(1.0f64)),
+                ]
+                .into(),
+            )
+            .unwrap();
+        let mut registry = Registry::default();
+        let vars = [
+            ("RESTORE:A", Type::F64),
+            ("RESTORE:B", Type::U8),
+            ("OTHER", Type::F64),
+        ]
+        .map(|(name, type_)| {
+            let fake = FakeVar::new(name, type_, 0, Direction::Output)
+                .auto_process()
+                .leak();
+            registry.insert(name.into(), fake.var());
+            fake
+        });
+        let count = block_on(autosave.restore(&mut registry));
+        fs::remove_file(autosave.path()).unwrap();
+        assert_eq!(count.unwrap(), 1);
+        assert_eq!(registry.len(), 3);
+        assert_eq!(vars[0].load(), DynValue::from(2.5f64));
+        // Value doesn't fit, record is rejected.
+        assert_eq!(vars[1].load(), DynValue::from(0u8));
+        assert!(vars[1].commits.lock().unwrap()[0].is_err());
+        assert_eq!(
+            vars[2].requests.load(std::sync::atomic::Ordering::SeqCst),
+            0
+        );
+    }
+
+    #[test]
+    fn spawn_stop() {
+        let fake = FakeVar::new("AUTOSAVE:SPAWN", Type::I32, 0, Direction::Output)
+            .scalar(7i32)
+            .leak();
+        shared().add_variable(fake.var());
+        let autosave = Autosave::new(temp_path("spawn"))
+            .name("AUTOSAVE:SPAWN")
+            .period(Duration::from_secs(3600));
+        let path = autosave.path().to_owned();
+        autosave.clone().spawn().stop();
+        let values = autosave.load();
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            values.unwrap(),
+            [("AUTOSAVE:SPAWN".to_string(), DynValue::from(7i32))].into()
+        );
+    }
+}
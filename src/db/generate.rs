@@ -0,0 +1,142 @@
+use super::{ftvl, AUTOSAVE_INFO, DTYP};
+use crate::{
+    manifest::{Direction, Entry, Manifest},
+    variable::Type,
+};
+use derive_more::{Display, Error, From};
+use std::{fmt::Write as _, fs, io, path::Path};
+
+#[derive(Debug, Display, Error, From)]
+pub enum GenerateError {
+    #[display(
+        fmt = "PV '{}': No record type for {:?} {:?}",
+        "name",
+        "direction",
+        "type_"
+    )]
+    #[from(ignore)]
+    Unsupported {
+        name: String,
+        type_: Type,
+        direction: Direction,
+    },
+    Io(io::Error),
+}
+
+/// Generator of EPICS database from [`Manifest`].
+///
+/// Intended to be used in build scripts to keep `.db` files in sync with application code.
+#[derive(Clone, Debug)]
+pub struct Generator {
+    dtyp: String,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self { dtyp: DTYP.into() }
+    }
+}
+
+impl Generator {
+    /// Override device type of generated records.
+    pub fn dtyp(mut self, dtyp: &str) -> Self {
+        self.dtyp = dtyp.into();
+        self
+    }
+
+    /// Record type that backs variable.
+    pub fn record_type(entry: &Entry) -> Option<&'static str> {
+        Some(
+            match (entry.info.max_len, entry.info.type_, entry.direction) {
+                (0, Type::F64, Direction::Input) => "ai",
+                (0, Type::F64, Direction::Output) => "ao",
+                (0, Type::I32, Direction::Input) => "longin",
+                (0, Type::I32, Direction::Output) => "longout",
+                (0, Type::I64, Direction::Input) => "int64in",
+                (0, Type::I64, Direction::Output) => "int64out",
+                (0, Type::U16, Direction::Input) => "bi",
+                (0, Type::U16, Direction::Output) => "bo",
+                (0, _, _) => return None,
+                (_, _, Direction::Input) => "aai",
+                (_, _, Direction::Output) => "aao",
+            },
+        )
+    }
+
+    pub fn generate(&self, manifest: &Manifest) -> Result<String, GenerateError> {
+        let mut text = String::new();
+        for entry in manifest.entries() {
+            let record_type = Self::record_type(entry).ok_or(GenerateError::Unsupported {
+                name: entry.name.clone(),
+                type_: entry.info.type_,
+                direction: entry.direction,
+            })?;
+            writeln!(text, "record({}, \"{}\") {{", record_type, entry.name).unwrap();
+            writeln!(text, "    field(DTYP, \"{}\")", self.dtyp).unwrap();
+            if entry.direction == Direction::Input {
+                writeln!(text, "    field(SCAN, \"I/O Intr\")").unwrap();
+            }
+            if entry.info.max_len != 0 {
+                writeln!(text, "    field(FTVL, \"{}\")", ftvl(entry.info.type_)).unwrap();
+                writeln!(text, "    field(NELM, \"{}\")", entry.info.max_len).unwrap();
+            }
+            if entry.persistent {
+                writeln!(text, "    info({}, \"VAL\")", AUTOSAVE_INFO).unwrap();
+            }
+            writeln!(text, "}}").unwrap();
+        }
+        Ok(text)
+    }
+
+    /// Generate database and write it to file if its contents changed.
+    pub fn write<P: AsRef<Path>>(&self, manifest: &Manifest, path: P) -> Result<(), GenerateError> {
+        let text = self.generate(manifest)?;
+        if fs::read_to_string(&path).ok().as_deref() != Some(text.as_str()) {
+            fs::write(path, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate() {
+        let manifest = Manifest::new()
+            .scalar::<f64>("TEMP", Direction::Input)
+            .array::<u8>("NAME", 16, Direction::Output)
+            .persistent();
+        assert_eq!(
+            Generator::default()
+                .dtyp("Test")
+                .generate(&manifest)
+                .unwrap(),
+            concat!(
+                "record(ai, \"TEMP\") {\n",
+                "    field(DTYP, \"Test\")\n",
+                "    field(SCAN, \"I/O Intr\")\n",
+                "}\n",
+                "record(aao, \"NAME\") {\n",
+                "    field(DTYP, \"Test\")\n",
+                "    field(FTVL, \"UCHAR\")\n",
+                "    field(NELM, \"16\")\n",
+                "    info(autosaveFields, \"VAL\")\n",
+                "}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let manifest = Manifest::new().scalar::<u8>("BYTE", Direction::Output);
+        match Generator::default().generate(&manifest) {
+            Err(GenerateError::Unsupported { name, type_, .. }) => {
+                assert_eq!(name, "BYTE");
+                assert_eq!(type_, Type::U8);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use super::ParseError;
+use std::collections::HashMap;
+
+/// Macro definitions by name.
+pub type Macros = HashMap<String, String>;
+
+/// Maximum depth of macro references inside macro values.
+const MAX_DEPTH: usize = 16;
+
+/// Substitute `$(NAME)`, `${NAME}` and `$(NAME=default)` macro references in `text`.
+///
+/// Macro values and defaults may contain other macro references.
+/// Default is expanded only if the macro is undefined.
+pub fn expand(text: &str, macros: &Macros) -> Result<String, ParseError> {
+    Expander { macros, line: 1 }.expand(text, 0)
+}
+
+struct Expander<'a> {
+    macros: &'a Macros,
+    line: usize,
+}
+
+impl Expander<'_> {
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn expand(&mut self, text: &str, depth: usize) -> Result<String, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Too deep or recursive macro reference".into()));
+        }
+        let mut output = String::new();
+        let mut rest = text;
+        while let Some(pos) = rest.find('$') {
+            output.push_str(&rest[..pos]);
+            if depth == 0 {
+                self.line += rest[..pos].matches('\n').count();
+            }
+            rest = &rest[pos..];
+            let close = match rest[1..].chars().next() {
+                Some('(') => ')',
+                Some('{') => '}',
+                _ => {
+                    output.push('$');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            let len = reference_len(rest, close)
+                .ok_or_else(|| self.error("Unterminated macro reference".into()))?;
+            let (name, default) = split_default(&rest[2..(len - 1)]);
+            let name = self.expand(name, depth + 1)?;
+            match self.macros.get(&name) {
+                Some(value) => output.push_str(&self.expand(value, depth + 1)?),
+                None => match default {
+                    Some(default) => output.push_str(&self.expand(default, depth + 1)?),
+                    None => return Err(self.error(format!("Undefined macro '{}'", name))),
+                },
+            }
+            rest = &rest[len..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+/// Split `NAME=default` at the first `=` outside of nested references.
+fn split_default(inner: &str) -> (&str, Option<&str>) {
+    let mut depth = 0usize;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth = depth.saturating_sub(1),
+            '=' if depth == 0 => return (&inner[..i], Some(&inner[(i + 1)..])),
+            _ => (),
+        }
+    }
+    (inner, None)
+}
+
+/// Length of macro reference at the beginning of `text`, `None` if there is no terminated reference.
+pub(super) fn reference_at(text: &str) -> Option<usize> {
+    match text.strip_prefix('$')?.chars().next()? {
+        '(' => reference_len(text, ')'),
+        '{' => reference_len(text, '}'),
+        _ => None,
+    }
+}
+
+/// Length of macro reference at the beginning of `text` including `$` and brackets.
+fn reference_len(text: &str, close: char) -> Option<usize> {
+    let open = if close == ')' { '(' } else { '{' };
+    let mut depth = 0;
+    for (i, c) in text.char_indices().skip(1) {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn macros(defs: &[(&str, &str)]) -> Macros {
+        defs.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expand_references() {
+        let macros = macros(&[("P", "DEV:"), ("N", "1"), ("Q", "$(P)CH$(N)"), ("PN", "x")]);
+        assert_eq!(expand("$(P)A ${N} $$ $", &macros).unwrap(), "DEV:A 1 $$ $");
+        assert_eq!(expand("$(Q):B", &macros).unwrap(), "DEV:CH1:B");
+        assert_eq!(expand("$(P$(N)=none)", &macros).unwrap(), "none");
+        assert_eq!(expand("$(P$(X=N))", &macros).unwrap(), "x");
+    }
+
+    #[test]
+    fn lazy_default() {
+        let macros = macros(&[("A", "a"), ("C", "$(A)")]);
+        assert_eq!(expand("$(A=$(B))", &macros).unwrap(), "a");
+        assert_eq!(expand("$(B=$(C)-b)", &macros).unwrap(), "a-b");
+        assert_eq!(
+            expand("$(B=$(D))", &macros).unwrap_err().message,
+            "Undefined macro 'D'"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let macros = macros(&[("R", "$(R)")]);
+        assert_eq!(expand("a\nb\n$(X)", &macros).unwrap_err().line, 3);
+        assert_eq!(expand("\n$(A", &macros).unwrap_err().line, 2);
+        assert!(expand("$(R)", &macros).is_err());
+    }
+
+    #[test]
+    fn reference_length() {
+        assert_eq!(reference_at("$(A$(B))C"), Some(8));
+        assert_eq!(reference_at("${A}"), Some(4));
+        assert_eq!(reference_at("$(A"), None);
+        assert_eq!(reference_at("$A"), None);
+    }
+}
@@ -0,0 +1,294 @@
+//! EPICS database files support.
+
+mod generate;
+mod macros;
+mod parse;
+mod substitutions;
+
+pub use generate::{GenerateError, Generator};
+pub use macros::{expand, Macros};
+pub use parse::{parse, Database, ParseError, Record};
+#[cfg(feature = "stream")]
+pub(crate) use parse::{Parser, Token};
+pub use substitutions::{parse_substitutions, Substitution, Substitutions};
+
+use crate::{
+    manifest::{Direction, Entry, Manifest},
+    variable::{Info, Type},
+};
+use derive_more::{Display, Error, From};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Default device type of ferrite records.
+pub const DTYP: &str = "Ferrite";
+
+/// Value of `FTVL` field for specified type.
+pub fn ftvl(type_: Type) -> &'static str {
+    match type_ {
+        Type::U8 => "UCHAR",
+        Type::I8 => "CHAR",
+        Type::U16 => "USHORT",
+        Type::I16 => "SHORT",
+        Type::U32 => "ULONG",
+        Type::I32 => "LONG",
+        Type::U64 => "UINT64",
+        Type::I64 => "INT64",
+        Type::F32 => "FLOAT",
+        Type::F64 => "DOUBLE",
+    }
+}
+
+/// Type for specified value of `FTVL` field.
+pub fn ftvl_type(ftvl: &str) -> Option<Type> {
+    Some(match ftvl {
+        "UCHAR" => Type::U8,
+        "CHAR" => Type::I8,
+        "USHORT" => Type::U16,
+        "SHORT" => Type::I16,
+        "ULONG" => Type::U32,
+        "LONG" => Type::I32,
+        "UINT64" => Type::U64,
+        "INT64" => Type::I64,
+        "FLOAT" => Type::F32,
+        "DOUBLE" => Type::F64,
+        _ => return None,
+    })
+}
+
+/// Variable info and direction of the record, `None` if record type is not supported.
+///
+/// Inverse of [`Generator::record_type`].
+pub fn record_info(record: &Record) -> Option<(Info, Direction)> {
+    let scalar = |type_| Info { type_, max_len: 0 };
+    let array = || {
+        Some(Info {
+            type_: ftvl_type(record.field("FTVL").unwrap_or("STRING"))?,
+            max_len: record.field("NELM").unwrap_or("1").parse().ok()?,
+        })
+    };
+    Some(match record.type_.as_str() {
+        "ai" => (scalar(Type::F64), Direction::Input),
+        "ao" => (scalar(Type::F64), Direction::Output),
+        "longin" => (scalar(Type::I32), Direction::Input),
+        "longout" => (scalar(Type::I32), Direction::Output),
+        "int64in" => (scalar(Type::I64), Direction::Input),
+        "int64out" => (scalar(Type::I64), Direction::Output),
+        "bi" => (scalar(Type::U16), Direction::Input),
+        "bo" => (scalar(Type::U16), Direction::Output),
+        "aai" | "waveform" => (array()?, Direction::Input),
+        "aao" => (array()?, Direction::Output),
+        _ => return None,
+    })
+}
+
+/// Name of info tag listing autosaved fields.
+pub const AUTOSAVE_INFO: &str = "autosaveFields";
+
+/// Whether record `VAL` is listed in autosave info tags.
+fn is_autosaved(record: &Record) -> bool {
+    [
+        AUTOSAVE_INFO,
+        "autosaveFields_pass0",
+        "autosaveFields_pass1",
+    ]
+    .iter()
+    .filter_map(|tag| record.info(tag))
+    .any(|fields| fields.split_whitespace().any(|field| field == "VAL"))
+}
+
+/// Parse contents of `.template` file substituting `macros`.
+pub fn parse_template(text: &str, macros: &Macros) -> Result<Database, ParseError> {
+    parse(&expand(text, macros)?)
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum LoadError {
+    Io(io::Error),
+    #[display(fmt = "{}: {}", "path.display()", "error")]
+    #[from(ignore)]
+    Parse {
+        path: PathBuf,
+        error: ParseError,
+    },
+}
+
+fn read(path: &Path) -> Result<String, LoadError> {
+    Ok(fs::read_to_string(path)?)
+}
+
+/// Load `.db` or `.template` file substituting `macros`.
+pub fn load<P: AsRef<Path>>(path: P, macros: &Macros) -> Result<Database, LoadError> {
+    let path = path.as_ref();
+    parse_template(&read(path)?, macros).map_err(|error| LoadError::Parse {
+        path: path.into(),
+        error,
+    })
+}
+
+fn read_substitutions(path: &Path, macros: &Macros) -> Result<Substitutions, LoadError> {
+    parse_substitutions(&read(path)?, macros).map_err(|error| LoadError::Parse {
+        path: path.into(),
+        error,
+    })
+}
+fn template_path(path: &Path, file: &str) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(file)
+}
+
+/// Load `.substitutions` file and all templates it refers to.
+///
+/// Template paths are relative to the directory of substitutions file.
+pub fn load_substitutions<P: AsRef<Path>>(path: P, macros: &Macros) -> Result<Database, LoadError> {
+    let path = path.as_ref();
+    let subs = read_substitutions(path, macros)?;
+    let mut db = Database::default();
+    for sub in subs.files {
+        let template_path = template_path(path, &sub.file);
+        let template = read(&template_path)?;
+        for set in sub.sets {
+            let mut set_macros = macros.clone();
+            set_macros.extend(set);
+            let part =
+                parse_template(&template, &set_macros).map_err(|error| LoadError::Parse {
+                    path: template_path.clone(),
+                    error,
+                })?;
+            db.records.extend(part.records);
+        }
+    }
+    Ok(db)
+}
+
+/// Paths of templates that `.substitutions` file refers to, as they are resolved by [`load_substitutions`].
+pub fn substitutions_templates<P: AsRef<Path>>(
+    path: P,
+    macros: &Macros,
+) -> Result<Vec<PathBuf>, LoadError> {
+    let path = path.as_ref();
+    Ok(read_substitutions(path, macros)?
+        .files
+        .iter()
+        .map(|sub| template_path(path, &sub.file))
+        .collect())
+}
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "Record '{}': Unsupported record type '{}'", "name", "type_")]
+pub struct UnsupportedRecordError {
+    pub name: String,
+    pub type_: String,
+}
+
+impl Database {
+    /// Manifest of ferrite records (having specified `dtyp`).
+    ///
+    /// Can be used with [`Registry::validate`](`crate::Registry::validate`)
+    /// to cross-check actual variables against database.
+    pub fn manifest(&self, dtyp: &str) -> Result<Manifest, UnsupportedRecordError> {
+        let mut manifest = Manifest::new();
+        for record in &self.records {
+            if record.field("DTYP") != Some(dtyp) {
+                continue;
+            }
+            let (info, direction) = record_info(record).ok_or_else(|| UnsupportedRecordError {
+                name: record.name.clone(),
+                type_: record.type_.clone(),
+            })?;
+            manifest = manifest.entry(Entry {
+                name: record.name.clone(),
+                info,
+                direction,
+                persistent: is_autosaved(record),
+            });
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_info() {
+        let db = parse(concat!(
+            "record(longout, A) {}\n",
+            "record(waveform, B) { field(FTVL, FLOAT) field(NELM, 8) }\n",
+            "record(aai, C) { field(FTVL, STRING) }\n",
+            "record(calc, D) {}\n",
+        ))
+        .unwrap();
+        let info = |name| super::record_info(db.record(name).unwrap());
+        assert_eq!(
+            info("A"),
+            Some((
+                Info {
+                    type_: Type::I32,
+                    max_len: 0
+                },
+                Direction::Output
+            ))
+        );
+        assert_eq!(
+            info("B"),
+            Some((
+                Info {
+                    type_: Type::F32,
+                    max_len: 8
+                },
+                Direction::Input
+            ))
+        );
+        assert_eq!(info("C"), None);
+        assert_eq!(info("D"), None);
+    }
+
+    #[test]
+    fn manifest_roundtrip() {
+        let manifest = Manifest::new()
+            .scalar::<f64>("TEMP", Direction::Input)
+            .scalar::<u16>("ON", Direction::Output)
+            .persistent()
+            .array::<i16>("DATA", 32, Direction::Input);
+        let mut text = Generator::default().generate(&manifest).unwrap();
+        text.push_str("record(ai, OTHER) { field(DTYP, \"Soft Channel\") }\n");
+        let parsed = parse(&text).unwrap().manifest(DTYP).unwrap();
+        assert_eq!(parsed.entries(), manifest.entries());
+    }
+
+    #[test]
+    fn unsupported_record() {
+        let db = parse("record(calc, X) { field(DTYP, Ferrite) }").unwrap();
+        let err = db.manifest(DTYP).unwrap_err();
+        assert_eq!(err.name, "X");
+        assert_eq!(err.type_, "calc");
+    }
+
+    #[test]
+    fn load_substitutions() {
+        let dir = std::env::temp_dir().join(format!("ferrite-db-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("ch.template"),
+            "record(ai, \"$(P)CH$(N)\") { field(DESC, \"$(DESC=none)\") }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("app.substitutions"),
+            "file ch.template {\n    pattern { N }\n    { 1 }\n    { 2 }\n}\n",
+        )
+        .unwrap();
+        let macros = [("P".to_string(), "DEV:".to_string())].into();
+        let db = super::load_substitutions(dir.join("app.substitutions"), &macros);
+        let templates = substitutions_templates(dir.join("app.substitutions"), &macros);
+        fs::remove_dir_all(&dir).unwrap();
+        let db = db.unwrap();
+        assert_eq!(db.records.len(), 2);
+        assert_eq!(db.records[1].name, "DEV:CH2");
+        assert_eq!(db.records[1].field("DESC"), Some("none"));
+        assert_eq!(templates.unwrap(), [dir.join("ch.template")]);
+    }
+}
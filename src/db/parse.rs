@@ -0,0 +1,433 @@
+use super::macros::reference_at;
+use derive_more::{Display, Error};
+use std::{iter::Peekable, str::CharIndices};
+
+/// Record declared in database file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Record {
+    pub type_: String,
+    pub name: String,
+    /// Fields in order of declaration.
+    pub fields: Vec<(String, String)>,
+    /// Info tags in order of declaration.
+    pub infos: Vec<(String, String)>,
+    pub aliases: Vec<String>,
+}
+
+impl Record {
+    /// Value of the field, the last one if declared multiple times.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+    /// Value of the info tag, the last one if declared multiple times.
+    pub fn info(&self, name: &str) -> Option<&str> {
+        self.infos
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Contents of database file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Database {
+    pub records: Vec<Record>,
+}
+
+impl Database {
+    pub fn record(&self, name: &str) -> Option<&Record> {
+        self.records.iter().find(|record| record.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "Line {}: {}", "line", "message")]
+pub struct ParseError {
+    pub line: usize,
+    #[error(not(source))]
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    Word(String),
+    Punct(char),
+    /// Quoted string, only produced if parser [keeps quotes](Parser::keep_quotes).
+    Quoted(String),
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    puncts: &'static str,
+    keep_quotes: bool,
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-+:.[]<>;/\\".contains(c)
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str, puncts: &'static str) -> Self {
+        Self {
+            text,
+            chars: text.char_indices().peekable(),
+            line: 1,
+            puncts,
+            keep_quotes: false,
+        }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, ParseError> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            match c {
+                '\n' => {
+                    self.line += 1;
+                    self.chars.next();
+                }
+                '#' => while self.chars.next_if(|&(_, c)| c != '\n').is_some() {},
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                c if self.puncts.contains(c) => {
+                    self.chars.next();
+                    return Ok(Some(Token::Punct(c)));
+                }
+                '"' => {
+                    self.chars.next();
+                    let text = self.quoted()?;
+                    return Ok(Some(match self.keep_quotes {
+                        true => Token::Quoted(text),
+                        false => Token::Word(text),
+                    }));
+                }
+                c if is_bare(c) || c == '$' => {
+                    let word = self.bare();
+                    if word.is_empty() {
+                        return Err(self.error(format!("Unexpected character '{}'", c)));
+                    }
+                    return Ok(Some(Token::Word(word)));
+                }
+                c => return Err(self.error(format!("Unexpected character '{}'", c))),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Bare word, macro references are kept as is and may contain any characters.
+    fn bare(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&(i, c)) = self.chars.peek() {
+            if c == '$' {
+                if let Some(len) = reference_at(&self.text[i..]) {
+                    word.push_str(&self.text[i..(i + len)]);
+                    while self.chars.next_if(|&(j, _)| j < i + len).is_some() {}
+                    continue;
+                }
+            }
+            if !is_bare(c) || self.puncts.contains(c) {
+                break;
+            }
+            word.push(c);
+            self.chars.next();
+        }
+        word
+    }
+
+    fn quoted(&mut self) -> Result<String, ParseError> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(text),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) => {
+                        if c != '"' && c != '\\' {
+                            text.push('\\');
+                        }
+                        text.push(c);
+                    }
+                    None => break,
+                },
+                Some((_, '\n')) => return Err(self.error("Newline in string".into())),
+                Some((_, c)) => text.push(c),
+                None => break,
+            }
+        }
+        Err(self.error("Unterminated string".into()))
+    }
+}
+
+pub(crate) struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(text: &'a str, puncts: &'static str) -> Self {
+        Self {
+            lexer: Lexer::new(text, puncts),
+            peeked: None,
+        }
+    }
+
+    /// Return quoted strings as [`Token::Quoted`] instead of words.
+    #[cfg_attr(not(feature = "stream"), allow(dead_code))]
+    pub fn keep_quotes(mut self) -> Self {
+        self.lexer.keep_quotes = true;
+        self
+    }
+
+    pub fn error(&self, message: String) -> ParseError {
+        self.lexer.error(message)
+    }
+    /// Current line in source text.
+    pub fn line(&self) -> usize {
+        self.lexer.line
+    }
+
+    pub fn next(&mut self) -> Result<Option<Token>, ParseError> {
+        match self.peeked.take() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.next_token(),
+        }
+    }
+    pub fn peek(&mut self) -> Result<Option<&Token>, ParseError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    pub fn expect_punct(&mut self, punct: char) -> Result<(), ParseError> {
+        match self.next()? {
+            Some(Token::Punct(c)) if c == punct => Ok(()),
+            other => Err(self.unexpected(other, &format!("'{}'", punct))),
+        }
+    }
+    pub fn unexpected(&self, token: Option<Token>, expected: &str) -> ParseError {
+        self.lexer.error(match token {
+            Some(Token::Word(word)) => format!("Unexpected '{}', {} expected", word, expected),
+            Some(Token::Quoted(text)) => format!("Unexpected \"{}\", {} expected", text, expected),
+            Some(Token::Punct(c)) => format!("Unexpected '{}', {} expected", c, expected),
+            None => format!("Unexpected end of file, {} expected", expected),
+        })
+    }
+
+    /// Parse comma-separated arguments in parentheses.
+    fn args(&mut self) -> Result<Vec<String>, ParseError> {
+        self.expect_punct('(')?;
+        let mut args = Vec::new();
+        loop {
+            match self.next()? {
+                Some(Token::Punct(')')) if args.is_empty() => break,
+                Some(Token::Word(word)) => args.push(word),
+                other => return Err(self.unexpected(other, "argument")),
+            }
+            match self.next()? {
+                Some(Token::Punct(')')) => break,
+                Some(Token::Punct(',')) => continue,
+                other => return Err(self.unexpected(other, "',' or ')'")),
+            }
+        }
+        Ok(args)
+    }
+
+    /// Skip optional block in braces.
+    fn skip_body(&mut self) -> Result<(), ParseError> {
+        if self.peek()? != Some(&Token::Punct('{')) {
+            return Ok(());
+        }
+        let mut depth = 0;
+        loop {
+            match self.next()? {
+                Some(Token::Punct('{')) => depth += 1,
+                Some(Token::Punct('}')) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some(_) => (),
+                None => return Err(self.unexpected(None, "'}'")),
+            }
+        }
+    }
+
+    fn record(&mut self) -> Result<Record, ParseError> {
+        let args = self.args()?;
+        let [type_, name]: [String; 2] = args
+            .try_into()
+            .map_err(|_| self.lexer.error("Record must have type and name".into()))?;
+        let mut record = Record {
+            type_,
+            name,
+            ..Default::default()
+        };
+        if self.peek()? != Some(&Token::Punct('{')) {
+            return Ok(record);
+        }
+        self.next()?;
+        loop {
+            match self.next()? {
+                Some(Token::Punct('}')) => break,
+                Some(Token::Word(word)) if word == "field" || word == "info" => {
+                    let args = self.args()?;
+                    let [key, value]: [String; 2] = args.try_into().map_err(|_| {
+                        self.lexer
+                            .error(format!("'{}' must have two arguments", word))
+                    })?;
+                    if word == "field" {
+                        record.fields.push((key, value));
+                    } else {
+                        record.infos.push((key, value));
+                    }
+                }
+                Some(Token::Word(word)) if word == "alias" => {
+                    let args = self.args()?;
+                    let [alias]: [String; 1] = args
+                        .try_into()
+                        .map_err(|_| self.lexer.error("Alias must have single argument".into()))?;
+                    record.aliases.push(alias);
+                }
+                Some(Token::Word(_)) => {
+                    self.args()?;
+                }
+                other => return Err(self.unexpected(other, "'field', 'info', 'alias' or '}'")),
+            }
+        }
+        Ok(record)
+    }
+
+    fn database(&mut self) -> Result<Database, ParseError> {
+        let mut db = Database::default();
+        while let Some(token) = self.next()? {
+            match token {
+                Token::Word(word) if word == "record" || word == "grecord" => {
+                    db.records.push(self.record()?);
+                }
+                // Statements without parentheses, e.g. `include "common.db"`.
+                Token::Word(word) if ["include", "path", "addpath"].contains(&word.as_str()) => {
+                    match self.next()? {
+                        Some(Token::Word(_)) => (),
+                        other => return Err(self.unexpected(other, "string")),
+                    }
+                }
+                Token::Word(_) => {
+                    self.args()?;
+                    self.skip_body()?;
+                }
+                other => return Err(self.unexpected(Some(other), "statement")),
+            }
+        }
+        Ok(db)
+    }
+}
+
+/// Parse contents of `.db` file.
+///
+/// Text must not contain macros, see [`parse_template`](`super::parse_template`).
+pub fn parse(text: &str) -> Result<Database, ParseError> {
+    Parser::new(text, "(){},").database()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str, puncts: &'static str) -> Vec<Token> {
+        let mut parser = Parser::new(text, puncts);
+        let mut tokens = Vec::new();
+        while let Some(token) = parser.next().unwrap() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.into())
+    }
+
+    #[test]
+    fn bare_word_stops_at_punct() {
+        assert_eq!(
+            tokens("a;b c", ";"),
+            [word("a"), Token::Punct(';'), word("b"), word("c")]
+        );
+        assert_eq!(tokens("a;b c", ","), [word("a;b"), word("c")]);
+    }
+
+    #[test]
+    fn keep_quotes() {
+        assert_eq!(tokens("a \"b\"", ""), [word("a"), word("b")]);
+        let mut parser = Parser::new("a \"b\"", "").keep_quotes();
+        assert_eq!(parser.next().unwrap(), Some(word("a")));
+        assert_eq!(parser.next().unwrap(), Some(Token::Quoted("b".into())));
+    }
+
+    #[test]
+    fn bare_field_values() {
+        let db = parse(
+            "record(ai, X:Y) {\n  field(INP, \"A:B.VAL CP\")\n  field(DESC, a-b+c.d[1]<2>;e)\n}",
+        )
+        .unwrap();
+        let record = db.record("X:Y").unwrap();
+        assert_eq!(record.type_, "ai");
+        assert_eq!(record.field("INP"), Some("A:B.VAL CP"));
+        assert_eq!(record.field("DESC"), Some("a-b+c.d[1]<2>;e"));
+    }
+
+    #[test]
+    fn error_line() {
+        let err = parse("record(ai, A) {\n  field(VAL 1)\n}").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn records() {
+        let db = parse(concat!(
+            "# comment\n",
+            "path \"../db\"\n",
+            "menu(menuScan) {\n  choice(menuScanPassive, \"Passive\")\n}\n",
+            "record(ao, \"A\") {\n",
+            "    field(DESC, \"say \\\"hi\\\" \\n\")  # trailing comment\n",
+            "    field(VAL, \"1\")\n",
+            "    field(VAL, \"2\")\n",
+            "    info(autosaveFields, \"VAL\")\n",
+            "    alias(\"B\")\n",
+            "}\n",
+            "grecord(bi, C)\n",
+        ))
+        .unwrap();
+        assert_eq!(db.records.len(), 2);
+        let a = db.record("A").unwrap();
+        assert_eq!(a.field("DESC"), Some("say \"hi\" \\n"));
+        assert_eq!(a.field("VAL"), Some("2"));
+        assert_eq!(a.fields.len(), 3);
+        assert_eq!(a.info("autosaveFields"), Some("VAL"));
+        assert_eq!(a.aliases, ["B"]);
+        assert_eq!(db.record("C").unwrap().type_, "bi");
+        assert!(db.record("B").is_none());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("record(ai)").unwrap_err().line, 1);
+        assert_eq!(parse("\nrecord(ai, \"A").unwrap_err().line, 2);
+        assert_eq!(parse("record(ai, A) {\n\n").unwrap_err().line, 3);
+        assert!(parse("record(ai, A) { field(VAL) }").is_err());
+        assert!(parse("record(ai, A) { 1 }").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use super::{
+    expand,
+    parse::{Parser, Token},
+    Macros, ParseError,
+};
+
+/// Template file instantiated with several sets of macros.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Substitution {
+    pub file: String,
+    pub sets: Vec<Macros>,
+}
+
+/// Contents of `.substitutions` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Substitutions {
+    pub files: Vec<Substitution>,
+}
+
+/// Parse contents of `.substitutions` file.
+///
+/// Both `pattern` and `NAME=value` forms are supported, `global` definitions apply to all following sets.
+///
+/// File names and macro values are expanded where they are used,
+/// so they may refer to `macros`, global definitions and other macros of the same set.
+pub fn parse_substitutions(text: &str, macros: &Macros) -> Result<Substitutions, ParseError> {
+    let mut parser = Parser::new(text, "{},=");
+    let mut subs = Substitutions::default();
+    let mut globals = Macros::new();
+    while let Some(token) = parser.next()? {
+        match token {
+            Token::Word(word) if word == "global" => {
+                globals.extend(assignments(&mut parser)?);
+            }
+            Token::Word(word) if word == "file" => {
+                let file = match parser.next()? {
+                    Some(Token::Word(file)) => file,
+                    other => return Err(parser.unexpected(other, "file name")),
+                };
+                let file = expand_at(&parser, &file, &scope(macros, &globals))?;
+                subs.files.push(Substitution {
+                    file,
+                    sets: file_body(&mut parser, macros, &mut globals)?,
+                });
+            }
+            other => return Err(parser.unexpected(Some(other), "'file' or 'global'")),
+        }
+    }
+    Ok(subs)
+}
+
+/// Macros visible in a set.
+fn scope(macros: &Macros, defs: &Macros) -> Macros {
+    let mut scope = macros.clone();
+    scope.extend(defs.clone());
+    scope
+}
+
+/// Expand `text`, errors are reported at the current line of `parser`.
+fn expand_at(parser: &Parser, text: &str, macros: &Macros) -> Result<String, ParseError> {
+    expand(text, macros).map_err(|error| parser.error(error.message))
+}
+
+fn file_body(
+    parser: &mut Parser,
+    macros: &Macros,
+    globals: &mut Macros,
+) -> Result<Vec<Macros>, ParseError> {
+    let mut sets = Vec::new();
+    let mut pattern: Option<Vec<String>> = None;
+    parser.expect_punct('{')?;
+    loop {
+        match parser.peek()?.cloned() {
+            Some(Token::Punct('}')) => {
+                parser.next()?;
+                break;
+            }
+            Some(Token::Word(word)) if word == "global" => {
+                parser.next()?;
+                globals.extend(assignments(parser)?);
+            }
+            Some(Token::Word(word)) if word == "pattern" => {
+                parser.next()?;
+                pattern = Some(values(parser)?);
+            }
+            Some(Token::Punct('{')) => {
+                let line = parser.line();
+                let mut set = globals.clone();
+                match &pattern {
+                    Some(names) => {
+                        let values = values(parser)?;
+                        if values.len() != names.len() {
+                            return Err(parser.error(format!(
+                                "{} values given for {} pattern names",
+                                values.len(),
+                                names.len()
+                            )));
+                        }
+                        set.extend(names.iter().cloned().zip(values));
+                    }
+                    None => set.extend(assignments(parser)?),
+                }
+                let scope = scope(macros, &set);
+                let expanded = set
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), expand(value, &scope)?)))
+                    .collect::<Result<Macros, ParseError>>()
+                    .map_err(|error| ParseError {
+                        line,
+                        message: error.message,
+                    })?;
+                sets.push(expanded);
+            }
+            other => return Err(parser.unexpected(other, "'pattern', '{' or '}'")),
+        }
+    }
+    Ok(sets)
+}
+
+/// Parse `{ a, b, c }`, commas are optional.
+fn values(parser: &mut Parser) -> Result<Vec<String>, ParseError> {
+    parser.expect_punct('{')?;
+    let mut values = Vec::new();
+    loop {
+        match parser.next()? {
+            Some(Token::Punct('}')) => break,
+            Some(Token::Punct(',')) => (),
+            Some(Token::Word(word)) => values.push(word),
+            other => return Err(parser.unexpected(other, "value or '}'")),
+        }
+    }
+    Ok(values)
+}
+
+/// Parse `{ A=a, B=b }`, commas are optional.
+fn assignments(parser: &mut Parser) -> Result<Macros, ParseError> {
+    parser.expect_punct('{')?;
+    let mut macros = Macros::new();
+    loop {
+        match parser.next()? {
+            Some(Token::Punct('}')) => break,
+            Some(Token::Punct(',')) => (),
+            Some(Token::Word(name)) => {
+                parser.expect_punct('=')?;
+                match parser.next()? {
+                    Some(Token::Word(value)) => {
+                        macros.insert(name, value);
+                    }
+                    other => return Err(parser.unexpected(other, "macro value")),
+                }
+            }
+            other => return Err(parser.unexpected(other, "macro name or '}'")),
+        }
+    }
+    Ok(macros)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn macros(defs: &[(&str, &str)]) -> Macros {
+        defs.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn pattern_and_assignments() {
+        let subs = parse_substitutions(
+            concat!(
+                "file \"a.template\" {\n",
+                "    pattern { N, DESC }\n",
+                "    { 1, \"first, one\" }\n",
+                "    { 2 second }\n",
+                "}\n",
+                "file b.template { { X=1, Y = \"y\" } }\n",
+            ),
+            &Macros::new(),
+        )
+        .unwrap();
+        assert_eq!(subs.files.len(), 2);
+        assert_eq!(subs.files[0].file, "a.template");
+        assert_eq!(
+            subs.files[0].sets,
+            [
+                macros(&[("N", "1"), ("DESC", "first, one")]),
+                macros(&[("N", "2"), ("DESC", "second")])
+            ]
+        );
+        assert_eq!(subs.files[1].sets, [macros(&[("X", "1"), ("Y", "y")])]);
+    }
+
+    #[test]
+    fn bare_paths() {
+        let subs = parse_substitutions(
+            "file db/x.template { { A = a/b, B = c\\d } }\n",
+            &Macros::new(),
+        )
+        .unwrap();
+        assert_eq!(subs.files[0].file, "db/x.template");
+        assert_eq!(subs.files[0].sets, [macros(&[("A", "a/b"), ("B", "c\\d")])]);
+    }
+
+    #[test]
+    fn expand_in_scope() {
+        let subs = parse_substitutions(
+            concat!(
+                "global { P = $(TOP)DEV: }\n",
+                "file \"$(DIR)/ch.template\" {\n",
+                "    { R = $(P)CH$(N), N = 1 }\n",
+                "    global { P = OTHER: }\n",
+                "    { R = \"$(P)CH$(N=0)\" }\n",
+                "}\n",
+            ),
+            &macros(&[("TOP", "X:"), ("DIR", "db")]),
+        )
+        .unwrap();
+        let file = &subs.files[0];
+        assert_eq!(file.file, "db/ch.template");
+        assert_eq!(file.sets[0]["R"], "X:DEV:CH1");
+        assert_eq!(file.sets[0]["P"], "X:DEV:");
+        assert_eq!(file.sets[1]["R"], "OTHER:CH0");
+    }
+
+    #[test]
+    fn error_lines() {
+        let err = parse_substitutions(
+            "file a {\n    { A = 1 }\n    { A = $(B) }\n}\n",
+            &Macros::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "Undefined macro 'B'");
+
+        let err = parse_substitutions(
+            "file a {\n    pattern { A, B }\n\n    { 1 }\n}\n",
+            &Macros::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 4);
+    }
+}
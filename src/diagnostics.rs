@@ -0,0 +1,73 @@
+use derive_more::{Deref, Display, Error};
+use std::fmt;
+
+/// Problem with variable found during IOC initialization.
+///
+/// Such variables are not added to registry.
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum Diagnostic {
+    #[display(fmt = "Duplicate PV name '{}'", "_0")]
+    DuplicateName(String),
+    #[display(fmt = "PV name is not valid UTF-8: '{}'", "_0")]
+    InvalidName(String),
+    #[display(fmt = "PV '{}' has unsupported type {}", "name", "type_")]
+    UnsupportedType { name: String, type_: u32 },
+    #[display(fmt = "PV '{}' has unsupported direction {}", "name", "dir")]
+    UnsupportedDirection { name: String, dir: u32 },
+}
+
+/// All problems found during IOC initialization.
+#[derive(Clone, Debug, Default, Deref)]
+pub struct Diagnostics(pub(crate) Vec<Diagnostic>);
+
+#[derive(Clone, Debug, Error)]
+pub struct DiagnosticsError(#[error(not(source))] pub Diagnostics);
+
+impl Diagnostics {
+    pub fn check(&self) -> Result<(), DiagnosticsError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(DiagnosticsError(self.clone()))
+        }
+    }
+
+    /// Log report and exit application if there are any problems.
+    pub fn exit_on_error(&self) {
+        if let Err(err) = self.check() {
+            log::error!("{}", err);
+            crate::exit(1);
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "There are {} problem(s) with PVs:", self.0.len())?;
+        for diag in self.0.iter() {
+            writeln!(f, "  - {}", diag)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        assert!(Diagnostics::default().check().is_ok());
+        let diags = Diagnostics(vec![
+            Diagnostic::DuplicateName("A".into()),
+            Diagnostic::UnsupportedType {
+                name: "B".into(),
+                type_: 42,
+            },
+        ]);
+        assert_eq!(
+            diags.check().unwrap_err().to_string(),
+            "There are 2 problem(s) with PVs:\n  - Duplicate PV name 'A'\n  - PV 'B' has unsupported type 42\n"
+        );
+    }
+}
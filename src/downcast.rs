@@ -1,11 +1,19 @@
-use crate::{typed::Value, TypedVariable, Variable};
+use crate::{
+    dynamic::{CoercedVariable, DynValue, Scalar},
+    typed::{Input, Output, Value},
+    variable::Direction,
+    TypedVariable, Variable,
+};
 use std::any::TypeId;
 
 pub trait Downcast<V> {
     fn downcast(self) -> Option<V>;
 }
 
-impl<T: Value> Downcast<TypedVariable<T>> for Variable {
+/// Checks type and shape only, variable of any direction is accepted.
+///
+/// Use [`Input`] or [`Output`] to also check direction of the record.
+impl<T: Scalar> Downcast<TypedVariable<T>> for Variable {
     fn downcast(self) -> Option<TypedVariable<T>> {
         if self.info().type_.type_id() == TypeId::of::<T>() && self.info().max_len == 0 {
             Some(unsafe { TypedVariable::new_unchecked(self) })
@@ -14,6 +22,13 @@ impl<T: Value> Downcast<TypedVariable<T>> for Variable {
         }
     }
 }
+/// Dynamic variable can hold value of any type and shape, so any variable is accepted.
+impl Downcast<TypedVariable<DynValue>> for Variable {
+    fn downcast(self) -> Option<TypedVariable<DynValue>> {
+        Some(unsafe { TypedVariable::new_unchecked(self) })
+    }
+}
+/// Checks element type only, variable of any direction is accepted.
 impl<T: Value> Downcast<TypedVariable<[T]>> for Variable {
     fn downcast(self) -> Option<TypedVariable<[T]>> {
         if self.info().type_.type_id() == TypeId::of::<T>() {
@@ -23,3 +38,68 @@ impl<T: Value> Downcast<TypedVariable<[T]>> for Variable {
         }
     }
 }
+impl<T: Scalar> Downcast<CoercedVariable<T>> for Variable {
+    fn downcast(self) -> Option<CoercedVariable<T>> {
+        if self.info().max_len == 0 {
+            Some(CoercedVariable::new(self.downcast()?))
+        } else {
+            None
+        }
+    }
+}
+/// Accepts output records only.
+impl<V: Value + ?Sized> Downcast<Input<V>> for Variable
+where
+    Variable: Downcast<TypedVariable<V>>,
+{
+    fn downcast(self) -> Option<Input<V>> {
+        if self.direction() == Direction::Output {
+            Some(Input::new(self.downcast()?))
+        } else {
+            None
+        }
+    }
+}
+/// Accepts input records only.
+impl<V: Value + ?Sized> Downcast<Output<V>> for Variable
+where
+    Variable: Downcast<TypedVariable<V>>,
+{
+    fn downcast(self) -> Option<Output<V>> {
+        if self.direction() == Direction::Input {
+            Some(Output::new(self.downcast()?))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeVar, variable::Type};
+
+    #[test]
+    fn direction() {
+        let ai = FakeVar::new("DOWNCAST:AI", Type::F64, 0, Direction::Input).leak();
+        let ao = FakeVar::new("DOWNCAST:AO", Type::F64, 0, Direction::Output).leak();
+        assert!(Downcast::<Output<f64>>::downcast(ai.var()).is_some());
+        assert!(Downcast::<Input<f64>>::downcast(ai.var()).is_none());
+        assert!(Downcast::<Input<f64>>::downcast(ao.var()).is_some());
+        assert!(Downcast::<Output<f64>>::downcast(ao.var()).is_none());
+        assert!(Downcast::<Output<i32>>::downcast(ai.var()).is_none());
+        // Direction is not checked.
+        assert!(Downcast::<TypedVariable<f64>>::downcast(ai.var()).is_some());
+        assert!(Downcast::<TypedVariable<f64>>::downcast(ao.var()).is_some());
+    }
+
+    #[test]
+    fn shape() {
+        let aai = FakeVar::new("DOWNCAST:AAI", Type::I16, 8, Direction::Input).leak();
+        assert!(Downcast::<TypedVariable<[i16]>>::downcast(aai.var()).is_some());
+        assert!(Downcast::<TypedVariable<i16>>::downcast(aai.var()).is_none());
+        assert!(Downcast::<Output<[i16]>>::downcast(aai.var()).is_some());
+        assert!(Downcast::<Output<DynValue>>::downcast(aai.var()).is_some());
+        assert!(Downcast::<CoercedVariable<f64>>::downcast(aai.var()).is_none());
+    }
+}
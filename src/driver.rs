@@ -0,0 +1,164 @@
+//! Scaffolding shared by device drivers.
+//!
+//! Input records are polled from a single thread without waiting for them:
+//! values are read after the record begins processing, so one that is not processed doesn't delay other inputs.
+//! Output records are written from a separate thread each when processed.
+
+use crate::{
+    dynamic::{Conversion, DynValue, Overflow, Rounding},
+    executor::block_on_timeout,
+    typed::ValueGuard,
+    Info, Input, Output,
+};
+use futures::executor::block_on;
+use std::{
+    fmt::Display,
+    net::{SocketAddr, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Conversion of values received from device to variable type.
+pub const CONVERSION: Conversion = Conversion {
+    overflow: Overflow::Saturate,
+    rounding: Rounding::Nearest,
+};
+
+/// Input record that is not processed within this time after request is reported.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Resolve device address, `default_port` is used if `addr` has no port.
+pub fn resolve(addr: &str, default_port: Option<u16>) -> Option<SocketAddr> {
+    let addrs = match default_port {
+        Some(port) => addr
+            .to_socket_addrs()
+            .or_else(|_| (addr, port).to_socket_addrs()),
+        None => addr.to_socket_addrs(),
+    };
+    addrs.ok()?.next()
+}
+
+pub fn spawn<F: FnOnce() + Send + 'static>(name: &str, f: F) -> thread::JoinHandle<()> {
+    thread::Builder::new().name(name.into()).spawn(f).unwrap()
+}
+
+/// Message to reject processing with on communication failure.
+#[cfg_attr(not(any(feature = "modbus", feature = "scpi")), allow(dead_code))]
+pub fn comm_message<E: Display>(err: &E, timeout: bool) -> String {
+    match timeout {
+        true => String::from("COMM: Timeout"),
+        false => format!("COMM: {}", err),
+    }
+}
+
+/// Result of device operation, value (if any) is stored to the variable.
+pub type Reply = Result<Option<DynValue>, String>;
+
+async fn complete(guard: ValueGuard<'_, DynValue>, reply: Reply) {
+    match reply {
+        Ok(Some(value)) => {
+            if let Ok(commit) = guard.write(&value, CONVERSION) {
+                commit.await;
+            }
+        }
+        Ok(None) => guard.accept().await,
+        Err(message) => guard.reject(&message).await,
+    }
+}
+
+/// Input variable read from device with `period`.
+pub struct Polled<T> {
+    pub source: T,
+    pub period: Duration,
+    pub var: Output<DynValue>,
+}
+
+/// Polling state of input.
+#[derive(Clone, Copy)]
+enum PollState {
+    /// Processing will be requested at specified time.
+    Due(Instant),
+    /// Processing is requested and the record hasn't begun it yet.
+    Requested {
+        due: Instant,
+        /// Whether the record is already reported as not processed.
+        reported: bool,
+    },
+}
+
+/// Poll `inputs` in current thread forever.
+///
+/// `read` is called with source, variable info and current value when the record begins processing.
+pub fn poll<T, F>(mut inputs: Vec<Polled<T>>, mut read: F)
+where
+    F: FnMut(&T, Info, DynValue) -> Reply,
+{
+    let mut states = vec![PollState::Due(Instant::now()); inputs.len()];
+    loop {
+        for (input, state) in inputs.iter_mut().zip(&mut states) {
+            let now = Instant::now();
+            let due = match *state {
+                PollState::Due(due) if due > now => continue,
+                PollState::Due(due) | PollState::Requested { due, .. } => due,
+            };
+            let info = input.var.info();
+            // Request is polled without waiting, waker unparks this thread when processing begins.
+            if let Some(guard) = block_on_timeout(input.var.request(), Duration::ZERO) {
+                let reply = read(&input.source, info, guard.value());
+                block_on(complete(guard, reply));
+                *state = PollState::Due((due + input.period).max(Instant::now()));
+                continue;
+            }
+            let reported = matches!(*state, PollState::Requested { reported: true, .. });
+            let late = now >= due + REQUEST_TIMEOUT;
+            if late && !reported {
+                log::warn!("PV '{}': Record is not processed", input.var.name());
+            }
+            *state = PollState::Requested {
+                due,
+                reported: reported || late,
+            };
+        }
+
+        let wake = states
+            .iter()
+            .filter_map(|state| match *state {
+                PollState::Due(due) => Some(due),
+                PollState::Requested {
+                    due,
+                    reported: false,
+                } => Some(due + REQUEST_TIMEOUT),
+                PollState::Requested { reported: true, .. } => None,
+            })
+            .min();
+        match wake {
+            Some(wake) => thread::park_timeout(wake.saturating_duration_since(Instant::now())),
+            None => thread::park(),
+        }
+    }
+}
+
+/// Call `write` with the value of `var` each time it is processed, forever.
+pub fn write<F>(mut var: Input<DynValue>, mut write: F)
+where
+    F: FnMut(Info, DynValue) -> Reply,
+{
+    let info = var.info();
+    loop {
+        block_on(async {
+            let guard = var.wait().await;
+            let reply = write(info, guard.value());
+            complete(guard, reply).await;
+        });
+    }
+}
+
+/// Wait until `f` returns `true`, panics after 10 seconds.
+#[cfg(test)]
+pub fn wait_for<F: FnMut() -> bool>(mut f: F) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !f() {
+        assert!(Instant::now() < deadline, "Timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,104 @@
+use super::{Conversion, ConvertError, DynValue, DynVariable, Scalar};
+use crate::typed::{Commit, ValueGuard};
+use derive_more::{Deref, DerefMut};
+use std::marker::PhantomData;
+
+/// Scalar variable of any numeric type that is accessed as `T`.
+///
+/// Value is converted on read and write according to [`Conversion`] rules.
+#[derive(Deref, DerefMut)]
+pub struct CoercedVariable<T: Scalar> {
+    #[deref]
+    #[deref_mut]
+    base: DynVariable,
+    conv: Conversion,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Scalar> CoercedVariable<T> {
+    pub(crate) fn new(base: DynVariable) -> Self {
+        Self {
+            base,
+            conv: Conversion::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn conversion(&self) -> Conversion {
+        self.conv
+    }
+    pub fn with_conversion(self, conv: Conversion) -> Self {
+        Self { conv, ..self }
+    }
+
+    /// Passively wait for variable being processed.
+    pub async fn wait(&mut self) -> CoercedGuard<'_, T> {
+        let conv = self.conv;
+        CoercedGuard::new(self.base.wait().await, conv)
+    }
+    /// Actively request variable processing.
+    pub async fn request(&mut self) -> CoercedGuard<'_, T> {
+        let conv = self.conv;
+        CoercedGuard::new(self.base.request().await, conv)
+    }
+}
+
+#[must_use]
+pub struct CoercedGuard<'a, T: Scalar> {
+    base: ValueGuard<'a, DynValue>,
+    conv: Conversion,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T: Scalar> CoercedGuard<'a, T> {
+    fn new(base: ValueGuard<'a, DynValue>, conv: Conversion) -> Self {
+        Self {
+            base,
+            conv,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Current value converted to `T`.
+    pub fn get(&self) -> Result<T, ConvertError> {
+        self.base.value().get_scalar(self.conv)
+    }
+    /// Convert and store value without committing.
+    pub fn set(&mut self, value: T) -> Result<(), ConvertError> {
+        self.base.set(&value.into_dyn(), self.conv)
+    }
+
+    /// Convert and write value.
+    ///
+    /// If conversion failed then processing is rejected with the error message.
+    pub fn write(self, value: T) -> Result<Commit<'a, DynValue>, ConvertError> {
+        self.base.write(&value.into_dyn(), self.conv)
+    }
+
+    /// Successfully complete processing.
+    pub fn accept(self) -> Commit<'a, DynValue> {
+        self.base.accept()
+    }
+    /// Report that error occurred during value processing.
+    pub fn reject(self, message: &str) -> Commit<'a, DynValue> {
+        self.base.reject(message)
+    }
+}
+
+impl<T: Scalar> CoercedGuard<'_, T> {
+    /// Read value converted to `T`.
+    ///
+    /// If conversion failed then processing is rejected with the error message.
+    pub async fn read(self) -> Result<T, ConvertError> {
+        match self.get() {
+            Ok(value) => {
+                self.accept().await;
+                Ok(value)
+            }
+            Err(err) => {
+                self.reject(&err.to_string()).await;
+                Err(err)
+            }
+        }
+    }
+}
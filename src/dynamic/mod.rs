@@ -0,0 +1,133 @@
+mod coerced;
+mod value;
+
+pub use coerced::{CoercedGuard, CoercedVariable};
+#[cfg(feature = "snapshot")]
+pub(crate) use value::Num;
+pub use value::{Conversion, ConvertError, DynArray, DynValue, Overflow, Rounding, Scalar};
+
+use crate::{
+    typed::{Commit, Value, ValueGuard},
+    TypedVariable, Variable,
+};
+pub(crate) use value::dispatch_type;
+
+/// Variable which value type is known only at runtime.
+///
+/// Value is converted according to [`Variable::info`](`crate::Variable::info`).
+pub type DynVariable = TypedVariable<DynValue>;
+
+impl Variable {
+    /// Load value of any variable.
+    ///
+    /// *Variable must be locked or being processed.*
+    #[cfg_attr(not(any(feature = "events", feature = "metrics")), allow(dead_code))]
+    pub(crate) unsafe fn load_dyn(&self) -> DynValue {
+        (*(self as *const Variable as *const DynVariable)).load_value()
+    }
+}
+
+impl TypedVariable<DynValue> {
+    fn is_array(&self) -> bool {
+        self.info().max_len != 0
+    }
+
+    unsafe fn cast<V: Value + ?Sized>(&self) -> &TypedVariable<V> {
+        &*(self as *const Self as *const TypedVariable<V>)
+    }
+    unsafe fn cast_mut<V: Value + ?Sized>(&mut self) -> &mut TypedVariable<V> {
+        &mut *(self as *mut Self as *mut TypedVariable<V>)
+    }
+
+    pub(crate) unsafe fn load_value(&self) -> DynValue {
+        unsafe fn load_scalar<T: Scalar>(this: &DynVariable) -> DynValue {
+            this.cast::<T>().value_ref().into_dyn()
+        }
+        unsafe fn load_array<T: Scalar>(this: &DynVariable) -> DynValue {
+            DynValue::Array(T::into_dyn_array(
+                this.cast::<[T]>().value_ref().as_slice().to_vec(),
+            ))
+        }
+        let type_ = self.info().type_;
+        if self.is_array() {
+            dispatch_type!(type_, load_array, self)
+        } else {
+            dispatch_type!(type_, load_scalar, self)
+        }
+    }
+
+    unsafe fn store_value(
+        &mut self,
+        value: &DynValue,
+        conv: Conversion,
+    ) -> Result<(), ConvertError> {
+        unsafe fn store_scalar<T: Scalar>(
+            this: &mut DynVariable,
+            value: &DynValue,
+            conv: Conversion,
+        ) -> Result<(), ConvertError> {
+            *this.cast_mut::<T>().value_mut() = value.get_scalar(conv)?;
+            Ok(())
+        }
+        unsafe fn store_array<T: Scalar>(
+            this: &mut DynVariable,
+            value: &DynValue,
+            conv: Conversion,
+        ) -> Result<(), ConvertError> {
+            let src = value.get_array::<T>(conv)?;
+            let dst = this.cast_mut::<[T]>().value_mut();
+            if src.len() > dst.capacity() && conv.overflow == Overflow::Error {
+                return Err(ConvertError::TooLong {
+                    len: src.len(),
+                    max_len: dst.capacity(),
+                });
+            }
+            let len = dst.capacity().min(src.len());
+            dst.clear();
+            dst.push_slice(&src[..len]).unwrap();
+            Ok(())
+        }
+        let type_ = self.info().type_;
+        if self.is_array() {
+            dispatch_type!(type_, store_array, self, value, conv)
+        } else {
+            dispatch_type!(type_, store_scalar, self, value, conv)
+        }
+    }
+}
+
+impl<'a> ValueGuard<'a, DynValue> {
+    /// Current value of the variable.
+    pub fn value(&self) -> DynValue {
+        unsafe { self.owner().load_value() }
+    }
+    /// Convert `value` to the variable type and store it without committing.
+    pub fn set(&mut self, value: &DynValue, conv: Conversion) -> Result<(), ConvertError> {
+        unsafe { self.owner_mut().store_value(value, conv) }
+    }
+
+    /// Convert and write value.
+    ///
+    /// If conversion failed then processing is rejected with the error message.
+    pub fn write(
+        mut self,
+        value: &DynValue,
+        conv: Conversion,
+    ) -> Result<Commit<'a, DynValue>, ConvertError> {
+        match self.set(value, conv) {
+            Ok(()) => Ok(self.accept()),
+            Err(err) => {
+                drop(self.reject(&err.to_string()));
+                Err(err)
+            }
+        }
+    }
+}
+
+impl ValueGuard<'_, DynValue> {
+    pub async fn read(self) -> DynValue {
+        let value = self.value();
+        self.accept().await;
+        value
+    }
+}
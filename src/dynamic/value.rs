@@ -0,0 +1,537 @@
+use crate::{typed, variable::Type};
+use derive_more::{Display, Error};
+
+/// Array of values of any supported type.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DynArray {
+    U8(Vec<u8>),
+    I8(Vec<i8>),
+    U16(Vec<u16>),
+    I16(Vec<i16>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+    U64(Vec<u64>),
+    I64(Vec<i64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+/// Type-erased variable value.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DynValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Array(DynArray),
+}
+
+#[derive(Clone, Debug, Display, Error)]
+pub enum ConvertError {
+    #[display(fmt = "Value is out of {:?} range", "type_")]
+    OutOfRange { type_: Type },
+    #[display(fmt = "Value cannot be exactly represented as {:?}", "type_")]
+    Inexact { type_: Type },
+    #[display(fmt = "Cannot convert array to scalar or vice versa")]
+    Shape,
+    #[display(fmt = "Array length {} exceeds maximum {}", "len", "max_len")]
+    TooLong { len: usize, max_len: usize },
+}
+
+/// What to do when value is out of range of target type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail with [`ConvertError::OutOfRange`].
+    #[default]
+    Error,
+    /// Clamp value to the nearest representable one. NaN becomes zero.
+    ///
+    /// Too long arrays are truncated.
+    Saturate,
+}
+
+/// What to do when value cannot be exactly represented in target type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Fail with [`ConvertError::Inexact`].
+    #[default]
+    Exact,
+    /// Round to the nearest value, ties away from zero for float to integer conversion.
+    Nearest,
+    /// Round toward zero for float to integer conversion, to nearest otherwise.
+    TowardZero,
+}
+
+/// Conversion rules.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Conversion {
+    pub overflow: Overflow,
+    pub rounding: Rounding,
+}
+
+impl Conversion {
+    /// Value must be exactly representable in target type, arrays must fit.
+    pub const LOSSLESS: Self = Self {
+        overflow: Overflow::Error,
+        rounding: Rounding::Exact,
+    };
+    /// Similar to `as` cast: out-of-range values are saturated, floats are truncated toward zero.
+    pub const LOSSY: Self = Self {
+        overflow: Overflow::Saturate,
+        rounding: Rounding::TowardZero,
+    };
+}
+
+mod sealed {
+    use super::*;
+
+    /// Intermediate representation that every scalar type converts into without loss.
+    #[derive(Clone, Copy, Debug)]
+    pub enum Num {
+        Int(i128),
+        Float(f64),
+    }
+
+    /// Conversion plumbing of [`Scalar`], not a part of public API.
+    pub trait Convert: typed::Type {
+        const TYPE: Type;
+
+        fn into_num(self) -> Num;
+        fn from_num(num: Num, conv: Conversion) -> Result<Self, ConvertError>;
+
+        fn into_dyn(self) -> DynValue;
+        fn into_dyn_array(vec: Vec<Self>) -> DynArray;
+    }
+}
+pub(crate) use sealed::{Convert, Num};
+
+/// Primitive type of variable value.
+///
+/// *This trait is sealed and cannot be implemented outside of the crate.*
+pub trait Scalar: Convert {}
+impl<T: Convert> Scalar for T {}
+
+macro_rules! impl_scalar_int {
+    ($($variant:ident: $t:ty),* $(,)?) => {$(
+        impl Convert for $t {
+            const TYPE: Type = Type::$variant;
+
+            fn into_num(self) -> Num {
+                Num::Int(self as i128)
+            }
+            fn from_num(num: Num, conv: Conversion) -> Result<Self, ConvertError> {
+                let int = match num {
+                    Num::Int(x) => x,
+                    Num::Float(x) => {
+                        if !x.is_finite() {
+                            return match conv.overflow {
+                                Overflow::Error => Err(ConvertError::OutOfRange { type_: Self::TYPE }),
+                                Overflow::Saturate if x.is_nan() => Ok(0),
+                                Overflow::Saturate => Ok(if x < 0.0 { <$t>::MIN } else { <$t>::MAX }),
+                            };
+                        }
+                        let y = match conv.rounding {
+                            Rounding::Exact if x.fract() != 0.0 => {
+                                return Err(ConvertError::Inexact { type_: Self::TYPE })
+                            }
+                            Rounding::Exact | Rounding::TowardZero => x.trunc(),
+                            Rounding::Nearest => x.round(),
+                        };
+                        // Saturates for too large values, so range check below will fail.
+                        y as i128
+                    }
+                };
+                match <$t>::try_from(int) {
+                    Ok(x) => Ok(x),
+                    Err(_) => match conv.overflow {
+                        Overflow::Saturate => Ok(if int < 0 { <$t>::MIN } else { <$t>::MAX }),
+                        Overflow::Error => Err(ConvertError::OutOfRange { type_: Self::TYPE }),
+                    },
+                }
+            }
+
+            fn into_dyn(self) -> DynValue {
+                DynValue::$variant(self)
+            }
+            fn into_dyn_array(vec: Vec<Self>) -> DynArray {
+                DynArray::$variant(vec)
+            }
+        }
+    )*};
+}
+
+impl_scalar_int!(U8: u8, I8: i8, U16: u16, I16: i16, U32: u32, I32: i32, U64: u64, I64: i64);
+
+macro_rules! impl_scalar_float {
+    ($($variant:ident: $t:ty),* $(,)?) => {$(
+        impl Convert for $t {
+            const TYPE: Type = Type::$variant;
+
+            fn into_num(self) -> Num {
+                Num::Float(self as f64)
+            }
+            fn from_num(num: Num, conv: Conversion) -> Result<Self, ConvertError> {
+                let (value, exact) = match num {
+                    Num::Int(x) => {
+                        let y = x as $t;
+                        (y, y as i128 == x)
+                    }
+                    Num::Float(x) => {
+                        let y = x as $t;
+                        if x.is_finite() && y.is_infinite() {
+                            return match conv.overflow {
+                                Overflow::Error => Err(ConvertError::OutOfRange { type_: Self::TYPE }),
+                                Overflow::Saturate => Ok(if x < 0.0 { <$t>::MIN } else { <$t>::MAX }),
+                            };
+                        }
+                        (y, y as f64 == x || x.is_nan())
+                    }
+                };
+                if exact || conv.rounding != Rounding::Exact {
+                    Ok(value)
+                } else {
+                    Err(ConvertError::Inexact { type_: Self::TYPE })
+                }
+            }
+
+            fn into_dyn(self) -> DynValue {
+                DynValue::$variant(self)
+            }
+            fn into_dyn_array(vec: Vec<Self>) -> DynArray {
+                DynArray::$variant(vec)
+            }
+        }
+    )*};
+}
+
+impl_scalar_float!(F32: f32, F64: f64);
+
+/// Calls `$f::<T>($x)` where `T` is a primitive type that corresponds to `$type_`.
+macro_rules! dispatch_type {
+    ($type_:expr, $f:ident $(, $x:expr)* $(,)?) => {
+        match $type_ {
+            $crate::variable::Type::U8 => $f::<u8>($($x),*),
+            $crate::variable::Type::I8 => $f::<i8>($($x),*),
+            $crate::variable::Type::U16 => $f::<u16>($($x),*),
+            $crate::variable::Type::I16 => $f::<i16>($($x),*),
+            $crate::variable::Type::U32 => $f::<u32>($($x),*),
+            $crate::variable::Type::I32 => $f::<i32>($($x),*),
+            $crate::variable::Type::U64 => $f::<u64>($($x),*),
+            $crate::variable::Type::I64 => $f::<i64>($($x),*),
+            $crate::variable::Type::F32 => $f::<f32>($($x),*),
+            $crate::variable::Type::F64 => $f::<f64>($($x),*),
+        }
+    };
+}
+pub(crate) use dispatch_type;
+
+/// Applies `$f` to the inner value of every variant of `$enum`.
+macro_rules! map_variants {
+    ($enum:ident, $value:expr, $x:ident => $f:expr) => {
+        match $value {
+            $enum::U8($x) => $f,
+            $enum::I8($x) => $f,
+            $enum::U16($x) => $f,
+            $enum::I16($x) => $f,
+            $enum::U32($x) => $f,
+            $enum::I32($x) => $f,
+            $enum::U64($x) => $f,
+            $enum::I64($x) => $f,
+            $enum::F32($x) => $f,
+            $enum::F64($x) => $f,
+        }
+    };
+}
+
+impl DynArray {
+    pub fn type_(&self) -> Type {
+        fn type_of<T: Scalar>(_: &[T]) -> Type {
+            T::TYPE
+        }
+        map_variants!(DynArray, self, x => type_of(x))
+    }
+
+    pub fn len(&self) -> usize {
+        map_variants!(DynArray, self, x => x.len())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn get<T: Scalar>(&self, conv: Conversion) -> Result<Vec<T>, ConvertError> {
+        fn get<S: Scalar, T: Scalar>(src: &[S], conv: Conversion) -> Result<Vec<T>, ConvertError> {
+            src.iter()
+                .map(|x| T::from_num(x.into_num(), conv))
+                .collect()
+        }
+        map_variants!(DynArray, self, x => get(x, conv))
+    }
+
+    /// Convert elements of array to specified type.
+    pub fn convert(&self, type_: Type, conv: Conversion) -> Result<Self, ConvertError> {
+        fn convert<T: Scalar>(x: &DynArray, conv: Conversion) -> Result<DynArray, ConvertError> {
+            x.get::<T>(conv).map(T::into_dyn_array)
+        }
+        dispatch_type!(type_, convert, self, conv)
+    }
+}
+
+impl DynValue {
+    pub fn type_(&self) -> Type {
+        match self {
+            DynValue::U8(_) => Type::U8,
+            DynValue::I8(_) => Type::I8,
+            DynValue::U16(_) => Type::U16,
+            DynValue::I16(_) => Type::I16,
+            DynValue::U32(_) => Type::U32,
+            DynValue::I32(_) => Type::I32,
+            DynValue::U64(_) => Type::U64,
+            DynValue::I64(_) => Type::I64,
+            DynValue::F32(_) => Type::F32,
+            DynValue::F64(_) => Type::F64,
+            DynValue::Array(x) => x.type_(),
+        }
+    }
+    pub fn is_array(&self) -> bool {
+        matches!(self, DynValue::Array(_))
+    }
+
+    fn to_num(&self) -> Option<Num> {
+        Some(match *self {
+            DynValue::U8(x) => x.into_num(),
+            DynValue::I8(x) => x.into_num(),
+            DynValue::U16(x) => x.into_num(),
+            DynValue::I16(x) => x.into_num(),
+            DynValue::U32(x) => x.into_num(),
+            DynValue::I32(x) => x.into_num(),
+            DynValue::U64(x) => x.into_num(),
+            DynValue::I64(x) => x.into_num(),
+            DynValue::F32(x) => x.into_num(),
+            DynValue::F64(x) => x.into_num(),
+            DynValue::Array(_) => return None,
+        })
+    }
+
+    pub(crate) fn get_scalar<T: Scalar>(&self, conv: Conversion) -> Result<T, ConvertError> {
+        T::from_num(self.to_num().ok_or(ConvertError::Shape)?, conv)
+    }
+    pub(crate) fn get_array<T: Scalar>(&self, conv: Conversion) -> Result<Vec<T>, ConvertError> {
+        match self {
+            DynValue::Array(x) => x.get(conv),
+            _ => Err(ConvertError::Shape),
+        }
+    }
+
+    /// Convert value to specified type keeping its shape.
+    pub fn convert(&self, type_: Type, conv: Conversion) -> Result<Self, ConvertError> {
+        fn convert<T: Scalar>(x: &DynValue, conv: Conversion) -> Result<DynValue, ConvertError> {
+            x.get_scalar::<T>(conv).map(T::into_dyn)
+        }
+        match self {
+            DynValue::Array(x) => x.convert(type_, conv).map(DynValue::Array),
+            _ => dispatch_type!(type_, convert, self, conv),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($variant:ident: $t:ty),* $(,)?) => {$(
+        impl From<$t> for DynValue {
+            fn from(x: $t) -> Self {
+                DynValue::$variant(x)
+            }
+        }
+        impl From<Vec<$t>> for DynArray {
+            fn from(x: Vec<$t>) -> Self {
+                DynArray::$variant(x)
+            }
+        }
+        impl From<Vec<$t>> for DynValue {
+            fn from(x: Vec<$t>) -> Self {
+                DynValue::Array(DynArray::$variant(x))
+            }
+        }
+    )*};
+}
+
+impl_from!(U8: u8, I8: i8, U16: u16, I16: i16, U32: u32, I32: i32, U64: u64, I64: i64, F32: f32, F64: f64);
+
+impl From<DynArray> for DynValue {
+    fn from(x: DynArray) -> Self {
+        DynValue::Array(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lossless() {
+        let conv = Conversion::LOSSLESS;
+        assert_eq!(
+            DynValue::I32(-5).convert(Type::I64, conv).unwrap(),
+            DynValue::I64(-5)
+        );
+        assert_eq!(
+            DynValue::F64(3.0).convert(Type::U8, conv).unwrap(),
+            DynValue::U8(3)
+        );
+        assert_eq!(
+            DynValue::U32(7).convert(Type::F32, conv).unwrap(),
+            DynValue::F32(7.0)
+        );
+        assert!(matches!(
+            DynValue::I32(300).convert(Type::U8, conv),
+            Err(ConvertError::OutOfRange { type_: Type::U8 })
+        ));
+        assert!(matches!(
+            DynValue::I8(-1).convert(Type::U64, conv),
+            Err(ConvertError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            DynValue::F64(1.5).convert(Type::I32, conv),
+            Err(ConvertError::Inexact { type_: Type::I32 })
+        ));
+        assert!(matches!(
+            DynValue::F64(0.1).convert(Type::F32, conv),
+            Err(ConvertError::Inexact { .. })
+        ));
+        assert!(matches!(
+            DynValue::U64(u64::MAX).convert(Type::F64, conv),
+            Err(ConvertError::Inexact { .. })
+        ));
+    }
+
+    #[test]
+    fn lossy() {
+        let conv = Conversion::LOSSY;
+        assert_eq!(
+            DynValue::I32(300).convert(Type::U8, conv).unwrap(),
+            DynValue::U8(255)
+        );
+        assert_eq!(
+            DynValue::I32(-300).convert(Type::I8, conv).unwrap(),
+            DynValue::I8(-128)
+        );
+        assert_eq!(
+            DynValue::F64(-2.7).convert(Type::I16, conv).unwrap(),
+            DynValue::I16(-2)
+        );
+        assert_eq!(
+            DynValue::F64(f64::NAN).convert(Type::I32, conv).unwrap(),
+            DynValue::I32(0)
+        );
+        assert_eq!(
+            DynValue::F64(1e300).convert(Type::F32, conv).unwrap(),
+            DynValue::F32(f32::MAX)
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let value = DynValue::from(vec![1u8, 2, 255]);
+        assert_eq!(value.type_(), Type::U8);
+        assert!(value.is_array());
+        assert_eq!(
+            value.convert(Type::I16, Conversion::LOSSLESS).unwrap(),
+            DynValue::from(vec![1i16, 2, 255])
+        );
+        assert!(matches!(
+            value.convert(Type::I8, Conversion::LOSSLESS),
+            Err(ConvertError::OutOfRange { .. })
+        ));
+        assert_eq!(
+            value.get_array::<i8>(Conversion::LOSSY).unwrap(),
+            [1, 2, 127]
+        );
+        assert!(matches!(
+            value.get_scalar::<u8>(Conversion::LOSSY),
+            Err(ConvertError::Shape)
+        ));
+        assert!(matches!(
+            DynValue::U8(1).get_array::<u8>(Conversion::LOSSY),
+            Err(ConvertError::Shape)
+        ));
+    }
+
+    #[test]
+    fn policies() {
+        let conv = |overflow, rounding| Conversion { overflow, rounding };
+        let nearest = conv(Overflow::Error, Rounding::Nearest);
+        assert_eq!(DynValue::F64(2.5).get_scalar::<i32>(nearest).unwrap(), 3);
+        assert_eq!(DynValue::F64(-2.5).get_scalar::<i32>(nearest).unwrap(), -3);
+        assert!(matches!(
+            DynValue::F64(1e10).get_scalar::<i32>(nearest),
+            Err(ConvertError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            DynValue::F64(f64::NAN).get_scalar::<i32>(nearest),
+            Err(ConvertError::OutOfRange { .. })
+        ));
+
+        let saturate_exact = conv(Overflow::Saturate, Rounding::Exact);
+        assert_eq!(
+            DynValue::I64(-1).get_scalar::<u16>(saturate_exact).unwrap(),
+            0
+        );
+        assert!(matches!(
+            DynValue::F64(0.5).get_scalar::<u16>(saturate_exact),
+            Err(ConvertError::Inexact { .. })
+        ));
+        assert_eq!(
+            DynValue::F64(f64::NEG_INFINITY)
+                .get_scalar::<i16>(saturate_exact)
+                .unwrap(),
+            i16::MIN
+        );
+        for value in [f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                DynValue::F64(value).get_scalar::<u32>(Conversion::LOSSLESS),
+                Err(ConvertError::OutOfRange { .. })
+            ));
+        }
+        assert_eq!(
+            DynValue::F64(-1e40)
+                .get_scalar::<f32>(saturate_exact)
+                .unwrap(),
+            f32::MIN
+        );
+
+        let toward_zero = conv(Overflow::Error, Rounding::TowardZero);
+        assert_eq!(
+            DynValue::F32(-7.9).get_scalar::<i8>(toward_zero).unwrap(),
+            -7
+        );
+        assert_eq!(
+            DynValue::F64(0.1).get_scalar::<f32>(toward_zero).unwrap(),
+            0.1f32
+        );
+        assert_eq!(Conversion::default(), Conversion::LOSSLESS);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        for value in [
+            DynValue::from(-3i16),
+            DynValue::from(1.5f32),
+            DynValue::from(vec![1u64, u64::MAX]),
+        ] {
+            let text = serde_json::to_string(&value).unwrap();
+            assert_eq!(serde_json::from_str::<DynValue>(&text).unwrap(), value);
+        }
+        assert_eq!(
+            serde_json::to_string(&DynValue::from(vec![1u8, 2])).unwrap(),
+            r#"{"Array":{"U8":[1,2]}}"#
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Blocking execution of futures with timeout.
+
+use futures::task::{waker, ArcWake};
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+struct ThreadWaker(Thread);
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+/// Run `future` in current thread until it is complete or `timeout` elapsed.
+///
+/// Returns `None` on timeout, the future is dropped then.
+pub fn block_on_timeout<F: Future>(future: F, timeout: Duration) -> Option<F::Output> {
+    let deadline = Instant::now() + timeout;
+    let waker = waker(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::oneshot, future::pending};
+
+    #[test]
+    fn complete() {
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.send(1).unwrap();
+        });
+        assert_eq!(
+            block_on_timeout(receiver, Duration::from_secs(10)),
+            Some(Ok(1))
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        let start = Instant::now();
+        assert_eq!(
+            block_on_timeout(pending::<()>(), Duration::from_millis(20)),
+            None
+        );
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
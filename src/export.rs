@@ -1,14 +1,14 @@
 #![allow(clippy::missing_safety_doc)]
 
 use super::{import::*, variable::SystemVariable, Variable};
-use crate::registry::{self, Registry};
+use crate::{registry, Context};
 use std::{
     panic::{self, PanicHookInfo},
     thread,
 };
 
 extern "Rust" {
-    pub fn ferrite_app_main(variables: Registry);
+    pub fn ferrite_app_main(ctx: Context);
 }
 
 #[no_mangle]
@@ -23,7 +23,10 @@ pub extern "C" fn fer_app_init() {
 #[no_mangle]
 pub extern "C" fn fer_app_start() {
     thread::spawn(move || unsafe {
-        ferrite_app_main(registry::take());
+        ferrite_app_main(Context {
+            registry: registry::take(),
+            diagnostics: registry::take_diagnostics(),
+        });
         fer_app_exit(0);
     });
 }
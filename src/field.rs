@@ -0,0 +1,155 @@
+//! Access to fields of the backing record other than `VAL`.
+
+use crate::{
+    typed::Type as ValueType,
+    variable::{Type, Variable},
+};
+use derive_more::{Display, Error};
+use std::{any::TypeId, marker::PhantomData};
+
+#[derive(Clone, Debug, Display, Error)]
+pub enum FieldError {
+    #[display(fmt = "Record has no field '{}'", "name")]
+    NotFound { name: String },
+    #[display(fmt = "Field '{}' has unsupported type {}", "name", "raw_type")]
+    UnsupportedType { name: String, raw_type: u32 },
+    #[display(fmt = "Field '{}' has type {:?}", "name", "type_")]
+    WrongType { name: String, type_: Type },
+    #[display(fmt = "Field '{}' is not writable", "name")]
+    ReadOnly { name: String },
+}
+
+/// Fields that can be safely written by application.
+///
+/// Other fields (e.g. `NELM` or `NORD`) are used by the record support to access the value
+/// and must not be changed behind its back.
+pub const WRITABLE_FIELDS: &[&str] = &[
+    "HIHI", "HIGH", "LOW", "LOLO", "HHSV", "HSV", "LSV", "LLSV", "HYST", "ADEL", "MDEL", "HOPR",
+    "LOPR", "DRVH", "DRVL", "SIMM", "DISA",
+];
+
+/// Handle to record field.
+///
+/// *Each access locks the record.*
+pub struct Field<'a, T: ValueType> {
+    owner: &'a Variable,
+    name: &'a str,
+    ptr: *mut T,
+    _phantom: PhantomData<T>,
+}
+
+impl Variable {
+    /// Get record field by name (e.g. `HIHI`), checking that it has type `T`.
+    pub fn field<'a, T: ValueType>(&'a self, name: &'a str) -> Result<Field<'a, T>, FieldError> {
+        let (ptr, raw_type) = self
+            .field_ptr(name)
+            .ok_or_else(|| FieldError::NotFound { name: name.into() })?;
+        let type_ = Type::from_raw(raw_type).ok_or_else(|| FieldError::UnsupportedType {
+            name: name.into(),
+            raw_type,
+        })?;
+        if type_.type_id() != TypeId::of::<T>() {
+            return Err(FieldError::WrongType {
+                name: name.into(),
+                type_,
+            });
+        }
+        Ok(Field {
+            owner: self,
+            name,
+            ptr: ptr as *mut T,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: ValueType> Field<'_, T> {
+    pub fn get(&self) -> T {
+        let _guard = self.owner.lock();
+        unsafe { self.ptr.read() }
+    }
+    /// Write field if it is listed in [`WRITABLE_FIELDS`].
+    ///
+    /// *Monitors are not posted, clients see the new value after the next record processing.*
+    pub fn set(&self, value: T) -> Result<(), FieldError> {
+        if !WRITABLE_FIELDS.contains(&self.name) {
+            return Err(FieldError::ReadOnly {
+                name: self.name.into(),
+            });
+        }
+        unsafe { self.set_unchecked(value) };
+        Ok(())
+    }
+    /// Write any field.
+    ///
+    /// # Safety
+    ///
+    /// Writing fields used by the record support (e.g. `NELM`) may break its invariants.
+    pub unsafe fn set_unchecked(&self, value: T) {
+        let _guard = self.owner.lock();
+        self.ptr.write(value)
+    }
+}
+
+impl Variable {
+    /// Number of elements currently stored in the array record (`NORD`).
+    ///
+    /// Unlike `NELM` which is the capacity, see [`Info::max_len`](`crate::variable::Info::max_len`).
+    pub fn nord(&self) -> Result<usize, FieldError> {
+        Ok(self.field::<u32>("NORD")?.get() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeVar, variable::Direction};
+
+    #[test]
+    fn get_set() {
+        let fake = FakeVar::new("FIELD:AI", Type::F64, 0, Direction::Input)
+            .field("HIHI", Type::F64, 10.0f64)
+            .field("PREC", Type::I16, 3i16)
+            .field("INP", Type::U8, 0u8)
+            .raw_field_type("INP", 100)
+            .leak();
+        let var = fake.var();
+        let hihi = var.field::<f64>("HIHI").unwrap();
+        assert_eq!(hihi.get(), 10.0);
+        hihi.set(12.5).unwrap();
+        assert_eq!(fake.field_value::<f64>("HIHI"), 12.5);
+
+        let prec = var.field::<i16>("PREC").unwrap();
+        assert!(matches!(prec.set(5), Err(FieldError::ReadOnly { .. })));
+        assert_eq!(fake.field_value::<i16>("PREC"), 3);
+        unsafe { prec.set_unchecked(5) };
+        assert_eq!(prec.get(), 5);
+
+        assert!(matches!(
+            var.field::<f32>("HIHI"),
+            Err(FieldError::WrongType {
+                type_: Type::F64,
+                ..
+            })
+        ));
+        assert!(matches!(
+            var.field::<u8>("INP"),
+            Err(FieldError::UnsupportedType { raw_type: 100, .. })
+        ));
+        assert!(matches!(
+            var.field::<f64>("LOLO"),
+            Err(FieldError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn nord() {
+        let fake = FakeVar::new("FIELD:AAI", Type::I32, 16, Direction::Input)
+            .field("NELM", Type::U32, 16u32)
+            .field("NORD", Type::U32, 5u32)
+            .leak();
+        assert_eq!(fake.var().nord().unwrap(), 5);
+        let ai = FakeVar::new("FIELD:NONE", Type::F64, 0, Direction::Input).leak();
+        assert!(ai.var().nord().is_err());
+    }
+}
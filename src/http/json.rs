@@ -0,0 +1,141 @@
+//! Plain JSON representation of variable values: numbers for scalars, lists of numbers for arrays.
+
+use crate::{
+    dynamic::{dispatch_type, Conversion, ConvertError, DynArray, DynValue, Scalar},
+    variable::Info,
+};
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+pub fn to_json(value: &DynValue) -> Value {
+    fn convert<T: Scalar + Serialize>(value: &DynValue) -> Value {
+        match value {
+            DynValue::Array(_) => {
+                serde_json::to_value(value.get_array::<T>(Conversion::LOSSLESS).unwrap())
+            }
+            _ => serde_json::to_value(value.get_scalar::<T>(Conversion::LOSSLESS).unwrap()),
+        }
+        .unwrap()
+    }
+    dispatch_type!(value.type_(), convert, value)
+}
+
+fn number(number: &Number) -> DynValue {
+    if let Some(x) = number.as_i64() {
+        DynValue::I64(x)
+    } else if let Some(x) = number.as_u64() {
+        DynValue::U64(x)
+    } else {
+        DynValue::F64(number.as_f64().unwrap())
+    }
+}
+
+/// Parse value from JSON, it must then be converted to the variable type.
+pub fn from_json(json: &Value) -> Option<DynValue> {
+    match json {
+        Value::Number(x) => Some(number(x)),
+        Value::Array(xs) => {
+            let xs = xs
+                .iter()
+                .map(|x| x.as_number().map(number))
+                .collect::<Option<Vec<_>>>()?;
+            Some(DynValue::Array(
+                if xs.iter().all(|x| matches!(x, DynValue::I64(_))) {
+                    DynArray::I64(
+                        xs.into_iter()
+                            .map(|x| x.get_scalar(Conversion::LOSSLESS).unwrap())
+                            .collect(),
+                    )
+                } else if xs
+                    .iter()
+                    .all(|x| matches!(x, DynValue::I64(_) | DynValue::U64(_)))
+                {
+                    DynArray::U64(
+                        xs.into_iter()
+                            .map(|x| x.get_scalar(Conversion::LOSSLESS).ok())
+                            .collect::<Option<_>>()?,
+                    )
+                } else {
+                    DynArray::F64(
+                        xs.into_iter()
+                            .map(|x| x.get_scalar(Conversion::LOSSY).unwrap())
+                            .collect(),
+                    )
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Convert parsed value to the type and shape of the variable.
+pub fn convert(value: &DynValue, info: Info) -> Result<DynValue, ConvertError> {
+    if value.is_array() != (info.max_len != 0) {
+        return Err(ConvertError::Shape);
+    }
+    if let DynValue::Array(array) = value {
+        if array.len() > info.max_len {
+            return Err(ConvertError::TooLong {
+                len: array.len(),
+                max_len: info.max_len,
+            });
+        }
+    }
+    value.convert(info.type_, Conversion::LOSSLESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variable::Type;
+    use serde_json::json;
+
+    #[test]
+    fn parse() {
+        assert_eq!(from_json(&json!(-1)), Some(DynValue::I64(-1)));
+        assert_eq!(from_json(&json!(u64::MAX)), Some(DynValue::U64(u64::MAX)));
+        assert_eq!(from_json(&json!(0.5)), Some(DynValue::F64(0.5)));
+        assert_eq!(
+            from_json(&json!([1, u64::MAX])),
+            Some(DynValue::from(vec![1u64, u64::MAX]))
+        );
+        assert_eq!(
+            from_json(&json!([1, 0.5])),
+            Some(DynValue::from(vec![1.0f64, 0.5]))
+        );
+        assert_eq!(from_json(&json!([-1, u64::MAX])), None);
+        assert_eq!(from_json(&json!("1")), None);
+        assert_eq!(from_json(&json!([1, "2"])), None);
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(to_json(&DynValue::from(3u16)), json!(3));
+        assert_eq!(to_json(&DynValue::from(vec![0.5f32])), json!([0.5]));
+    }
+
+    #[test]
+    fn convert_to_info() {
+        let info = |type_, max_len| Info { type_, max_len };
+        assert_eq!(
+            convert(&DynValue::I64(200), info(Type::U8, 0)).unwrap(),
+            DynValue::U8(200)
+        );
+        assert!(matches!(
+            convert(&DynValue::I64(-1), info(Type::U8, 0)),
+            Err(ConvertError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            convert(&DynValue::I64(1), info(Type::U8, 4)),
+            Err(ConvertError::Shape)
+        ));
+        assert!(matches!(
+            convert(&DynValue::from(vec![1i64; 5]), info(Type::I16, 4)),
+            Err(ConvertError::TooLong { len: 5, max_len: 4 })
+        ));
+        assert_eq!(
+            convert(&DynValue::from(vec![1i64; 4]), info(Type::F32, 4)).unwrap(),
+            DynValue::from(vec![1.0f32; 4])
+        );
+    }
+}
@@ -0,0 +1,535 @@
+//! Embedded HTTP gateway for debugging and dashboards.
+//!
+//! + `GET /vars` - list of all variables with their types.
+//! + `GET /vars/{name}` - current value of the variable.
+//! + `PUT /vars/{name}` - write value to the variable, allowed only for variables passed to [`Gateway::writable`].
+//!
+//! Request bodies are read and writes are performed in a separate thread for each variable,
+//! so slow clients and records don't block other requests.
+//! Bodies larger than 64 bytes plus 32 bytes per array element are rejected with `413`.
+//!
+//! Values are represented as JSON numbers for scalars and lists of numbers for arrays.
+//!
+//! With `websocket` feature committed values can also be streamed, see [`Gateway::websocket`].
+
+mod json;
+#[cfg(feature = "websocket")]
+mod ws;
+
+use crate::{
+    dynamic::{Conversion, DynValue, DynVariable},
+    registry::{shared, Observer},
+    variable::{Info, Type},
+};
+use futures::executor::block_on;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    net::{SocketAddr, ToSocketAddrs},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response};
+
+/// Gateway configuration.
+pub struct Gateway {
+    writable: HashMap<String, DynVariable>,
+    #[cfg(feature = "websocket")]
+    websocket: Option<SocketAddr>,
+    #[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+    min_interval: Duration,
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self {
+            writable: HashMap::new(),
+            #[cfg(feature = "websocket")]
+            websocket: None,
+            min_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Maximum number of pending writes to a single variable.
+const WRITE_QUEUE: usize = 4;
+
+/// Maximum size of PUT request body for variable: enough for any JSON number per element.
+fn max_body_len(info: Info) -> usize {
+    64 + 32 * info.max_len
+}
+
+/// Thread reading values from requests and writing them to the variable.
+struct Writer {
+    info: Info,
+    sender: SyncSender<Request>,
+}
+
+/// Gateway bound to address.
+pub struct Server {
+    inner: tiny_http::Server,
+    writers: HashMap<String, Writer>,
+    #[cfg(feature = "websocket")]
+    websocket_addr: Option<SocketAddr>,
+}
+
+#[derive(Serialize)]
+struct VarInfo {
+    name: String,
+    #[serde(rename = "type")]
+    type_: Type,
+    max_len: usize,
+    writable: bool,
+}
+
+#[derive(Serialize)]
+struct VarValue {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+type HttpResponse = Response<io::Cursor<Vec<u8>>>;
+
+fn json_response<T: Serialize>(code: u16, body: &T) -> HttpResponse {
+    Response::from_string(serde_json::to_string(body).unwrap())
+        .with_status_code(code)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+fn error_response(code: u16, error: String) -> HttpResponse {
+    json_response(code, &ErrorBody { error })
+}
+fn respond(request: Request, response: HttpResponse) {
+    if let Err(err) = request.respond(response) {
+        log::warn!("HTTP gateway: {}", err);
+    }
+}
+
+/// Decode `%XX` escapes in URL path.
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get((i + 1)..(i + 3))?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl Gateway {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow writing to the variable through the gateway.
+    ///
+    /// Values are written via [`request`](`crate::TypedVariable::request`), so the variable is owned by the gateway.
+    pub fn writable(mut self, var: DynVariable) -> Self {
+        self.writable.insert(var.name().into(), var);
+        self
+    }
+
+    /// Serve WebSocket clients at separate `addr`.
+    ///
+    /// Clients connect to `/ws?names=NAME1,NAME2` and receive committed values of these variables.
+    #[cfg(feature = "websocket")]
+    pub fn websocket(mut self, addr: SocketAddr) -> Self {
+        self.websocket = Some(addr);
+        self
+    }
+
+    /// Maximum number of messages per second sent to each WebSocket client, 10 by default.
+    ///
+    /// # Panics
+    ///
+    /// If `rate` is not positive.
+    pub fn max_rate(mut self, rate: f64) -> Self {
+        assert!(rate > 0.0, "Rate must be positive, got {}", rate);
+        self.min_interval = Duration::try_from_secs_f64(1.0 / rate).unwrap_or(Duration::MAX);
+        self
+    }
+
+    /// Bind to `addr` and start writer threads and WebSocket server.
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<Server> {
+        let inner =
+            tiny_http::Server::http(addr).map_err(|err| io::Error::other(err.to_string()))?;
+        #[cfg(feature = "websocket")]
+        let websocket_addr = match self.websocket {
+            Some(addr) => {
+                let listener = std::net::TcpListener::bind(addr)?;
+                let addr = listener.local_addr()?;
+                ws::spawn(listener, self.min_interval);
+                Some(addr)
+            }
+            None => None,
+        };
+        Ok(Server {
+            inner,
+            writers: self
+                .writable
+                .into_iter()
+                .map(|(name, var)| (name, Writer::spawn(var)))
+                .collect(),
+            #[cfg(feature = "websocket")]
+            websocket_addr,
+        })
+    }
+}
+
+/// Read and validate value, so that the record isn't touched if value is invalid.
+fn read_value(request: &mut Request, name: &str, info: Info) -> Result<DynValue, HttpResponse> {
+    let max_len = max_body_len(info);
+    let mut body = String::new();
+    if let Err(err) = request
+        .as_reader()
+        .take(max_len as u64 + 1)
+        .read_to_string(&mut body)
+    {
+        return Err(error_response(400, err.to_string()));
+    }
+    if body.len() > max_len {
+        return Err(too_large(name, max_len));
+    }
+    let value = serde_json::from_str(&body)
+        .ok()
+        .as_ref()
+        .and_then(json::from_json)
+        .ok_or_else(|| error_response(400, "Number or list of numbers expected".into()))?;
+    json::convert(&value, info)
+        .map_err(|err| error_response(400, format!("PV '{}': {}", name, err)))
+}
+
+fn too_large(name: &str, max_len: usize) -> HttpResponse {
+    let error = format!("PV '{}': Body exceeds {} bytes", name, max_len);
+    error_response(413, error)
+}
+
+impl Writer {
+    fn spawn(mut var: DynVariable) -> Self {
+        let info = var.info();
+        let (sender, receiver) = mpsc::sync_channel::<Request>(WRITE_QUEUE);
+        thread::Builder::new()
+            .name(format!("http:{}", var.name()))
+            .spawn(move || {
+                for mut request in receiver {
+                    let name = var.name().to_string();
+                    let value = match read_value(&mut request, &name, info) {
+                        Ok(value) => value,
+                        Err(response) => {
+                            respond(request, response);
+                            continue;
+                        }
+                    };
+                    let response = block_on(async {
+                        match var.request().await.write(&value, Conversion::LOSSLESS) {
+                            Ok(commit) => {
+                                commit.await;
+                                json_response(
+                                    200,
+                                    &VarValue {
+                                        name,
+                                        value: json::to_json(&value),
+                                    },
+                                )
+                            }
+                            Err(err) => error_response(400, format!("PV '{}': {}", name, err)),
+                        }
+                    });
+                    respond(request, response);
+                }
+            })
+            .unwrap();
+        Self { info, sender }
+    }
+}
+
+impl Server {
+    fn list(&self) -> HttpResponse {
+        let vars: Vec<_> = shared()
+            .names()
+            .into_iter()
+            .filter_map(|name| {
+                let observer: Observer<DynValue> = shared().get_downcast(&name).ok()?;
+                let info = observer.info();
+                Some(VarInfo {
+                    writable: self.writers.contains_key(&name),
+                    name,
+                    type_: info.type_,
+                    max_len: info.max_len,
+                })
+            })
+            .collect();
+        json_response(200, &vars)
+    }
+
+    fn get(&self, name: &str) -> HttpResponse {
+        let observer: Observer<DynValue> = match shared().get_downcast(name) {
+            Ok(observer) => observer,
+            Err(err) => return error_response(404, err.to_string()),
+        };
+        match observer.load() {
+            Some(value) => json_response(
+                200,
+                &VarValue {
+                    name: name.into(),
+                    value: json::to_json(&value),
+                },
+            ),
+            None => error_response(503, format!("PV '{}': Is being processed", name)),
+        }
+    }
+
+    /// Pass request to the writer, body is read there.
+    fn put(&self, name: &str, request: Request) {
+        let writer = match self.writers.get(name) {
+            Some(writer) => writer,
+            None if shared().contains(name) => {
+                let error = format!("PV '{}': Is not writable", name);
+                return respond(request, error_response(403, error));
+            }
+            None => {
+                return respond(
+                    request,
+                    error_response(404, format!("PV '{}': Not found", name)),
+                )
+            }
+        };
+        let max_len = max_body_len(writer.info);
+        if request.body_length().is_some_and(|len| len > max_len) {
+            return respond(request, too_large(name, max_len));
+        }
+        match writer.sender.try_send(request) {
+            Ok(()) => (),
+            Err(TrySendError::Full(request)) => {
+                let error = format!("PV '{}': Too many pending writes", name);
+                respond(request, error_response(503, error))
+            }
+            Err(TrySendError::Disconnected(request)) => {
+                let error = format!("PV '{}': Writer stopped", name);
+                respond(request, error_response(500, error))
+            }
+        }
+    }
+
+    fn handle(&self, request: Request) {
+        let path = request.url().split('?').next().unwrap_or_default();
+        let name = match path.strip_prefix("/vars") {
+            Some("") | Some("/") => None,
+            Some(rest) => match rest.strip_prefix('/').and_then(percent_decode) {
+                Some(name) => Some(name),
+                None => return respond(request, error_response(404, "Not found".into())),
+            },
+            None => return respond(request, error_response(404, "Not found".into())),
+        };
+        let response = match (request.method(), name) {
+            (Method::Get, None) => self.list(),
+            (Method::Get, Some(name)) => self.get(&name),
+            (Method::Put, Some(name)) => return self.put(&name, request),
+            _ => error_response(405, "Method not allowed".into()),
+        };
+        respond(request, response)
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.server_addr().to_ip()
+    }
+    /// Address of the WebSocket listener, if any.
+    #[cfg(feature = "websocket")]
+    pub fn websocket_addr(&self) -> Option<SocketAddr> {
+        self.websocket_addr
+    }
+
+    /// Handle requests one by one, never returns.
+    pub fn run(self) {
+        for request in self.inner.incoming_requests() {
+            self.handle(request);
+        }
+    }
+    /// Run server in a separate thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name("http".into())
+            .spawn(move || self.run())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{downcast::Downcast, testing::FakeVar, variable::Direction};
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::atomic::Ordering,
+    };
+
+    fn http(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: test\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let code = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (code, body)
+    }
+
+    fn add(name: &str, type_: Type, max_len: usize) -> &'static FakeVar {
+        let fake = FakeVar::new(name, type_, max_len, Direction::Output)
+            .auto_process()
+            .leak();
+        shared().add_variable(fake.var());
+        fake
+    }
+
+    fn writable(fake: &'static FakeVar) -> DynVariable {
+        Downcast::<DynVariable>::downcast(fake.var()).unwrap()
+    }
+
+    #[test]
+    fn percent() {
+        assert_eq!(percent_decode("A%3AB%20C").unwrap(), "A:B C");
+        assert!(percent_decode("A%3").is_none());
+        assert!(percent_decode("%ZZ").is_none());
+    }
+
+    #[test]
+    fn get_put() {
+        let value = add("HTTP:VALUE", Type::I32, 0);
+        let array = add("HTTP:ARRAY", Type::U8, 2);
+        add("HTTP:READONLY", Type::F64, 0);
+        let server = Gateway::new()
+            .writable(writable(value))
+            .writable(writable(array))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+
+        assert_eq!(
+            http(addr, "PUT", "/vars/HTTP%3AVALUE", "-7"),
+            (200, r#"{"name":"HTTP:VALUE","value":-7}"#.into())
+        );
+        assert_eq!(
+            http(addr, "GET", "/vars/HTTP:VALUE", ""),
+            (200, r#"{"name":"HTTP:VALUE","value":-7}"#.into())
+        );
+        assert_eq!(http(addr, "PUT", "/vars/HTTP:ARRAY", "[1, 2]").0, 200);
+        assert_eq!(array.load(), DynValue::from(vec![1u8, 2]));
+
+        // Invalid values are rejected without processing the record.
+        let requests = value.requests.load(Ordering::SeqCst);
+        for body in ["x", "1.5", "[1]", "3000000000"] {
+            assert_eq!(
+                http(addr, "PUT", "/vars/HTTP:VALUE", body).0,
+                400,
+                "{}",
+                body
+            );
+        }
+        for body in ["256", "[1, 2, 3]", "2"] {
+            assert_eq!(
+                http(addr, "PUT", "/vars/HTTP:ARRAY", body).0,
+                400,
+                "{}",
+                body
+            );
+        }
+        // Body size is checked before reading it.
+        let (code, body) = http(addr, "PUT", "/vars/HTTP:VALUE", &" ".repeat(65));
+        assert_eq!(code, 413);
+        assert!(body.contains("Body exceeds 64 bytes"));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"PUT /vars/HTTP:VALUE HTTP/1.1\r\nConnection: close\r\nContent-Length: 1000000000\r\n\r\n",
+            )
+            .unwrap();
+        // Connection is not closed until the body is drained, so only the status is read.
+        let mut response = [0; 12];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"HTTP/1.1 413");
+        drop(stream);
+        assert_eq!(value.requests.load(Ordering::SeqCst), requests);
+        assert_eq!(value.load(), DynValue::from(-7i32));
+        assert_eq!(
+            http(
+                addr,
+                "PUT",
+                "/vars/HTTP:ARRAY",
+                &format!("[1,{}2]", " ".repeat(100))
+            )
+            .0,
+            200
+        );
+
+        assert_eq!(http(addr, "PUT", "/vars/HTTP:READONLY", "1").0, 403);
+        assert_eq!(http(addr, "PUT", "/vars/HTTP:NONE", "1").0, 404);
+        assert_eq!(http(addr, "GET", "/vars/HTTP:NONE", "").0, 404);
+        assert_eq!(http(addr, "DELETE", "/vars/HTTP:VALUE", "").0, 405);
+        assert_eq!(http(addr, "GET", "/other", "").0, 404);
+
+        let (code, list) = http(addr, "GET", "/vars", "");
+        assert_eq!(code, 200);
+        assert!(list.contains(r#"{"name":"HTTP:ARRAY","type":"U8","max_len":2,"writable":true}"#));
+        assert!(
+            list.contains(r#"{"name":"HTTP:READONLY","type":"F64","max_len":0,"writable":false}"#)
+        );
+    }
+
+    #[test]
+    fn stuck_put() {
+        // Record is never processed.
+        let stuck = FakeVar::new("HTTP:STUCK", Type::F64, 0, Direction::Output).leak();
+        shared().add_variable(stuck.var());
+        let server = Gateway::new()
+            .writable(writable(stuck))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+
+        // First write is being processed, others are queued until the queue is full.
+        let mut pending = Vec::new();
+        let code = loop {
+            assert!(pending.len() <= WRITE_QUEUE + 1);
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(b"PUT /vars/HTTP:STUCK HTTP/1.1\r\nContent-Length: 1\r\n\r\n1")
+                .unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(500)))
+                .unwrap();
+            let mut response = [0; 12];
+            match stream.read_exact(&mut response) {
+                Ok(()) => break String::from_utf8_lossy(&response[9..]).into_owned(),
+                Err(_) => pending.push(stream),
+            }
+        };
+        assert_eq!(code, "503");
+        assert_eq!(pending.len(), WRITE_QUEUE + 1);
+        assert_eq!(stuck.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(http(addr, "GET", "/vars/HTTP:STUCK", "").0, 200);
+    }
+}
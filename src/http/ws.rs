@@ -0,0 +1,271 @@
+//! Live subscription to committed values over WebSocket.
+//!
+//! Client connects to `/ws?names=NAME1,NAME2` at [`Gateway::websocket`](super::Gateway::websocket) address
+//! and receives a JSON message for each committed value.
+//! Values committed faster than the client rate limit are replaced by the latest ones.
+
+use super::{json, percent_decode, ErrorBody};
+use crate::registry::{shared, Event, Subscription};
+use serde::Serialize;
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use tungstenite::{
+    accept_hdr,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    Error, Message, WebSocket,
+};
+
+/// Interval between pings when there are no events.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+/// Maximum time to complete the opening handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for client messages (pings and close requests) between checks for events.
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+#[derive(Serialize)]
+struct EventBody<'a> {
+    name: &'a str,
+    value: serde_json::Value,
+    /// Seconds since UNIX epoch.
+    timestamp: f64,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+}
+
+impl<'a> EventBody<'a> {
+    fn new(event: &'a Event) -> Self {
+        Self {
+            name: &event.name,
+            value: json::to_json(&event.value),
+            timestamp: event
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            status: if event.status.is_ok() { "ok" } else { "error" },
+            message: event.status.as_ref().err().map(String::as_str),
+        }
+    }
+}
+
+fn error_response(code: StatusCode, error: String) -> ErrorResponse {
+    let mut response =
+        ErrorResponse::new(Some(serde_json::to_string(&ErrorBody { error }).unwrap()));
+    *response.status_mut() = code;
+    response
+}
+
+fn subscribe(query: &str) -> Result<Subscription, String> {
+    let names = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("names="))
+        .and_then(percent_decode)
+        .ok_or("Missing 'names' parameter")?;
+    let names: Vec<_> = names.split(',').filter(|name| !name.is_empty()).collect();
+    shared().subscribe(&names).map_err(|err| err.to_string())
+}
+
+/// Accept clients in a separate thread, each client is served in its own thread.
+pub fn spawn(listener: TcpListener, min_interval: Duration) {
+    thread::Builder::new()
+        .name("websocket".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("WebSocket: {}", err);
+                        continue;
+                    }
+                };
+                thread::Builder::new()
+                    .name("websocket:client".into())
+                    .spawn(move || match handshake(stream) {
+                        Ok((socket, subscription)) => {
+                            if let Err(err) = serve(socket, subscription, min_interval) {
+                                log::debug!("WebSocket client disconnected: {}", err);
+                            }
+                        }
+                        Err(err) => log::debug!("WebSocket handshake failed: {}", err),
+                    })
+                    .unwrap();
+            }
+        })
+        .unwrap();
+}
+
+// Error response type is defined by `tungstenite`.
+#[allow(clippy::result_large_err)]
+fn handshake(stream: TcpStream) -> Result<(WebSocket<TcpStream>, Subscription), String> {
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    let mut subscription = None;
+    let socket = accept_hdr(stream, |request: &Request, response: Response| {
+        if request.uri().path() != "/ws" {
+            return Err(error_response(StatusCode::NOT_FOUND, "Not found".into()));
+        }
+        match subscribe(request.uri().query().unwrap_or_default()) {
+            Ok(value) => {
+                subscription = Some(value);
+                Ok(response)
+            }
+            Err(error) => Err(error_response(StatusCode::BAD_REQUEST, error)),
+        }
+    })
+    .map_err(|err| err.to_string())?;
+    Ok((socket, subscription.unwrap()))
+}
+
+fn serve(
+    mut socket: WebSocket<TcpStream>,
+    subscription: Subscription,
+    min_interval: Duration,
+) -> Result<(), Box<Error>> {
+    socket
+        .get_ref()
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(Error::Io)?;
+    let mut last_active = Instant::now();
+    // `None` if interval is too large to ever send again.
+    let mut next_send = Some(last_active);
+    loop {
+        // Replies to pings and close requests are queued by `read` and sent by `flush`.
+        let result = match socket.read() {
+            Ok(_) => socket.flush(),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => (),
+            Err(Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+        if last_active.elapsed() >= PING_INTERVAL {
+            socket.send(Message::Ping(Vec::new()))?;
+            last_active = Instant::now();
+        }
+        if next_send.is_none_or(|time| Instant::now() < time) {
+            continue;
+        }
+        let events = subscription.recv_timeout(READ_TIMEOUT);
+        if events.is_empty() {
+            continue;
+        }
+        for event in &events {
+            let body = serde_json::to_string(&EventBody::new(event)).unwrap();
+            socket.write(Message::Text(body))?;
+        }
+        socket.flush()?;
+        last_active = Instant::now();
+        next_send = last_active.checked_add(min_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        downcast::Downcast,
+        dynamic::{Conversion, DynValue, DynVariable},
+        http::Gateway,
+        testing::FakeVar,
+        variable::{Direction, Type},
+    };
+    use futures::executor::block_on;
+    use std::net::SocketAddr;
+
+    /// Status code is returned if handshake is rejected by server.
+    fn connect(addr: SocketAddr, path: &str) -> Result<WebSocket<TcpStream>, StatusCode> {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        tungstenite::client(format!("ws://{}{}", addr, path), stream)
+            .map(|(socket, _)| socket)
+            .map_err(|err| match err {
+                tungstenite::HandshakeError::Failure(Error::Http(response)) => response.status(),
+                err => panic!("Handshake failed: {}", err),
+            })
+    }
+
+    fn read_event(socket: &mut WebSocket<TcpStream>) -> serde_json::Value {
+        match socket.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn subscribe() {
+        let fake = FakeVar::new("WS:VALUE", Type::I32, 0, Direction::Output)
+            .auto_process()
+            .leak();
+        shared().add_variable(fake.var());
+        let mut var = Downcast::<DynVariable>::downcast(fake.var()).unwrap();
+        let mut write = |value: i32| {
+            block_on(async {
+                let guard = var.request().await;
+                guard
+                    .write(&DynValue::from(value), Conversion::LOSSLESS)
+                    .unwrap()
+                    .await
+            })
+        };
+
+        let server = Gateway::new()
+            .websocket("127.0.0.1:0".parse().unwrap())
+            .max_rate(1.0)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.websocket_addr().unwrap();
+
+        assert_eq!(
+            connect(addr, "/ws?names=WS:NONE").err(),
+            Some(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            connect(addr, "/other?names=WS:VALUE").err(),
+            Some(StatusCode::NOT_FOUND)
+        );
+
+        let mut socket = connect(addr, "/ws?names=WS%3AVALUE").unwrap();
+        write(1);
+        let event = read_event(&mut socket);
+        assert_eq!(event["name"], "WS:VALUE");
+        assert_eq!(event["value"], 1);
+        assert_eq!(event["status"], "ok");
+
+        // Values committed during rate limit interval are replaced by the latest one.
+        write(2);
+        write(3);
+        let start = Instant::now();
+        assert_eq!(read_event(&mut socket)["value"], 3);
+        assert!(start.elapsed() > Duration::from_millis(500));
+
+        socket.send(Message::Ping(vec![1, 2])).unwrap();
+        assert_eq!(socket.read().unwrap(), Message::Pong(vec![1, 2]));
+
+        socket.close(None).unwrap();
+        loop {
+            match socket.read() {
+                Ok(Message::Close(_)) => (),
+                Err(Error::ConnectionClosed) => break,
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_rate() {
+        let _ = Gateway::new().max_rate(0.0);
+    }
+}
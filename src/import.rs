@@ -17,6 +17,7 @@ pub enum FerVarStatus {
 
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FerVarType {
     U8 = 0,
     I8,
@@ -31,6 +32,35 @@ pub enum FerVarType {
 }
 
 impl FerVarType {
+    pub const ALL: [Self; 10] = [
+        FerVarType::U8,
+        FerVarType::I8,
+        FerVarType::U16,
+        FerVarType::I16,
+        FerVarType::U32,
+        FerVarType::I32,
+        FerVarType::U64,
+        FerVarType::I64,
+        FerVarType::F32,
+        FerVarType::F64,
+    ];
+
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        Some(match raw {
+            0 => FerVarType::U8,
+            1 => FerVarType::I8,
+            2 => FerVarType::U16,
+            3 => FerVarType::I16,
+            4 => FerVarType::U32,
+            5 => FerVarType::I32,
+            6 => FerVarType::U64,
+            7 => FerVarType::I64,
+            8 => FerVarType::F32,
+            9 => FerVarType::F64,
+            _ => return None,
+        })
+    }
+
     pub fn type_id(self) -> TypeId {
         match self {
             FerVarType::U8 => TypeId::of::<u8>(),
@@ -47,13 +77,43 @@ impl FerVarType {
     }
 }
 
+/// Whether variable backs an input or an output record.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FerVarDir {
+    /// Input record (e.g. `ai`), value comes from application to IOC, see [`Output`](`crate::Output`).
+    Input = 0,
+    /// Output record (e.g. `ao`), value comes from IOC to application, see [`Input`](`crate::Input`).
+    Output,
+}
+
+impl FerVarDir {
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(FerVarDir::Input),
+            1 => Some(FerVarDir::Output),
+            _ => None,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FerVarInfo {
     pub type_: FerVarType,
     pub max_len: usize,
 }
 
+/// [`FerVarInfo`] as it comes from C side, type is not validated yet.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FerVarRawInfo {
+    pub type_: u32,
+    pub max_len: usize,
+}
+
 #[repr(C)]
 pub struct FerVarValue {
     _unused: [u8; 0],
@@ -69,8 +129,27 @@ extern "C" {
     pub fn fer_var_unlock(var: *mut FerVar);
 
     pub fn fer_var_name(var: *mut FerVar) -> *const c_char;
-    pub fn fer_var_info(var: *mut FerVar) -> FerVarInfo;
+    pub fn fer_var_info(var: *mut FerVar) -> FerVarRawInfo;
+    /// Raw [`FerVarDir`] of the record: `0` for input records and `1` for output ones.
+    ///
+    /// Constant during variable lifetime, may be called without lock.
+    pub fn fer_var_dir(var: *mut FerVar) -> u32;
+    /// Null-terminated name of the record type (e.g. `ai`).
+    ///
+    /// Pointed string is owned by C side and lives as long as variable, may be called without lock.
+    pub fn fer_var_rtyp(var: *mut FerVar) -> *const c_char;
     pub fn fer_var_value(var: *mut FerVar) -> *mut FerVarValue;
+    /// Pointer to record field, null if there is no such field.
+    ///
+    /// `name` is a field name (e.g. `NORD`) of `name_len` bytes, not null-terminated.
+    /// Raw [`FerVarType`] of the field is stored to `type_`, it is left untouched if there is no such field.
+    /// Pointed value should be accessed only under `fer_var_lock`.
+    pub fn fer_var_field(
+        var: *mut FerVar,
+        name: *const c_char,
+        name_len: usize,
+        type_: *mut u32,
+    ) -> *mut c_void;
     //pub fn fer_var_value_len(var: *mut FerVar) -> *mut usize;
     //pub fn fer_var_value_data(var: *mut FerVar) -> *mut c_void;
 
@@ -1,19 +1,60 @@
+mod diagnostics;
 mod downcast;
+#[cfg(any(feature = "modbus", feature = "scpi", feature = "stream"))]
+mod driver;
+#[cfg(any(feature = "modbus", feature = "scpi", feature = "stream"))]
+mod executor;
 mod import;
+mod pattern;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "tracing")]
+mod trace;
 
+pub mod app;
 pub mod atomic;
+pub mod autosave;
+pub mod db;
+pub mod dynamic;
 pub mod export;
+pub mod field;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod manifest;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+pub mod record;
 pub mod registry;
+#[cfg(feature = "scpi")]
+pub mod scpi;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(feature = "stream")]
+pub mod stream;
 pub mod typed;
 pub mod variable;
 
+pub use app::App;
+pub use diagnostics::{Diagnostic, Diagnostics, DiagnosticsError};
 pub use downcast::Downcast;
+pub use dynamic::{DynValue, DynVariable};
+pub use manifest::Manifest;
 pub use registry::Registry;
-pub use typed::{FlatVec, TypedVariable};
-pub use variable::{Info, Variable};
+pub use typed::{FlatVec, Input, Output, TypedVariable};
+pub use variable::{Direction, Info, Variable};
 
 pub struct Context {
     pub registry: Registry,
+    /// Problems with variables found during initialization.
+    pub diagnostics: Diagnostics,
+}
+
+/// Exit the whole IOC with specified `code`.
+pub fn exit(code: i32) -> ! {
+    unsafe { import::fer_app_exit(code) };
+    std::process::exit(code)
 }
 
 #[macro_export]
@@ -33,3 +74,28 @@ macro_rules! entry_point {
         }
     );
 }
+
+/// Define entry point that routes variables to several independent apps by name prefix.
+///
+/// ```ignore
+/// ferrite_core::apps! {
+///     dev1: "DEV1:" => dev1::main,
+///     dev2: "DEV2:" => dev2::main,
+/// }
+/// ```
+#[macro_export]
+macro_rules! apps {
+    ($($name:ident : $prefix:literal => $main:path),* $(,)?) => (
+        #[no_mangle]
+        pub extern "Rust" fn ferrite_app_main(ctx: $crate::Context) {
+            $crate::app::run(
+                ctx,
+                &[$($crate::App {
+                    name: stringify!($name),
+                    prefix: $prefix,
+                    main: $main,
+                }),*],
+            )
+        }
+    );
+}
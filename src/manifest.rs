@@ -0,0 +1,181 @@
+pub use crate::variable::Direction;
+
+use crate::{
+    dynamic::Scalar,
+    variable::{Info, Type},
+};
+use derive_more::Error;
+use std::{collections::HashMap, fmt};
+
+/// Declaration of the variable expected by application.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    pub name: String,
+    pub info: Info,
+    /// Direction of the backing record, checked by [`Registry::validate`](`crate::Registry::validate`).
+    pub direction: Direction,
+    /// Value should be saved and restored on restart, see [`Autosave`](`crate::autosave::Autosave`).
+    pub persistent: bool,
+}
+
+/// List of variables expected by application.
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    entries: Vec<Entry>,
+    /// Index of the last entry with given name.
+    index: HashMap<String, usize>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entry(mut self, entry: Entry) -> Self {
+        self.index.insert(entry.name.clone(), self.entries.len());
+        self.entries.push(entry);
+        self
+    }
+    pub fn scalar<T: Scalar>(self, name: &str, direction: Direction) -> Self {
+        self.entry(Entry {
+            name: name.into(),
+            info: Info {
+                type_: T::TYPE,
+                max_len: 0,
+            },
+            direction,
+            persistent: false,
+        })
+    }
+    pub fn array<T: Scalar>(self, name: &str, max_len: usize, direction: Direction) -> Self {
+        self.entry(Entry {
+            name: name.into(),
+            info: Info {
+                type_: T::TYPE,
+                max_len,
+            },
+            direction,
+            persistent: false,
+        })
+    }
+    /// Mark the last added entry as persistent.
+    pub fn persistent(mut self) -> Self {
+        self.entries
+            .last_mut()
+            .expect("Manifest is empty")
+            .persistent = true;
+        self
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.index.get(name).map(|&i| &self.entries[i])
+    }
+}
+
+/// Difference between expected and actual property of the variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mismatch<T> {
+    pub name: String,
+    pub expected: T,
+    pub actual: T,
+}
+
+/// Result of [`Registry::validate`](`crate::Registry::validate`), lists all inconsistencies found.
+#[derive(Clone, Debug, Default, Error)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidationReport {
+    /// Declared in manifest but not present in registry.
+    pub missing: Vec<String>,
+    /// Present in registry but not declared in manifest.
+    pub extra: Vec<String>,
+    pub wrong_type: Vec<Mismatch<Type>>,
+    pub wrong_len: Vec<Mismatch<usize>>,
+    pub wrong_direction: Vec<Mismatch<Direction>>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.wrong_type.is_empty()
+            && self.wrong_len.is_empty()
+            && self.wrong_direction.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "PVs don't match manifest:")?;
+        for name in &self.missing {
+            writeln!(f, "  - PV '{}': Missing", name)?;
+        }
+        for name in &self.extra {
+            writeln!(f, "  - PV '{}': Not declared", name)?;
+        }
+        for m in &self.wrong_type {
+            writeln!(
+                f,
+                "  - PV '{}': Wrong type {:?}, {:?} expected",
+                m.name, m.actual, m.expected
+            )?;
+        }
+        for m in &self.wrong_len {
+            writeln!(
+                f,
+                "  - PV '{}': Wrong max length {}, {} expected",
+                m.name, m.actual, m.expected
+            )?;
+        }
+        for m in &self.wrong_direction {
+            writeln!(
+                f,
+                "  - PV '{}': Wrong direction {:?}, {:?} expected",
+                m.name, m.actual, m.expected
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get() {
+        let manifest = Manifest::new()
+            .scalar::<f64>("TEMP", Direction::Input)
+            .array::<u8>("NAME", 16, Direction::Output)
+            .persistent();
+        assert_eq!(manifest.entries().len(), 2);
+        let name = manifest.get("NAME").unwrap();
+        assert_eq!(name.info.type_, Type::U8);
+        assert_eq!(name.info.max_len, 16);
+        assert!(name.persistent);
+        assert!(!manifest.get("TEMP").unwrap().persistent);
+        assert!(manifest.get("VOLT").is_none());
+    }
+
+    #[test]
+    fn report() {
+        let report = ValidationReport {
+            missing: vec!["A".into()],
+            wrong_len: vec![Mismatch {
+                name: "B".into(),
+                expected: 4,
+                actual: 8,
+            }],
+            ..Default::default()
+        };
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.to_string(),
+            "PVs don't match manifest:\n  - PV 'A': Missing\n  - PV 'B': Wrong max length 8, 4 expected\n"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Per-variable metrics in Prometheus text format.
+//!
+//! Metrics are gathered with atomics only, so there is no additional locking in processing path.
+
+use crate::{
+    dynamic::Conversion,
+    registry::shared,
+    variable::{Status, Variable},
+};
+use lazy_static::lazy_static;
+use std::{
+    fmt::Write as _,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::Instant,
+};
+use tiny_http::{Header, Response};
+
+lazy_static! {
+    static ref START: Instant = Instant::now();
+}
+
+/// Nanoseconds since [`START`], never zero.
+fn now() -> u64 {
+    START.elapsed().as_nanos() as u64 + 1
+}
+
+/// Upper bounds of histogram buckets in seconds.
+const BUCKETS: [f64; 8] = [1e-6, 1e-5, 1e-4, 1e-3, 1e-2, 1e-1, 1.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_ns: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, ns: u64) {
+        let secs = ns as f64 * 1e-9;
+        if let Some(i) = BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_ns.fetch_add(ns, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, text: &mut String, metric: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            writeln!(
+                text,
+                "{metric}_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+            )
+            .unwrap();
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        writeln!(text, "{metric}_bucket{{{labels},le=\"+Inf\"}} {count}").unwrap();
+        let sum = self.sum_ns.load(Ordering::Relaxed) as f64 * 1e-9;
+        writeln!(text, "{metric}_sum{{{labels}}} {sum}").unwrap();
+        writeln!(text, "{metric}_count{{{labels}}} {count}").unwrap();
+    }
+}
+
+/// Metrics of a single variable.
+#[derive(Default)]
+pub(crate) struct VarMetrics {
+    processed: AtomicU64,
+    rejected: AtomicU64,
+    /// Time of the pending request, zero if there is none.
+    requested_at: AtomicU64,
+    /// Time of the processing begin, zero if it is committed already.
+    proc_begin_at: AtomicU64,
+    /// Time from processing begin to commit.
+    processing: Histogram,
+    latency: Histogram,
+    /// Bits of `f64`, [`NO_VALUE`] if not committed yet.
+    value: AtomicU64,
+}
+
+/// NaN with payload that is never produced by conversion.
+const NO_VALUE: u64 = 0x7ff8_dead_beef_0000;
+
+impl VarMetrics {
+    pub fn new() -> Self {
+        Self {
+            value: AtomicU64::new(NO_VALUE),
+            ..Self::default()
+        }
+    }
+
+    pub fn on_request(&self) {
+        self.requested_at.store(now(), Ordering::Relaxed);
+    }
+    pub fn on_proc_begin(&self) {
+        self.proc_begin_at.store(now(), Ordering::Relaxed);
+    }
+    /// *Variable must be locked.*
+    pub unsafe fn on_commit(&self, var: &Variable, status: Status<'_>) {
+        if status.is_err() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        let now = now();
+        let requested_at = self.requested_at.swap(0, Ordering::Relaxed);
+        if requested_at != 0 {
+            self.latency.observe(now - requested_at);
+        }
+        let proc_begin_at = self.proc_begin_at.swap(0, Ordering::Relaxed);
+        if proc_begin_at != 0 {
+            self.processing.observe(now - proc_begin_at);
+        }
+        if var.info().max_len == 0 {
+            if let Ok(value) = var.load_dyn().get_scalar::<f64>(Conversion::LOSSY) {
+                self.value.store(value.to_bits(), Ordering::Relaxed);
+            }
+        }
+    }
+    pub fn on_proc_end(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render metrics of all variables in Prometheus text format.
+pub fn render() -> String {
+    let mut names = shared().names();
+    names.sort();
+    let mut text = String::new();
+    let header = |text: &mut String, metric: &str, type_: &str, help: &str| {
+        writeln!(text, "# HELP {metric} {help}").unwrap();
+        writeln!(text, "# TYPE {metric} {type_}").unwrap();
+    };
+    let for_each = |text: &mut String, f: &dyn Fn(&mut String, &str, &VarMetrics)| {
+        for name in &names {
+            let labels = format!("name=\"{}\"", escape(name));
+            let _ = shared().with_var(name, |var| f(text, &labels, &var.state().metrics));
+        }
+    };
+
+    let metric = "ferrite_processed_total";
+    header(
+        &mut text,
+        metric,
+        "counter",
+        "Number of completed processing cycles.",
+    );
+    for_each(&mut text, &|text, labels, m| {
+        writeln!(
+            text,
+            "{metric}{{{labels}}} {}",
+            m.processed.load(Ordering::Relaxed)
+        )
+        .unwrap();
+    });
+    let metric = "ferrite_rejected_total";
+    header(
+        &mut text,
+        metric,
+        "counter",
+        "Number of rejected processing cycles.",
+    );
+    for_each(&mut text, &|text, labels, m| {
+        writeln!(
+            text,
+            "{metric}{{{labels}}} {}",
+            m.rejected.load(Ordering::Relaxed)
+        )
+        .unwrap();
+    });
+    let metric = "ferrite_processing_seconds";
+    header(
+        &mut text,
+        metric,
+        "histogram",
+        "Time from processing begin to commit.",
+    );
+    for_each(&mut text, &|text, labels, m| {
+        m.processing.render(text, metric, labels)
+    });
+    let metric = "ferrite_request_latency_seconds";
+    header(
+        &mut text,
+        metric,
+        "histogram",
+        "Time from request to commit.",
+    );
+    for_each(&mut text, &|text, labels, m| {
+        m.latency.render(text, metric, labels)
+    });
+    let metric = "ferrite_value";
+    header(
+        &mut text,
+        metric,
+        "gauge",
+        "Last committed value of scalar variable.",
+    );
+    for_each(&mut text, &|text, labels, m| {
+        let bits = m.value.load(Ordering::Relaxed);
+        if bits != NO_VALUE {
+            writeln!(text, "{metric}{{{labels}}} {}", f64::from_bits(bits)).unwrap();
+        }
+    });
+    text
+}
+
+/// HTTP server exposing metrics.
+pub struct Exporter {
+    inner: tiny_http::Server,
+}
+
+impl Exporter {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let inner =
+            tiny_http::Server::http(addr).map_err(|err| io::Error::other(err.to_string()))?;
+        Ok(Self { inner })
+    }
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.server_addr().to_ip()
+    }
+
+    /// Serve metrics on any path, never returns.
+    pub fn run(self) {
+        let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+        for request in self.inner.incoming_requests() {
+            let response = Response::from_string(render()).with_header(content_type.clone());
+            if let Err(err) = request.respond(response) {
+                log::warn!("Metrics exporter: {}", err);
+            }
+        }
+    }
+    /// Run exporter in a separate thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name("metrics".into())
+            .spawn(move || self.run())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        downcast::Downcast,
+        dynamic::{DynValue, DynVariable},
+        testing::FakeVar,
+        variable::{Direction, Type},
+    };
+    use futures::executor::block_on;
+
+    #[test]
+    fn histogram() {
+        let histogram = Histogram::default();
+        histogram.observe(500);
+        histogram.observe(2_000_000);
+        histogram.observe(20_000_000_000);
+        let mut text = String::new();
+        histogram.render(&mut text, "m", "name=\"A\"");
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "m_bucket{name=\"A\",le=\"0.000001\"} 1",
+                "m_bucket{name=\"A\",le=\"0.00001\"} 1",
+                "m_bucket{name=\"A\",le=\"0.0001\"} 1",
+                "m_bucket{name=\"A\",le=\"0.001\"} 1",
+                "m_bucket{name=\"A\",le=\"0.01\"} 2",
+                "m_bucket{name=\"A\",le=\"0.1\"} 2",
+                "m_bucket{name=\"A\",le=\"1\"} 2",
+                "m_bucket{name=\"A\",le=\"10\"} 2",
+                "m_bucket{name=\"A\",le=\"+Inf\"} 3",
+                "m_sum{name=\"A\"} 20.0020005",
+                "m_count{name=\"A\"} 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape(r#"A"B\C"#), r#"A\"B\\C"#);
+        assert_eq!(escape("A\nB"), r"A\nB");
+    }
+
+    #[test]
+    fn variable_metrics() {
+        let fake = FakeVar::new("METRICS:VALUE", Type::I32, 0, Direction::Output)
+            .auto_process()
+            .leak();
+        shared().add_variable(fake.var());
+        let array = FakeVar::new("METRICS:ARRAY", Type::I32, 4, Direction::Output).leak();
+        shared().add_variable(array.var());
+        let mut var = Downcast::<DynVariable>::downcast(fake.var()).unwrap();
+
+        let text = render();
+        assert!(text.contains("ferrite_processed_total{name=\"METRICS:VALUE\"} 0\n"));
+        assert!(!text.contains("ferrite_value{name=\"METRICS:VALUE\"}"));
+
+        block_on(async {
+            let guard = var.request().await;
+            guard
+                .write(&DynValue::I32(-3), Conversion::LOSSLESS)
+                .unwrap()
+                .await;
+            var.request().await.reject("Failed").await;
+        });
+        let text = render();
+        for line in [
+            "ferrite_processed_total{name=\"METRICS:VALUE\"} 2",
+            "ferrite_rejected_total{name=\"METRICS:VALUE\"} 1",
+            "ferrite_processing_seconds_count{name=\"METRICS:VALUE\"} 2",
+            "ferrite_request_latency_seconds_count{name=\"METRICS:VALUE\"} 2",
+            "ferrite_value{name=\"METRICS:VALUE\"} -3",
+            "ferrite_processed_total{name=\"METRICS:ARRAY\"} 0",
+        ] {
+            assert!(text.contains(&format!("{}\n", line)), "{}", line);
+        }
+        assert!(!text.contains("ferrite_value{name=\"METRICS:ARRAY\"}"));
+    }
+}
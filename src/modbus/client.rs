@@ -0,0 +1,229 @@
+use derive_more::{Display, Error, From};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Modbus function codes.
+pub(super) mod function {
+    pub const READ_COILS: u8 = 0x01;
+    pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+    pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+    pub const READ_INPUT_REGISTERS: u8 = 0x04;
+    pub const WRITE_SINGLE_COIL: u8 = 0x05;
+    pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+    pub const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+    pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+}
+
+/// Modbus exception codes.
+pub(super) mod exception {
+    pub const ILLEGAL_FUNCTION: u8 = 0x01;
+    pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+    pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum Error {
+    Io(io::Error),
+    #[display(fmt = "Exception code {}", "_0")]
+    #[from(ignore)]
+    Exception(#[error(not(source))] u8),
+    #[display(fmt = "Protocol error: {}", "_0")]
+    #[from(ignore)]
+    Protocol(#[error(not(source))] &'static str),
+}
+
+impl Error {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Io(err) if matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock))
+    }
+}
+
+/// Blocking Modbus TCP client.
+pub struct Client {
+    stream: TcpStream,
+    unit: u8,
+    transaction: u16,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A, unit: u8, timeout: Duration) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address"))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            unit,
+            transaction: 0,
+        })
+    }
+
+    /// Send request PDU and receive response PDU.
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>, Error> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let mut frame = Vec::with_capacity(7 + pdu.len());
+        frame.extend(self.transaction.to_be_bytes());
+        frame.extend(0u16.to_be_bytes());
+        frame.extend((pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(self.unit);
+        frame.extend(pdu);
+        self.stream.write_all(&frame)?;
+
+        let mut header = [0; 7];
+        self.stream.read_exact(&mut header)?;
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if len < 2 {
+            return Err(Error::Protocol("Invalid length"));
+        }
+        let mut response = vec![0; len - 1];
+        self.stream.read_exact(&mut response)?;
+        if header[0..2] != self.transaction.to_be_bytes() {
+            return Err(Error::Protocol("Transaction id mismatch"));
+        }
+        if response[0] == pdu[0] | 0x80 {
+            return Err(Error::Exception(*response.get(1).unwrap_or(&0)));
+        }
+        if response[0] != pdu[0] {
+            return Err(Error::Protocol("Function code mismatch"));
+        }
+        Ok(response)
+    }
+
+    fn read(&mut self, function: u8, address: u16, count: u16) -> Result<Vec<u8>, Error> {
+        let mut pdu = vec![function];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(count.to_be_bytes());
+        let response = self.transact(&pdu)?;
+        match response.get(1) {
+            Some(&len) if response.len() == len as usize + 2 => Ok(response[2..].to_vec()),
+            _ => Err(Error::Protocol("Invalid byte count")),
+        }
+    }
+    fn read_bits(&mut self, function: u8, address: u16, count: u16) -> Result<Vec<bool>, Error> {
+        let bytes = self.read(function, address, count)?;
+        if bytes.len() * 8 < count as usize {
+            return Err(Error::Protocol("Too few bits"));
+        }
+        Ok((0..count as usize)
+            .map(|i| bytes[i / 8] & (1 << (i % 8)) != 0)
+            .collect())
+    }
+    fn read_words(&mut self, function: u8, address: u16, count: u16) -> Result<Vec<u16>, Error> {
+        let bytes = self.read(function, address, count)?;
+        if bytes.len() != 2 * count as usize {
+            return Err(Error::Protocol("Wrong number of registers"));
+        }
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]))
+            .collect())
+    }
+
+    pub fn read_coils(&mut self, address: u16, count: u16) -> Result<Vec<bool>, Error> {
+        self.read_bits(function::READ_COILS, address, count)
+    }
+    pub fn read_discrete_inputs(&mut self, address: u16, count: u16) -> Result<Vec<bool>, Error> {
+        self.read_bits(function::READ_DISCRETE_INPUTS, address, count)
+    }
+    pub fn read_holding_registers(&mut self, address: u16, count: u16) -> Result<Vec<u16>, Error> {
+        self.read_words(function::READ_HOLDING_REGISTERS, address, count)
+    }
+    pub fn read_input_registers(&mut self, address: u16, count: u16) -> Result<Vec<u16>, Error> {
+        self.read_words(function::READ_INPUT_REGISTERS, address, count)
+    }
+
+    pub fn write_single_coil(&mut self, address: u16, value: bool) -> Result<(), Error> {
+        let mut pdu = vec![function::WRITE_SINGLE_COIL];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(if value { [0xff, 0x00] } else { [0x00, 0x00] });
+        self.transact(&pdu)?;
+        Ok(())
+    }
+    pub fn write_single_register(&mut self, address: u16, value: u16) -> Result<(), Error> {
+        let mut pdu = vec![function::WRITE_SINGLE_REGISTER];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(value.to_be_bytes());
+        self.transact(&pdu)?;
+        Ok(())
+    }
+    /// At most 123 registers can be written at once.
+    pub fn write_multiple_registers(&mut self, address: u16, values: &[u16]) -> Result<(), Error> {
+        if values.len() > 123 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Too many registers").into());
+        }
+        let mut pdu = vec![function::WRITE_MULTIPLE_REGISTERS];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend((values.len() as u16).to_be_bytes());
+        pdu.push((2 * values.len()) as u8);
+        for value in values {
+            pdu.extend(value.to_be_bytes());
+        }
+        self.transact(&pdu)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::sim::{Memory, Simulator};
+
+    #[test]
+    fn simulator() {
+        let mut memory = Memory::new(300);
+        memory.discrete_inputs[2] = true;
+        memory.input_registers[7] = 0xabcd;
+        let sim = Simulator::bind("127.0.0.1:0", memory).unwrap();
+        let addr = sim.local_addr().unwrap();
+        let memory = sim.memory();
+        sim.spawn();
+
+        let mut client = Client::connect(addr, 1, Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            client.read_discrete_inputs(0, 3).unwrap(),
+            [false, false, true]
+        );
+        assert_eq!(client.read_input_registers(7, 1).unwrap(), [0xabcd]);
+
+        client.write_single_coil(10, true).unwrap();
+        assert_eq!(client.read_coils(9, 2).unwrap(), [false, true]);
+        client.write_single_register(0, 7).unwrap();
+        client.write_multiple_registers(1, &[8, 9]).unwrap();
+        assert_eq!(client.read_holding_registers(0, 3).unwrap(), [7, 8, 9]);
+        assert_eq!(memory.lock().unwrap().holding_registers[..3], [7, 8, 9]);
+
+        let words = client.read_holding_registers(0, 125).unwrap();
+        assert_eq!(words.len(), 125);
+        assert!(matches!(
+            client.read_holding_registers(0, 126),
+            Err(Error::Exception(exception::ILLEGAL_DATA_VALUE))
+        ));
+        assert!(matches!(
+            client.read_coils(299, 2),
+            Err(Error::Exception(exception::ILLEGAL_DATA_ADDRESS))
+        ));
+        client.write_multiple_registers(0, &[1; 123]).unwrap();
+        assert!(matches!(
+            client.write_multiple_registers(0, &[1; 124]),
+            Err(Error::Io(_))
+        ));
+        // Connection is still usable after exceptions.
+        assert_eq!(client.read_holding_registers(122, 2).unwrap(), [1, 0]);
+    }
+
+    #[test]
+    fn timeout() {
+        // Server that accepts connection but never responds.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client =
+            Client::connect(listener.local_addr().unwrap(), 1, Duration::from_millis(20)).unwrap();
+        assert!(client.read_coils(0, 1).unwrap_err().is_timeout());
+    }
+}
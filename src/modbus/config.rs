@@ -0,0 +1,351 @@
+use derive_more::{Display, Error};
+use std::{str::FromStr, time::Duration};
+
+/// Modbus data table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Table {
+    Coil,
+    DiscreteInput,
+    InputRegister,
+    HoldingRegister,
+}
+
+impl Table {
+    /// Whether the table can be written by client.
+    pub fn is_writable(self) -> bool {
+        matches!(self, Table::Coil | Table::HoldingRegister)
+    }
+    pub fn is_bits(self) -> bool {
+        matches!(self, Table::Coil | Table::DiscreteInput)
+    }
+}
+
+/// Type of value stored in registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType {
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl DataType {
+    /// Number of 16-bit registers.
+    pub fn registers(self) -> u16 {
+        match self {
+            DataType::U16 | DataType::I16 => 1,
+            DataType::U32 | DataType::I32 | DataType::F32 => 2,
+            DataType::U64 | DataType::I64 | DataType::F64 => 4,
+        }
+    }
+}
+
+/// Order of bytes in register or of registers in value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    #[default]
+    Big,
+    Little,
+}
+
+/// Binding of variable to Modbus data.
+///
+/// Numeric values are converted as `value = raw * scale + offset`.
+#[derive(Clone, Debug)]
+pub struct Point {
+    pub name: String,
+    pub table: Table,
+    pub address: u16,
+    /// Ignored for coils and discrete inputs.
+    pub data_type: DataType,
+    pub byte_order: Order,
+    pub word_order: Order,
+    pub scale: f64,
+    pub offset: f64,
+    /// Polling period of input variables.
+    pub period: Duration,
+}
+
+impl Point {
+    pub fn new(name: &str, table: Table, address: u16, data_type: DataType) -> Self {
+        Self {
+            name: name.into(),
+            table,
+            address,
+            data_type,
+            byte_order: Order::Big,
+            word_order: Order::Big,
+            scale: 1.0,
+            offset: 0.0,
+            period: Duration::from_secs(1),
+        }
+    }
+
+    /// Number of coils or registers.
+    pub fn count(&self) -> u16 {
+        if self.table.is_bits() {
+            1
+        } else {
+            self.data_type.registers()
+        }
+    }
+
+    pub fn decode(&self, words: &[u16]) -> f64 {
+        let mut bytes = Vec::with_capacity(2 * words.len());
+        let mut push = |word: &u16| match self.byte_order {
+            Order::Big => bytes.extend(word.to_be_bytes()),
+            Order::Little => bytes.extend(word.to_le_bytes()),
+        };
+        match self.word_order {
+            Order::Big => words.iter().for_each(&mut push),
+            Order::Little => words.iter().rev().for_each(&mut push),
+        }
+        let raw = match self.data_type {
+            DataType::U16 => u16::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::I16 => i16::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::U32 => u32::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::I32 => i32::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::U64 => u64::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::I64 => i64::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::F32 => f32::from_be_bytes(bytes[..].try_into().unwrap()) as f64,
+            DataType::F64 => f64::from_be_bytes(bytes[..].try_into().unwrap()),
+        };
+        raw * self.scale + self.offset
+    }
+
+    /// *Integers are rounded and saturated.*
+    pub fn encode(&self, value: f64) -> Vec<u16> {
+        let raw = (value - self.offset) / self.scale;
+        let bytes = match self.data_type {
+            DataType::U16 => (raw.round() as u16).to_be_bytes().to_vec(),
+            DataType::I16 => (raw.round() as i16).to_be_bytes().to_vec(),
+            DataType::U32 => (raw.round() as u32).to_be_bytes().to_vec(),
+            DataType::I32 => (raw.round() as i32).to_be_bytes().to_vec(),
+            DataType::U64 => (raw.round() as u64).to_be_bytes().to_vec(),
+            DataType::I64 => (raw.round() as i64).to_be_bytes().to_vec(),
+            DataType::F32 => (raw as f32).to_be_bytes().to_vec(),
+            DataType::F64 => raw.to_be_bytes().to_vec(),
+        };
+        let mut words: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| match self.byte_order {
+                Order::Big => u16::from_be_bytes([pair[0], pair[1]]),
+                Order::Little => u16::from_le_bytes([pair[0], pair[1]]),
+            })
+            .collect();
+        if self.word_order == Order::Little {
+            words.reverse();
+        }
+        words
+    }
+}
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "Line {}: {}", "line", "message")]
+pub struct ParseError {
+    pub line: usize,
+    #[error(not(source))]
+    pub message: String,
+}
+
+fn parse_value<T: FromStr>(what: &str, text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("Invalid {} '{}'", what, text))
+}
+
+fn parse_point(line: &str) -> Result<Point, String> {
+    let mut tokens = line.split_whitespace();
+    let mut next = |what: &str| tokens.next().ok_or_else(|| format!("Missing {}", what));
+    let name = next("name")?;
+    let table = match next("table")? {
+        "coil" => Table::Coil,
+        "discrete" => Table::DiscreteInput,
+        "input" => Table::InputRegister,
+        "holding" => Table::HoldingRegister,
+        other => return Err(format!("Unknown table '{}'", other)),
+    };
+    let address = parse_value("address", next("address")?)?;
+    let data_type = if table.is_bits() {
+        DataType::U16
+    } else {
+        match next("data type")? {
+            "u16" => DataType::U16,
+            "i16" => DataType::I16,
+            "u32" => DataType::U32,
+            "i32" => DataType::I32,
+            "u64" => DataType::U64,
+            "i64" => DataType::I64,
+            "f32" => DataType::F32,
+            "f64" => DataType::F64,
+            other => return Err(format!("Unknown data type '{}'", other)),
+        }
+    };
+    let mut point = Point::new(name, table, address, data_type);
+    for option in tokens {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| format!("Option 'key=value' expected, got '{}'", option))?;
+        let order = |value: &str| match value {
+            "big" => Ok(Order::Big),
+            "little" => Ok(Order::Little),
+            other => Err(format!("Unknown order '{}'", other)),
+        };
+        match key {
+            "byte_order" => point.byte_order = order(value)?,
+            "word_order" => point.word_order = order(value)?,
+            "scale" => point.scale = parse_value("scale", value)?,
+            "offset" => point.offset = parse_value("offset", value)?,
+            "period_ms" => point.period = Duration::from_millis(parse_value("period", value)?),
+            other => return Err(format!("Unknown option '{}'", other)),
+        }
+    }
+    Ok(point)
+}
+
+/// Parse point table.
+///
+/// Each line is `NAME TABLE ADDRESS [TYPE] [OPTION=VALUE]...`, where:
+/// + `TABLE` is one of `coil`, `discrete`, `input`, `holding`,
+/// + `TYPE` (for registers only) is one of `u16`, `i16`, `u32`, `i32`, `u64`, `i64`, `f32`, `f64`,
+/// + options are `byte_order` and `word_order` (`big` or `little`), `scale`, `offset` and `period_ms`.
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_points(text: &str) -> Result<Vec<Point>, ParseError> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| parse_point(text).map_err(|message| ParseError { line, message }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(data_type: DataType, byte_order: Order, word_order: Order) -> Point {
+        Point {
+            byte_order,
+            word_order,
+            ..Point::new("P", Table::HoldingRegister, 0, data_type)
+        }
+    }
+
+    #[test]
+    fn orders() {
+        let value = 0x1234_5678 as f64;
+        for (byte_order, word_order, words) in [
+            (Order::Big, Order::Big, [0x1234, 0x5678]),
+            (Order::Big, Order::Little, [0x5678, 0x1234]),
+            (Order::Little, Order::Big, [0x3412, 0x7856]),
+            (Order::Little, Order::Little, [0x7856, 0x3412]),
+        ] {
+            let point = point(DataType::U32, byte_order, word_order);
+            assert_eq!(point.encode(value), words);
+            assert_eq!(point.decode(&words), value);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for (data_type, value) in [
+            (DataType::U16, 65535.0),
+            (DataType::I16, -32768.0),
+            (DataType::U32, 4e9),
+            (DataType::I32, -2e9),
+            (DataType::U64, 1e18),
+            (DataType::I64, -1e18),
+            (DataType::F32, 0.5),
+            (DataType::F64, 1.0 / 3.0),
+        ] {
+            for word_order in [Order::Big, Order::Little] {
+                let point = point(data_type, Order::Big, word_order);
+                let words = point.encode(value);
+                assert_eq!(words.len(), data_type.registers() as usize);
+                assert_eq!(point.decode(&words), value, "{:?}", data_type);
+            }
+        }
+    }
+
+    #[test]
+    fn scaling() {
+        let point = Point {
+            scale: 0.1,
+            offset: -10.0,
+            ..point(DataType::I16, Order::Big, Order::Big)
+        };
+        assert_eq!(point.encode(2.0), [120]);
+        assert_eq!(point.decode(&[120]), 2.0);
+        assert_eq!(point.decode(&[0xffff]), -10.1);
+    }
+
+    #[test]
+    fn rounding() {
+        let point = point(DataType::U16, Order::Big, Order::Big);
+        assert_eq!(point.encode(2.6), [3]);
+        assert_eq!(point.encode(-5.0), [0]);
+        assert_eq!(point.encode(1e10), [u16::MAX]);
+        assert_eq!(point.encode(f64::NAN), [0]);
+    }
+
+    #[test]
+    fn parse() {
+        let points = parse_points(
+            "# Device table\n\
+             \n\
+             TEMP input 10 f32 word_order=little scale=0.5 offset=1 period_ms=200\n\
+             PUMP coil 3 period_ms=50\n\
+             SET holding 0x10 i16\n",
+        );
+        assert_eq!(points.unwrap_err().line, 5);
+
+        let points = parse_points(
+            "TEMP input 10 f32 word_order=little scale=0.5 offset=1 period_ms=200\n\
+             # Coils have no data type\n\
+             PUMP coil 3 period_ms=50",
+        )
+        .unwrap();
+        assert_eq!(points.len(), 2);
+        let temp = &points[0];
+        assert_eq!(
+            (temp.name.as_str(), temp.table, temp.address, temp.data_type),
+            ("TEMP", Table::InputRegister, 10, DataType::F32)
+        );
+        assert_eq!(
+            (temp.byte_order, temp.word_order),
+            (Order::Big, Order::Little)
+        );
+        assert_eq!((temp.scale, temp.offset), (0.5, 1.0));
+        assert_eq!(temp.period, Duration::from_millis(200));
+        assert_eq!(points[1].table, Table::Coil);
+        assert_eq!(points[1].count(), 1);
+        assert_eq!(points[1].period, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, message) in [
+            ("A", "Missing table"),
+            ("A input", "Missing address"),
+            ("A input 1", "Missing data type"),
+            ("A register 1 u16", "Unknown table 'register'"),
+            ("A input 70000 u16", "Invalid address '70000'"),
+            ("A input 1 u8", "Unknown data type 'u8'"),
+            (
+                "A input 1 u16 scale",
+                "Option 'key=value' expected, got 'scale'",
+            ),
+            ("A input 1 u16 scale=x", "Invalid scale 'x'"),
+            ("A input 1 u16 byte_order=middle", "Unknown order 'middle'"),
+            ("A input 1 u16 speed=1", "Unknown option 'speed'"),
+        ] {
+            let error = parse_points(&format!("\n{}", text)).unwrap_err();
+            assert_eq!(error.line, 2);
+            assert_eq!(error.message, message);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Modbus TCP client driver.
+//!
+//! Binds variables to coils and registers according to a table of [`Point`]s.
+//! Input records (e.g. `ai`) are polled periodically, output records (e.g. `ao`) are written when processed.
+//! Communication errors reject processing with `COMM` message, so that the record goes into alarm.
+
+mod client;
+mod config;
+pub mod sim;
+
+pub use client::{Client, Error};
+pub use config::{parse_points, DataType, Order, ParseError, Point, Table};
+
+use crate::{
+    driver::{self, comm_message, Polled},
+    dynamic::{Conversion, DynValue},
+    registry::GetDowncastError,
+    variable::Direction,
+    Registry,
+};
+use derive_more::{Display, Error, From};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[derive(Debug, Display, Error, From)]
+pub enum DriverError {
+    Var(GetDowncastError),
+    #[display(fmt = "PV '{}': {:?} is read-only", "name", "table")]
+    #[from(ignore)]
+    ReadOnly {
+        name: String,
+        table: Table,
+    },
+    #[display(fmt = "Cannot resolve address '{}'", "_0")]
+    #[from(ignore)]
+    Address(#[error(not(source))] String),
+}
+
+/// Connection that is re-established after communication errors.
+struct Connection {
+    addr: SocketAddr,
+    unit: u8,
+    timeout: Duration,
+    client: Option<Client>,
+}
+
+impl Connection {
+    fn with_client<R, F: FnOnce(&mut Client) -> Result<R, Error>>(
+        &mut self,
+        f: F,
+    ) -> Result<R, Error> {
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => self
+                .client
+                .insert(Client::connect(self.addr, self.unit, self.timeout)?),
+        };
+        let result = f(client);
+        if matches!(result, Err(Error::Io(_)) | Err(Error::Protocol(_))) {
+            self.client = None;
+        }
+        result
+    }
+
+    fn read(&mut self, point: &Point) -> Result<f64, Error> {
+        let (address, count) = (point.address, point.count());
+        self.with_client(|client| match point.table {
+            Table::Coil => Ok(client.read_coils(address, 1)?[0] as u8 as f64),
+            Table::DiscreteInput => Ok(client.read_discrete_inputs(address, 1)?[0] as u8 as f64),
+            Table::InputRegister => Ok(point.decode(&client.read_input_registers(address, count)?)),
+            Table::HoldingRegister => {
+                Ok(point.decode(&client.read_holding_registers(address, count)?))
+            }
+        })
+    }
+    fn write(&mut self, point: &Point, value: f64) -> Result<(), Error> {
+        self.with_client(|client| match point.table {
+            Table::Coil => client.write_single_coil(point.address, value != 0.0),
+            Table::HoldingRegister => {
+                client.write_multiple_registers(point.address, &point.encode(value))
+            }
+            Table::DiscreteInput | Table::InputRegister => unreachable!(),
+        })
+    }
+}
+
+/// Modbus TCP driver configuration.
+pub struct Driver {
+    addr: String,
+    unit: u8,
+    timeout: Duration,
+    points: Vec<Point>,
+}
+
+impl Driver {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.into(),
+            unit: 1,
+            timeout: Duration::from_secs(1),
+            points: Vec::new(),
+        }
+    }
+
+    /// Unit identifier, 1 by default.
+    pub fn unit(mut self, unit: u8) -> Self {
+        self.unit = unit;
+        self
+    }
+    /// Response timeout, 1 second by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    pub fn point(mut self, point: Point) -> Self {
+        self.points.push(point);
+        self
+    }
+    pub fn points<I: IntoIterator<Item = Point>>(mut self, points: I) -> Self {
+        self.points.extend(points);
+        self
+    }
+
+    /// Take variables of all points from `registry` and start polling and writing in separate threads.
+    ///
+    /// Input records must be bound to readable tables, output records only to coils or holding registers.
+    pub fn start(
+        self,
+        registry: &mut Registry,
+    ) -> Result<Vec<thread::JoinHandle<()>>, DriverError> {
+        let addr = driver::resolve(&self.addr, None)
+            .ok_or_else(|| DriverError::Address(self.addr.clone()))?;
+        // Check all points first, so that registry is left intact on error.
+        let mut directions = Vec::new();
+        for point in &self.points {
+            let direction = registry.try_get(&point.name)?.direction();
+            if direction == Direction::Output && !point.table.is_writable() {
+                return Err(DriverError::ReadOnly {
+                    name: registry.full_name(&point.name),
+                    table: point.table,
+                });
+            }
+            directions.push(direction);
+        }
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for (point, direction) in self.points.into_iter().zip(directions) {
+            let name = point.name.clone();
+            match direction {
+                Direction::Output => outputs.push((point, registry.remove_downcast(&name)?)),
+                Direction::Input => inputs.push(Polled {
+                    period: point.period,
+                    var: registry.remove_downcast(&name)?,
+                    source: point,
+                }),
+            }
+        }
+
+        let conn = Arc::new(Mutex::new(Connection {
+            addr,
+            unit: self.unit,
+            timeout: self.timeout,
+            client: None,
+        }));
+        let mut handles = Vec::new();
+        if !inputs.is_empty() {
+            let conn = conn.clone();
+            handles.push(driver::spawn("modbus-poll", move || {
+                driver::poll(inputs, |point, _, _| {
+                    let value = conn.lock().unwrap().read(point).map_err(comm)?;
+                    Ok(Some(DynValue::F64(value)))
+                })
+            }));
+        }
+        for (point, var) in outputs {
+            let conn = conn.clone();
+            handles.push(driver::spawn("modbus-write", move || {
+                driver::write(var, |_, value| {
+                    let value = value
+                        .get_scalar::<f64>(Conversion::LOSSY)
+                        .map_err(|err| err.to_string())?;
+                    conn.lock().unwrap().write(&point, value).map_err(comm)?;
+                    Ok(None)
+                })
+            }));
+        }
+        Ok(handles)
+    }
+}
+
+fn comm(err: Error) -> String {
+    comm_message(&err, err.is_timeout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        driver::wait_for,
+        testing::FakeVar,
+        variable::{Direction, Type},
+    };
+    use sim::{Memory, Simulator};
+    use std::{sync::atomic::Ordering, time::Instant};
+
+    #[test]
+    fn driver() {
+        let sim = Simulator::bind("127.0.0.1:0", Memory::new(10)).unwrap();
+        let addr = sim.local_addr().unwrap().to_string();
+        let memory = sim.memory();
+        memory.lock().unwrap().input_registers[0] = 10;
+        sim.spawn();
+
+        let mut registry = Registry::default();
+        let mut add = |name: &str, var: FakeVar| {
+            let var = var.leak();
+            registry.insert(name.into(), var.var());
+            var
+        };
+        let input = |name| FakeVar::new(name, Type::F64, 0, Direction::Input);
+        let output = |name, value: f64| {
+            FakeVar::new(name, Type::F64, 0, Direction::Output)
+                .scalar(value)
+                .auto_process()
+        };
+        let temp = add("MB:TEMP", input("MB:TEMP").auto_process());
+        let stuck = add("MB:STUCK", input("MB:STUCK"));
+        let missing = add("MB:MISSING", input("MB:MISSING").auto_process());
+        let set = add("MB:SET", output("MB:SET", 21.0));
+        let coil = add("MB:COIL", output("MB:COIL", 1.0));
+        let bad = add("MB:BAD", output("MB:BAD", 1.0));
+
+        let period = Duration::from_millis(10);
+        let points = [
+            Point {
+                scale: 0.5,
+                period,
+                ..Point::new("MB:TEMP", Table::InputRegister, 0, DataType::U16)
+            },
+            Point {
+                period,
+                ..Point::new("MB:STUCK", Table::InputRegister, 1, DataType::U16)
+            },
+            Point {
+                period,
+                ..Point::new("MB:MISSING", Table::InputRegister, 20, DataType::U16)
+            },
+            Point {
+                scale: 0.5,
+                ..Point::new("MB:SET", Table::HoldingRegister, 2, DataType::U16)
+            },
+            Point::new("MB:COIL", Table::Coil, 3, DataType::U16),
+            Point::new("MB:BAD", Table::HoldingRegister, 20, DataType::U16),
+        ];
+
+        // Registry is left intact if any point is invalid.
+        let readonly = Point::new("MB:SET", Table::DiscreteInput, 0, DataType::U16);
+        assert!(matches!(
+            Driver::new(&addr)
+                .points(points.clone())
+                .point(readonly)
+                .start(&mut registry),
+            Err(DriverError::ReadOnly { .. })
+        ));
+        let unknown = Point::new("MB:UNKNOWN", Table::Coil, 0, DataType::U16);
+        assert!(matches!(
+            Driver::new(&addr)
+                .points(points.clone())
+                .point(unknown)
+                .start(&mut registry),
+            Err(DriverError::Var(_))
+        ));
+        assert_eq!(registry.len(), points.len());
+
+        Driver::new(&addr)
+            .points(points)
+            .start(&mut registry)
+            .unwrap();
+        assert!(registry.is_empty());
+        wait_for(|| temp.load() == DynValue::F64(5.0));
+        wait_for(|| !missing.commits.lock().unwrap().is_empty());
+        assert_eq!(
+            missing.commits.lock().unwrap()[0],
+            Err("COMM: Exception code 2".into())
+        );
+
+        set.process();
+        coil.process();
+        bad.process();
+        for var in [set, coil, bad] {
+            wait_for(|| !var.commits.lock().unwrap().is_empty());
+        }
+        assert_eq!(set.commits.lock().unwrap()[0], Ok(()));
+        assert_eq!(coil.commits.lock().unwrap()[0], Ok(()));
+        assert_eq!(
+            bad.commits.lock().unwrap()[0],
+            Err("COMM: Exception code 2".into())
+        );
+        assert_eq!(memory.lock().unwrap().holding_registers[2], 42);
+        assert!(memory.lock().unwrap().coils[3]);
+
+        // Record that is never processed doesn't slow down polling of other inputs.
+        wait_for(|| stuck.requests.load(Ordering::SeqCst) > 0);
+        memory.lock().unwrap().input_registers[0] = 20;
+        wait_for(|| temp.load() == DynValue::F64(10.0));
+        let start = (Instant::now(), temp.commits.lock().unwrap().len());
+        thread::sleep(Duration::from_millis(500));
+        let commits = temp.commits.lock().unwrap().len() - start.1;
+        assert!(
+            commits >= 10,
+            "{} commits in {:?}",
+            commits,
+            start.0.elapsed()
+        );
+        assert!(stuck.commits.lock().unwrap().is_empty());
+        assert_eq!(stuck.requests.load(Ordering::SeqCst), 1);
+    }
+}
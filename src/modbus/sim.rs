@@ -0,0 +1,319 @@
+//! Local Modbus TCP server for testing.
+
+use super::client::{exception, function};
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Data tables of simulated device.
+#[derive(Clone, Debug)]
+pub struct Memory {
+    pub coils: Vec<bool>,
+    pub discrete_inputs: Vec<bool>,
+    pub input_registers: Vec<u16>,
+    pub holding_registers: Vec<u16>,
+}
+
+impl Memory {
+    /// Memory with `size` zeroed entries in each table.
+    pub fn new(size: usize) -> Self {
+        Self {
+            coils: vec![false; size],
+            discrete_inputs: vec![false; size],
+            input_registers: vec![0; size],
+            holding_registers: vec![0; size],
+        }
+    }
+}
+
+/// Simulated Modbus device serving each client in a separate thread.
+pub struct Simulator {
+    listener: TcpListener,
+    memory: Arc<Mutex<Memory>>,
+}
+
+/// Maximum number of coils or discrete inputs read at once.
+const MAX_READ_BITS: usize = 2000;
+/// Maximum number of registers read at once.
+const MAX_READ_REGISTERS: usize = 125;
+/// Maximum number of coils written at once.
+const MAX_WRITE_BITS: usize = 1968;
+/// Maximum number of registers written at once.
+const MAX_WRITE_REGISTERS: usize = 123;
+
+/// Range of entries, number of entries must not exceed `max_count`.
+fn range(
+    address: &[u8],
+    count: &[u8],
+    size: usize,
+    max_count: usize,
+) -> Result<(usize, usize), u8> {
+    let start = u16::from_be_bytes([address[0], address[1]]) as usize;
+    let count = u16::from_be_bytes([count[0], count[1]]) as usize;
+    if count == 0 || count > max_count {
+        Err(exception::ILLEGAL_DATA_VALUE)
+    } else if start + count > size {
+        Err(exception::ILLEGAL_DATA_ADDRESS)
+    } else {
+        Ok((start, start + count))
+    }
+}
+
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0; bits.len().div_ceil(8)];
+    for (i, bit) in bits.iter().enumerate() {
+        bytes[i / 8] |= (*bit as u8) << (i % 8);
+    }
+    bytes
+}
+
+fn handle(memory: &mut Memory, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+    if pdu.len() < 5 {
+        return Err(exception::ILLEGAL_DATA_VALUE);
+    }
+    let (function, address, arg) = (pdu[0], &pdu[1..3], &pdu[3..5]);
+    let mut response = vec![function];
+    match function {
+        function::READ_COILS | function::READ_DISCRETE_INPUTS => {
+            let bits = match function {
+                function::READ_COILS => &memory.coils,
+                _ => &memory.discrete_inputs,
+            };
+            let (start, end) = range(address, arg, bits.len(), MAX_READ_BITS)?;
+            let bytes = pack_bits(&bits[start..end]);
+            response.push(bytes.len() as u8);
+            response.extend(bytes);
+        }
+        function::READ_HOLDING_REGISTERS | function::READ_INPUT_REGISTERS => {
+            let words = match function {
+                function::READ_HOLDING_REGISTERS => &memory.holding_registers,
+                _ => &memory.input_registers,
+            };
+            let (start, end) = range(address, arg, words.len(), MAX_READ_REGISTERS)?;
+            response.push((2 * (end - start)) as u8);
+            for word in &words[start..end] {
+                response.extend(word.to_be_bytes());
+            }
+        }
+        function::WRITE_SINGLE_COIL => {
+            let (start, _) = range(address, &[0, 1], memory.coils.len(), 1)?;
+            memory.coils[start] = match arg {
+                [0xff, 0x00] => true,
+                [0x00, 0x00] => false,
+                _ => return Err(exception::ILLEGAL_DATA_VALUE),
+            };
+            response.extend(&pdu[1..5]);
+        }
+        function::WRITE_SINGLE_REGISTER => {
+            let (start, _) = range(address, &[0, 1], memory.holding_registers.len(), 1)?;
+            memory.holding_registers[start] = u16::from_be_bytes([arg[0], arg[1]]);
+            response.extend(&pdu[1..5]);
+        }
+        function::WRITE_MULTIPLE_COILS => {
+            let (start, end) = range(address, arg, memory.coils.len(), MAX_WRITE_BITS)?;
+            let data = pdu.get(6..).ok_or(exception::ILLEGAL_DATA_VALUE)?;
+            if data.len() * 8 < end - start {
+                return Err(exception::ILLEGAL_DATA_VALUE);
+            }
+            for i in 0..(end - start) {
+                memory.coils[start + i] = data[i / 8] & (1 << (i % 8)) != 0;
+            }
+            response.extend(&pdu[1..5]);
+        }
+        function::WRITE_MULTIPLE_REGISTERS => {
+            let (start, end) = range(
+                address,
+                arg,
+                memory.holding_registers.len(),
+                MAX_WRITE_REGISTERS,
+            )?;
+            let data = pdu.get(6..).ok_or(exception::ILLEGAL_DATA_VALUE)?;
+            if data.len() != 2 * (end - start) {
+                return Err(exception::ILLEGAL_DATA_VALUE);
+            }
+            for (i, word) in data.chunks_exact(2).enumerate() {
+                memory.holding_registers[start + i] = u16::from_be_bytes([word[0], word[1]]);
+            }
+            response.extend(&pdu[1..5]);
+        }
+        _ => return Err(exception::ILLEGAL_FUNCTION),
+    }
+    Ok(response)
+}
+
+fn serve(mut stream: TcpStream, memory: Arc<Mutex<Memory>>) -> io::Result<()> {
+    loop {
+        let mut header = [0; 7];
+        stream.read_exact(&mut header)?;
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let mut pdu = vec![0; len.saturating_sub(1)];
+        stream.read_exact(&mut pdu)?;
+        if pdu.is_empty() {
+            continue;
+        }
+        let response = match handle(&mut memory.lock().unwrap(), &pdu) {
+            Ok(response) => response,
+            Err(code) => vec![pdu[0] | 0x80, code],
+        };
+        let mut frame = header[..4].to_vec();
+        frame.extend((response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend(response);
+        stream.write_all(&frame)?;
+    }
+}
+
+impl Simulator {
+    pub fn bind<A: ToSocketAddrs>(addr: A, memory: Memory) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            memory: Arc::new(Mutex::new(memory)),
+        })
+    }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    /// Shared memory of the device, can be modified while simulator is running.
+    pub fn memory(&self) -> Arc<Mutex<Memory>> {
+        self.memory.clone()
+    }
+
+    /// Accept clients in a separate thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name("modbus-sim".into())
+            .spawn(move || {
+                for stream in self.listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            log::warn!("Modbus simulator: {}", err);
+                            continue;
+                        }
+                    };
+                    let memory = self.memory.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, memory) {
+                            log::debug!("Modbus simulator client disconnected: {}", err);
+                        }
+                    });
+                }
+            })
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(function: u8, address: u16, arg: u16, data: &[u8]) -> Vec<u8> {
+        let mut pdu = vec![function];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(arg.to_be_bytes());
+        pdu.extend(data);
+        pdu
+    }
+
+    #[test]
+    fn registers() {
+        let mut memory = Memory::new(200);
+        memory.holding_registers[1] = 0x1234;
+        assert_eq!(
+            handle(
+                &mut memory,
+                &request(function::READ_HOLDING_REGISTERS, 1, 2, &[])
+            ),
+            Ok(vec![function::READ_HOLDING_REGISTERS, 4, 0x12, 0x34, 0, 0])
+        );
+        let response = handle(
+            &mut memory,
+            &request(function::READ_INPUT_REGISTERS, 0, 125, &[]),
+        )
+        .unwrap();
+        assert_eq!(response[1], 250);
+        assert_eq!(response.len(), 252);
+        assert_eq!(
+            handle(
+                &mut memory,
+                &request(function::READ_INPUT_REGISTERS, 0, 126, &[])
+            ),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+        assert_eq!(
+            handle(
+                &mut memory,
+                &request(function::READ_INPUT_REGISTERS, 199, 2, &[])
+            ),
+            Err(exception::ILLEGAL_DATA_ADDRESS)
+        );
+
+        let write = request(function::WRITE_MULTIPLE_REGISTERS, 5, 2, &[4, 0, 1, 0, 2]);
+        assert_eq!(handle(&mut memory, &write), Ok(write[..5].to_vec()));
+        assert_eq!(memory.holding_registers[5..7], [1, 2]);
+        let write = request(function::WRITE_MULTIPLE_REGISTERS, 5, 2, &[2, 0, 1]);
+        assert_eq!(
+            handle(&mut memory, &write),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+        let write = request(function::WRITE_MULTIPLE_REGISTERS, 0, 124, &[]);
+        assert_eq!(
+            handle(&mut memory, &write),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+    }
+
+    #[test]
+    fn bits() {
+        let mut memory = Memory::new(2000);
+        memory.discrete_inputs[0] = true;
+        memory.discrete_inputs[9] = true;
+        assert_eq!(
+            handle(
+                &mut memory,
+                &request(function::READ_DISCRETE_INPUTS, 0, 10, &[])
+            ),
+            Ok(vec![function::READ_DISCRETE_INPUTS, 2, 0x01, 0x02])
+        );
+        assert_eq!(
+            handle(&mut memory, &request(function::READ_COILS, 0, 2000, &[])).map(|r| r[1]),
+            Ok(250)
+        );
+        assert_eq!(
+            handle(&mut memory, &request(function::READ_COILS, 0, 2001, &[])),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+
+        let write = request(function::WRITE_SINGLE_COIL, 3, 0xff00, &[]);
+        assert_eq!(handle(&mut memory, &write), Ok(write.clone()));
+        assert!(memory.coils[3]);
+        let write = request(function::WRITE_SINGLE_COIL, 3, 0x1234, &[]);
+        assert_eq!(
+            handle(&mut memory, &write),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+        let write = request(function::WRITE_MULTIPLE_COILS, 8, 3, &[1, 0b101]);
+        assert_eq!(handle(&mut memory, &write), Ok(write[..5].to_vec()));
+        assert_eq!(memory.coils[8..11], [true, false, true]);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut memory = Memory::new(10);
+        assert_eq!(
+            handle(&mut memory, &[function::READ_COILS, 0]),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+        assert_eq!(
+            handle(&mut memory, &request(0x2b, 0, 1, &[])),
+            Err(exception::ILLEGAL_FUNCTION)
+        );
+        assert_eq!(
+            handle(&mut memory, &request(function::READ_COILS, 0, 0, &[])),
+            Err(exception::ILLEGAL_DATA_VALUE)
+        );
+    }
+}
@@ -0,0 +1,103 @@
+/// Match `text` against glob `pattern` and return parts of text captured by wildcards.
+///
+/// `*` matches any (possibly empty) sequence of characters, `?` matches any single character.
+/// Earlier `*` wildcards capture as few characters as possible.
+pub(crate) fn glob_captures<'a>(pattern: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let chars: Vec<char> = text.chars().collect();
+    let offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([text.len()])
+        .collect();
+
+    // Character ranges of text matched by wildcards.
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    // Pattern position after the last `*`, index of its span and text position where it ends.
+    let mut star: Option<(usize, usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < chars.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, spans.len(), t));
+                spans.push((t, t));
+                p += 1;
+            }
+            Some('?') => {
+                spans.push((t, t + 1));
+                p += 1;
+                t += 1;
+            }
+            Some(&c) if c == chars[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                // Let the last `*` consume one more character and retry from there.
+                let (star_p, index, end) = star?;
+                star = Some((star_p, index, end + 1));
+                spans.truncate(index + 1);
+                spans[index].1 = end + 1;
+                p = star_p;
+                t = end + 1;
+            }
+        }
+    }
+    while pattern.get(p) == Some(&'*') {
+        spans.push((t, t));
+        p += 1;
+    }
+    if p != pattern.len() {
+        return None;
+    }
+    Some(
+        spans
+            .into_iter()
+            .map(|(begin, end)| &text[offsets[begin]..offsets[end]])
+            .collect(),
+    )
+}
+
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_captures(pattern, text).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("A:B", "A:B"));
+        assert!(!glob_match("A:B", "A:BC"));
+        assert!(glob_match("A:*", "A:B:C"));
+        assert!(glob_match("*:C", "A:B:C"));
+        assert!(!glob_match("*:D", "A:B:C"));
+        assert!(glob_match("A?B", "A:B"));
+        assert!(!glob_match("A?B", "AB"));
+        assert!(glob_match("*a*b*c", "xaybzc"));
+        assert!(!glob_match("*a*b*c", "xaybz"));
+        assert!(glob_match("Ж*", "Жук"));
+    }
+
+    #[test]
+    fn captures() {
+        assert_eq!(glob_captures("CH*:*", "CH12:TEMP").unwrap(), ["12", "TEMP"]);
+        assert_eq!(glob_captures("*:*", "A:B:C").unwrap(), ["A", "B:C"]);
+        assert_eq!(glob_captures("?*?", "abcd").unwrap(), ["a", "bc", "d"]);
+        assert_eq!(glob_captures("a**", "ab").unwrap(), ["", "b"]);
+        assert_eq!(glob_captures("*ü", "aüü").unwrap(), ["aü"]);
+        assert_eq!(glob_captures("x", "y"), None);
+    }
+
+    #[test]
+    fn many_stars() {
+        let pattern = "*a".repeat(20) + "b";
+        let text = "a".repeat(100);
+        assert!(!glob_match(&pattern, &text));
+        assert!(glob_match(&pattern, &(text + "b")));
+    }
+}
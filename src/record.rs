@@ -0,0 +1,261 @@
+//! Wrappers for specific EPICS record types.
+//!
+//! Each wrapper encodes the processing pattern of the record type
+//! and is obtained by downcasting only if the backing record is of that type.
+
+use crate::{
+    downcast::Downcast,
+    typed::{Acquire, Input, Output, Type},
+    Variable,
+};
+use futures::stream::{self, FusedStream, StreamExt};
+use std::ops::Deref;
+
+macro_rules! impl_record {
+    ($name:ident, $inner:ty, [$($rtyp:literal),*]) => {
+        impl $name {
+            /// Record types that can back this variable.
+            pub const RECORD_TYPES: &'static [&'static str] = &[$($rtyp),*];
+        }
+        impl Downcast<$name> for Variable {
+            fn downcast(self) -> Option<$name> {
+                if $name::RECORD_TYPES.contains(&&*self.record_type()) {
+                    Some($name { base: Downcast::<$inner>::downcast(self)? })
+                } else {
+                    None
+                }
+            }
+        }
+        impl Deref for $name {
+            type Target = Variable;
+            fn deref(&self) -> &Variable {
+                &self.base
+            }
+        }
+    };
+    ($name:ident<$T:ident>, $inner:ty, [$($rtyp:literal),*]) => {
+        impl<$T: Type> $name<$T> {
+            /// Record types that can back this variable.
+            pub const RECORD_TYPES: &'static [&'static str] = &[$($rtyp),*];
+        }
+        impl<$T: Type> Downcast<$name<$T>> for Variable {
+            fn downcast(self) -> Option<$name<$T>> {
+                if $name::<$T>::RECORD_TYPES.contains(&&*self.record_type()) {
+                    Some($name { base: Downcast::<$inner>::downcast(self)? })
+                } else {
+                    None
+                }
+            }
+        }
+        impl<$T: Type> Deref for $name<$T> {
+            type Target = Variable;
+            fn deref(&self) -> &Variable {
+                &self.base
+            }
+        }
+    };
+}
+
+macro_rules! publish_record {
+    ($(#[$attr:meta])* $name:ident, $T:ty, $rtyp:literal) => {
+        $(#[$attr])*
+        pub struct $name {
+            base: Output<$T>,
+        }
+        impl $name {
+            /// Publish new value and wait until the record is processed.
+            pub async fn publish(&mut self, value: $T) {
+                self.base.write(value).await
+            }
+            /// Request processing for custom handling of value and status.
+            pub fn request(&mut self) -> Acquire<'_, $T> {
+                self.base.request()
+            }
+        }
+        impl_record!($name, Output<$T>, [$rtyp]);
+    };
+}
+
+macro_rules! write_record {
+    ($(#[$attr:meta])* $name:ident, $T:ty, $rtyp:literal) => {
+        $(#[$attr])*
+        pub struct $name {
+            base: Input<$T>,
+        }
+        impl $name {
+            /// Wait for the next written value.
+            pub async fn next_write(&mut self) -> $T {
+                self.base.wait().await.read().await
+            }
+            /// Wait for processing for custom handling of value and status.
+            pub fn wait(&mut self) -> Acquire<'_, $T> {
+                self.base.wait()
+            }
+            /// Stream of values written to the record.
+            pub fn on_write(self) -> impl FusedStream<Item = $T> {
+                self.base.into_stream()
+            }
+        }
+        impl_record!($name, Input<$T>, [$rtyp]);
+    };
+}
+
+publish_record!(
+    /// Analog input record (`ai`).
+    AiVariable,
+    f64,
+    "ai"
+);
+write_record!(
+    /// Analog output record (`ao`).
+    AoVariable,
+    f64,
+    "ao"
+);
+publish_record!(
+    /// Long input record (`longin`).
+    LonginVariable,
+    i32,
+    "longin"
+);
+write_record!(
+    /// Long output record (`longout`).
+    LongoutVariable,
+    i32,
+    "longout"
+);
+
+/// Binary input record (`bi`).
+///
+/// *Raw value is `u16`, any non-zero value is treated as `true`.*
+pub struct BiVariable {
+    base: Output<u16>,
+}
+
+impl BiVariable {
+    /// Publish new state and wait until the record is processed.
+    pub async fn publish(&mut self, value: bool) {
+        self.base.write(value as u16).await
+    }
+    /// Request processing for custom handling of value and status.
+    pub fn request(&mut self) -> Acquire<'_, u16> {
+        self.base.request()
+    }
+}
+
+impl_record!(BiVariable, Output<u16>, ["bi"]);
+
+/// Binary output record (`bo`).
+///
+/// *Raw value is `u16`, any non-zero value is treated as `true`.*
+pub struct BoVariable {
+    base: Input<u16>,
+}
+
+impl BoVariable {
+    /// Wait for the next written state.
+    pub async fn next_write(&mut self) -> bool {
+        self.base.wait().await.read().await != 0
+    }
+    /// Wait for processing for custom handling of value and status.
+    pub fn wait(&mut self) -> Acquire<'_, u16> {
+        self.base.wait()
+    }
+    /// Stream of states written to the record.
+    pub fn on_write(self) -> impl FusedStream<Item = bool> {
+        self.base.into_stream().map(|value| value != 0)
+    }
+}
+
+impl_record!(BoVariable, Input<u16>, ["bo"]);
+
+/// Array input record (`waveform` or `aai`).
+pub struct WaveformIn<T: Type> {
+    base: Output<[T]>,
+}
+
+impl<T: Type> WaveformIn<T> {
+    pub fn max_len(&self) -> usize {
+        self.base.max_len()
+    }
+    /// Publish values and wait until the record is processed.
+    ///
+    /// *Slice is truncated to [`Self::max_len`].*
+    pub async fn publish_slice(&mut self, slice: &[T]) {
+        self.base.write_from_slice(slice).await
+    }
+    /// Request processing for custom handling of value and status.
+    pub fn request(&mut self) -> Acquire<'_, [T]> {
+        self.base.request()
+    }
+}
+
+impl_record!(WaveformIn<T>, Output<[T]>, ["waveform", "aai"]);
+
+/// Array output record (`aao`).
+pub struct WaveformOut<T: Type> {
+    base: Input<[T]>,
+}
+
+impl<T: Type> WaveformOut<T> {
+    pub fn max_len(&self) -> usize {
+        self.base.max_len()
+    }
+    /// Wait for the next written values.
+    pub async fn next_write(&mut self) -> Vec<T> {
+        self.base.wait().await.read_into_vec().await
+    }
+    /// Wait for processing for custom handling of value and status.
+    pub fn wait(&mut self) -> Acquire<'_, [T]> {
+        self.base.wait()
+    }
+    /// Stream of values written to the record.
+    pub fn on_write(self) -> impl FusedStream<Item = Vec<T>> {
+        stream::unfold(self, |mut this| async move {
+            Some((this.next_write().await, this))
+        })
+        .fuse()
+    }
+}
+
+impl_record!(WaveformOut<T>, Input<[T]>, ["aao"]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::FakeVar,
+        variable::{Direction, Type as VarType},
+    };
+
+    #[test]
+    fn record_types() {
+        let ai = FakeVar::new("RECORD:AI", VarType::F64, 0, Direction::Input).leak();
+        assert_eq!(ai.var().record_type(), "ai");
+        assert!(Downcast::<AiVariable>::downcast(ai.var()).is_some());
+        assert!(Downcast::<AoVariable>::downcast(ai.var()).is_none());
+        assert!(Downcast::<LonginVariable>::downcast(ai.var()).is_none());
+
+        let waveform = FakeVar::new("RECORD:WF", VarType::U8, 4, Direction::Input)
+            .record_type(b"waveform")
+            .leak();
+        assert!(Downcast::<WaveformIn<u8>>::downcast(waveform.var()).is_some());
+        assert!(Downcast::<WaveformIn<i8>>::downcast(waveform.var()).is_none());
+        assert!(Downcast::<WaveformOut<u8>>::downcast(waveform.var()).is_none());
+
+        // Record type matches but direction doesn't.
+        let bo = FakeVar::new("RECORD:BO", VarType::U16, 0, Direction::Input)
+            .record_type(b"bo")
+            .leak();
+        assert!(Downcast::<BoVariable>::downcast(bo.var()).is_none());
+    }
+
+    #[test]
+    fn invalid_record_type() {
+        let var = FakeVar::new("RECORD:BAD", VarType::F64, 0, Direction::Input)
+            .record_type(b"a\xffi")
+            .leak();
+        assert_eq!(var.var().record_type(), "a\u{fffd}i");
+        assert!(Downcast::<AiVariable>::downcast(var.var()).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use super::{shared, GetDowncastError};
+use crate::dynamic::DynValue;
+use futures::{future::poll_fn, task::AtomicWaker};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    task::Poll,
+    time::{Duration, SystemTime},
+};
+
+/// Value committed by application.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub name: String,
+    pub value: DynValue,
+    /// Error message if processing was rejected.
+    pub status: Result<(), String>,
+    pub time: SystemTime,
+}
+
+/// Latest pending events of subscribed variables.
+#[derive(Default)]
+pub(crate) struct Slot {
+    events: Mutex<HashMap<String, Event>>,
+    cond: Condvar,
+    waker: AtomicWaker,
+    dropped: AtomicU64,
+}
+
+/// Subscribers of a single variable.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    slots: Mutex<Vec<Weak<Slot>>>,
+}
+
+impl Subscribers {
+    fn add(&self, slot: &Arc<Slot>) {
+        self.slots.lock().unwrap().push(Arc::downgrade(slot));
+    }
+
+    /// Send event to all subscribers, `make_event` is called only if there are any.
+    ///
+    /// *Never blocks on slow subscribers: unread event of the same variable is replaced.*
+    pub fn publish<F: FnOnce() -> Event>(&self, make_event: F) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.retain(|slot| slot.strong_count() > 0);
+        if slots.is_empty() {
+            return;
+        }
+        let event = make_event();
+        for slot in slots.iter().filter_map(Weak::upgrade) {
+            let replaced = slot
+                .events
+                .lock()
+                .unwrap()
+                .insert(event.name.clone(), event.clone());
+            if replaced.is_some() {
+                slot.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            slot.cond.notify_all();
+            slot.waker.wake();
+        }
+    }
+}
+
+/// Stream of committed values of a set of variables.
+///
+/// Only the latest unread value of each variable is kept,
+/// so slow consumer never stalls the application.
+#[derive(Default)]
+pub struct Subscription {
+    slot: Arc<Slot>,
+}
+
+impl Subscription {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to variable from [`shared`] registry.
+    pub fn add(&mut self, name: &str) -> Result<(), GetDowncastError> {
+        shared().with_var(name, |var| var.state().subscribers.add(&self.slot))
+    }
+
+    /// Take pending events without waiting.
+    pub fn try_recv(&self) -> Vec<Event> {
+        self.slot
+            .events
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, event)| event)
+            .collect()
+    }
+    /// Wait for pending events and take them.
+    pub async fn recv(&self) -> Vec<Event> {
+        poll_fn(|cx| {
+            self.slot.waker.register(cx.waker());
+            let events = self.try_recv();
+            if events.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(events)
+            }
+        })
+        .await
+    }
+    /// Block current thread until there are pending events or `timeout` elapsed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Vec<Event> {
+        let events = self.slot.events.lock().unwrap();
+        let (mut events, _) = self
+            .slot
+            .cond
+            .wait_timeout_while(events, timeout, |events| events.is_empty())
+            .unwrap();
+        events.drain().map(|(_, event)| event).collect()
+    }
+
+    /// Number of events replaced by newer ones before being read.
+    pub fn dropped(&self) -> u64 {
+        self.slot.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        downcast::Downcast,
+        dynamic::{Conversion, DynVariable},
+        testing::FakeVar,
+        variable::{Direction, Type},
+    };
+    use futures::executor::block_on;
+
+    #[test]
+    fn latest_events() {
+        let fake = FakeVar::new("EVENTS:VALUE", Type::I32, 0, Direction::Output)
+            .auto_process()
+            .leak();
+        shared().add_variable(fake.var());
+        let mut var = Downcast::<DynVariable>::downcast(fake.var()).unwrap();
+        let subscription = shared().subscribe(&["EVENTS:VALUE"]).unwrap();
+        assert!(shared().subscribe(&["EVENTS:NONE"]).is_err());
+
+        block_on(async {
+            for value in [1i32, 2] {
+                let guard = var.request().await;
+                guard
+                    .write(&value.into(), Conversion::LOSSLESS)
+                    .unwrap()
+                    .await;
+            }
+        });
+        let events = subscription.recv_timeout(Duration::from_secs(10));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "EVENTS:VALUE");
+        assert_eq!(events[0].value, DynValue::I32(2));
+        assert_eq!(events[0].status, Ok(()));
+        assert_eq!(subscription.dropped(), 1);
+        assert!(subscription.try_recv().is_empty());
+
+        block_on(async { var.request().await.reject("Failed").await });
+        let events = block_on(subscription.recv());
+        assert_eq!(events[0].status, Err("Failed".into()));
+
+        drop(subscription);
+        block_on(async { var.request().await.accept().await });
+        assert!(fake
+            .var()
+            .state()
+            .subscribers
+            .slots
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,643 @@
+#[cfg(feature = "events")]
+mod events;
+mod shared;
+
+#[cfg(feature = "events")]
+pub use events::{Event, Subscription};
+pub use shared::{shared, Observer, SharedRegistry};
+
+#[cfg(feature = "events")]
+pub(crate) use events::Subscribers;
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics},
+    dynamic::{CoercedVariable, Scalar},
+    manifest::{Manifest, Mismatch, ValidationReport},
+    pattern::{glob_captures, glob_match},
+    variable::Type,
+    Downcast, Info, Variable,
+};
+use derive_more::{Deref, DerefMut, Display, Error};
+use futures::task::{Spawn, SpawnError, SpawnExt};
+use lazy_static::lazy_static;
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    mem,
+    sync::Mutex,
+};
+
+/// Set of variables available to the application.
+///
+/// May be a view of a larger registry, then variable names are relative to [`Self::prefix`].
+///
+/// Variables cannot be [claimed](`SharedRegistry::claim`) while they are in registry,
+/// ones that are left in registry when it is dropped become available for claiming.
+///
+/// Map of variables is read-only, so that they cannot be removed without release.
+#[derive(Deref)]
+pub struct Registry {
+    #[deref]
+    vars: HashMap<String, Variable>,
+    prefix: String,
+    owner: String,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            vars: HashMap::new(),
+            prefix: String::new(),
+            owner: String::from("main"),
+        }
+    }
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        // Remaining variables are not used by application anymore.
+        for name in self.vars.keys() {
+            shared().release_held(&self.full_name(name));
+        }
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+    static ref DIAGNOSTICS: Mutex<Diagnostics> = Mutex::new(Diagnostics::default());
+}
+
+pub(crate) fn add_variable(var: Variable) {
+    if let Err(diag) = try_add_variable(var) {
+        log::error!("{}", diag);
+        DIAGNOSTICS.lock().unwrap().0.push(diag);
+    }
+}
+
+fn try_add_variable(var: Variable) -> Result<(), Diagnostic> {
+    let name = match var.try_name() {
+        Ok(name) => name,
+        Err(_) => {
+            return Err(Diagnostic::InvalidName(
+                String::from_utf8_lossy(var.raw_name()).into_owned(),
+            ))
+        }
+    };
+    if let Err(type_) = var.try_info() {
+        return Err(Diagnostic::UnsupportedType {
+            name: name.into(),
+            type_,
+        });
+    }
+    if let Err(dir) = var.try_direction() {
+        return Err(Diagnostic::UnsupportedDirection {
+            name: name.into(),
+            dir,
+        });
+    }
+    let mut registry = REGISTRY.lock().unwrap();
+    if registry.contains_key(name) || shared().contains(name) {
+        return Err(Diagnostic::DuplicateName(name.into()));
+    }
+    shared().add_variable(unsafe { var.clone_unchecked() });
+    registry.vars.insert(name.into(), var);
+    Ok(())
+}
+
+pub(crate) fn take() -> Registry {
+    let mut ret = Registry::default();
+    mem::swap(&mut *REGISTRY.lock().unwrap(), &mut ret);
+    ret
+}
+
+pub(crate) fn take_diagnostics() -> Diagnostics {
+    mem::take(&mut *DIAGNOSTICS.lock().unwrap())
+}
+
+#[derive(Clone, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GetDowncastErrorKind {
+    #[display(fmt = "Not found")]
+    NotFound,
+    #[display(fmt = "Wrong type, {:?} expected", "_0")]
+    WrongType(Info),
+    #[display(fmt = "Already claimed by '{}'", "_0")]
+    Claimed(String),
+    #[display(fmt = "Still held by application registry")]
+    Held,
+}
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "PV '{}': {}", "name", "kind")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetDowncastError {
+    name: String,
+    kind: GetDowncastErrorKind,
+}
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "There are unused PVs: {:?}", "_0")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CheckEmptyError(#[error(not(source))] pub Vec<String>);
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "Instances miss PVs: {:?}", "_0")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IncompleteInstancesError(#[error(not(source))] pub BTreeMap<String, Vec<String>>);
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "Instance pattern '{}' must contain exactly one wildcard", "_0")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstancePatternError(#[error(not(source))] pub String);
+
+/// Instances of the same device by id, see [`Registry::instances`].
+#[derive(Default, Deref, DerefMut)]
+pub struct Instances(BTreeMap<String, Registry>);
+
+impl Instances {
+    /// Check that each instance contains variables with all `expected` names.
+    pub fn check(&self, expected: &[&str]) -> Result<(), IncompleteInstancesError> {
+        let missing: BTreeMap<_, _> = self
+            .iter()
+            .filter_map(|(id, registry)| {
+                let names: Vec<_> = expected
+                    .iter()
+                    .filter(|name| !registry.contains_key(**name))
+                    .map(|name| registry.full_name(name))
+                    .collect();
+                if names.is_empty() {
+                    None
+                } else {
+                    Some((id.clone(), names))
+                }
+            })
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(IncompleteInstancesError(missing))
+        }
+    }
+
+    /// Spawn `handler` for each instance.
+    pub fn spawn<S, F, R>(self, spawner: &S, mut handler: F) -> Result<(), SpawnError>
+    where
+        S: Spawn + ?Sized,
+        F: FnMut(String, Registry) -> R,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        for (id, registry) in self.0 {
+            spawner.spawn(handler(id, registry))?;
+        }
+        Ok(())
+    }
+}
+
+impl Registry {
+    /// Variable by name, `NotFound` error if there is no such variable.
+    pub(crate) fn try_get(&self, name: &str) -> Result<&Variable, GetDowncastError> {
+        self.get(name).ok_or_else(|| GetDowncastError {
+            name: self.full_name(name),
+            kind: GetDowncastErrorKind::NotFound,
+        })
+    }
+
+    pub fn remove_downcast<V>(&mut self, name: &str) -> Result<V, GetDowncastError>
+    where
+        Variable: Downcast<V>,
+    {
+        log::debug!("take: {}{}", self.prefix, name);
+        let info = self.try_get(name)?.info();
+        let var = unsafe { self[name].clone_unchecked() };
+        match var.downcast() {
+            Some(var) => {
+                shared().take_held(&self.full_name(name), &self.owner)?;
+                self.vars.remove(name);
+                Ok(var)
+            }
+            None => Err(GetDowncastError {
+                name: self.full_name(name),
+                kind: GetDowncastErrorKind::WrongType(info),
+            }),
+        }
+    }
+
+    pub fn remove_downcast_suffix<V>(&mut self, suffix: &str) -> Result<V, GetDowncastError>
+    where
+        Variable: Downcast<V>,
+    {
+        match self.keys().find(|name| is_suffix(name, suffix)).cloned() {
+            Some(name) => self.remove_downcast(&name),
+            None => Err(GetDowncastError {
+                name: format!("{}*{}", self.prefix, suffix),
+                kind: GetDowncastErrorKind::NotFound,
+            }),
+        }
+    }
+
+    /// Take scalar variable of any numeric type and access it as `T`.
+    pub fn remove_coerce<T: Scalar>(
+        &mut self,
+        name: &str,
+    ) -> Result<CoercedVariable<T>, GetDowncastError> {
+        self.remove_downcast(name)
+    }
+
+    /// Remove all variables which names match glob `pattern`.
+    ///
+    /// `*` matches any sequence of characters, `?` matches any single character.
+    pub fn remove_matching(&mut self, pattern: &str) -> Registry {
+        self.remove_filter(|name| glob_match(pattern, name))
+    }
+
+    /// Remove all variables which names match regular expression.
+    #[cfg(feature = "regex")]
+    pub fn remove_matching_regex(&mut self, regex: &regex::Regex) -> Registry {
+        self.remove_filter(|name| regex.is_match(name))
+    }
+
+    /// Remove all variables which names satisfy predicate.
+    pub fn remove_filter<F: FnMut(&str) -> bool>(&mut self, mut f: F) -> Registry {
+        let names: Vec<_> = self.keys().filter(|name| f(name)).cloned().collect();
+        Registry {
+            vars: names
+                .into_iter()
+                .map(|name| {
+                    let var = self.vars.remove(&name).unwrap();
+                    (name, var)
+                })
+                .collect(),
+            prefix: self.prefix.clone(),
+            owner: self.owner.clone(),
+        }
+    }
+
+    /// Remove all variables which names start with `prefix`.
+    ///
+    /// Returned registry contains these variables with `prefix` stripped from their names.
+    pub fn subtree(&mut self, prefix: &str) -> Registry {
+        let mut subtree = self.remove_filter(|name| name.starts_with(prefix));
+        subtree.vars = mem::take(&mut subtree.vars)
+            .into_iter()
+            .map(|(name, var)| (name[prefix.len()..].into(), var))
+            .collect();
+        subtree.prefix = self.full_name(prefix);
+        subtree
+    }
+
+    /// Split variables into instances of the same device.
+    ///
+    /// `pattern` is a prefix of variable names with a single wildcard which captures instance id,
+    /// e.g. `"CH*:"` groups `CH1:TEMP` and `CH1:VOLT` into instance `1`.
+    /// Each instance is a [`subtree`](`Self::subtree`) with the instance prefix stripped.
+    pub fn instances(&mut self, pattern: &str) -> Result<Instances, InstancePatternError> {
+        if pattern.matches(['*', '?']).count() != 1 {
+            return Err(InstancePatternError(pattern.into()));
+        }
+        let full_pattern = format!("{}*", pattern);
+        let mut prefixes = BTreeMap::new();
+        for name in self.keys() {
+            if let Some(captures) = glob_captures(&full_pattern, name) {
+                let suffix = captures[1];
+                let prefix = &name[..(name.len() - suffix.len())];
+                prefixes.insert(captures[0].to_string(), prefix.to_string());
+            }
+        }
+        Ok(Instances(
+            prefixes
+                .into_iter()
+                .map(|(id, prefix)| (id, self.subtree(&prefix)))
+                .collect(),
+        ))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn insert(&mut self, name: String, var: Variable) {
+        self.vars.insert(name, var);
+    }
+    /// Remove variable without taking it, so that it becomes available for [claiming](`SharedRegistry::claim`).
+    ///
+    /// Returns `false` if there is no such variable.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.vars.remove(name) {
+            Some(_) => {
+                shared().release_held(&self.full_name(name));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Iterate over variables of specified type.
+    pub fn iter_type(&self, type_: Type) -> impl Iterator<Item = (&str, &Variable)> {
+        self.iter()
+            .filter(move |(_, var)| var.info().type_ == type_)
+            .map(|(name, var)| (name.as_str(), var))
+    }
+
+    /// Name of the component that owns variables taken from this registry.
+    ///
+    /// Taken variables are marked as claimed by this owner in [`shared`] registry.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+    pub fn set_owner(&mut self, owner: &str) {
+        self.owner = owner.into();
+    }
+
+    /// Prefix that is stripped from variable names.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    pub(crate) fn full_name(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    /// Check that variables in registry exactly match `manifest` and report all differences.
+    pub fn validate(&self, manifest: &Manifest) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::default();
+        for entry in manifest.entries() {
+            let (info, direction) = match self.get(&entry.name) {
+                Some(var) => (var.info(), var.direction()),
+                None => {
+                    report.missing.push(self.full_name(&entry.name));
+                    continue;
+                }
+            };
+            if info.type_ != entry.info.type_ {
+                report.wrong_type.push(Mismatch {
+                    name: self.full_name(&entry.name),
+                    expected: entry.info.type_,
+                    actual: info.type_,
+                });
+            }
+            if info.max_len != entry.info.max_len {
+                report.wrong_len.push(Mismatch {
+                    name: self.full_name(&entry.name),
+                    expected: entry.info.max_len,
+                    actual: info.max_len,
+                });
+            }
+            if direction != entry.direction {
+                report.wrong_direction.push(Mismatch {
+                    name: self.full_name(&entry.name),
+                    expected: entry.direction,
+                    actual: direction,
+                });
+            }
+        }
+        for name in self.keys() {
+            if manifest.get(name).is_none() {
+                report.extra.push(self.full_name(name));
+            }
+        }
+        report.extra.sort();
+
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(report)
+        }
+    }
+
+    pub fn check_empty(&self) -> Result<(), CheckEmptyError> {
+        if !self.is_empty() {
+            Err(CheckEmptyError(
+                self.keys().map(|name| self.full_name(name)).collect(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn is_suffix(text: &str, suffix: &str) -> bool {
+    if text.ends_with(suffix) {
+        if text.len() == suffix.len() {
+            return true;
+        }
+        let c = text.chars().nth(text.len() - suffix.len() - 1).unwrap();
+        if c.is_ascii_punctuation() && c != '_' {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeVar, variable::Direction, TypedVariable};
+
+    fn registry(names: &[&str]) -> Registry {
+        let mut registry = Registry::default();
+        for name in names {
+            let var = FakeVar::new(name, Type::F64, 0, Direction::Input).leak();
+            registry.insert(name.to_string(), var.var());
+        }
+        registry
+    }
+
+    #[test]
+    fn instances() {
+        let mut registry = registry(&["CH1:TEMP", "CH1:VOLT", "CH2:TEMP", "STATUS"]);
+        let instances = registry.instances("CH*:").unwrap();
+        assert_eq!(instances.keys().collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(instances["1"].prefix(), "CH1:");
+        assert!(instances["1"].contains_key("VOLT"));
+        assert_eq!(
+            instances.check(&["TEMP", "VOLT"]).unwrap_err().0["2"],
+            ["CH2:VOLT"]
+        );
+        assert_eq!(registry.keys().collect::<Vec<_>>(), ["STATUS"]);
+    }
+
+    #[test]
+    fn instance_pattern() {
+        let mut registry = registry(&["A1:X"]);
+        assert!(registry.instances("A:").is_err());
+        assert!(registry.instances("A*:*").is_err());
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn subtree() {
+        let mut registry = registry(&["DEV:A", "DEV:B:C", "OTHER"]);
+        let mut dev = registry.subtree("DEV:");
+        assert_eq!(registry.len(), 1);
+        let b = dev.subtree("B:");
+        assert_eq!(b.prefix(), "DEV:B:");
+        assert_eq!(b.full_name("C"), "DEV:B:C");
+        assert!(dev.contains_key("A"));
+    }
+
+    #[test]
+    fn remove_matching() {
+        let mut registry = registry(&["M:A1", "M:A2", "M:B1"]);
+        let mut removed = registry.remove_matching("M:A?");
+        assert_eq!(removed.len(), 2);
+        assert_eq!(registry.keys().collect::<Vec<_>>(), ["M:B1"]);
+        assert!(removed
+            .remove_downcast::<TypedVariable<f64>>("M:A1")
+            .is_ok());
+    }
+
+    /// Add variable both to shared registry and to returned one, as on IOC initialization.
+    fn add_shared(name: &str) -> Registry {
+        let var = FakeVar::new(name, Type::I32, 0, Direction::Output).leak();
+        shared().add_variable(var.var());
+        let mut registry = Registry::default();
+        registry.insert(name.into(), var.var());
+        registry
+    }
+
+    #[test]
+    fn claim_after_take() {
+        let mut registry = add_shared("OWN:TAKEN");
+        registry.set_owner("app");
+        registry
+            .remove_downcast::<TypedVariable<i32>>("OWN:TAKEN")
+            .unwrap();
+        assert_eq!(shared().owner("OWN:TAKEN").unwrap().as_deref(), Some("app"));
+        let err = shared()
+            .claim::<TypedVariable<i32>>("OWN:TAKEN", "other")
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, GetDowncastErrorKind::Claimed(owner) if owner == "app"));
+    }
+
+    #[test]
+    fn claim_before_take() {
+        let mut registry = add_shared("OWN:HELD");
+        let err = shared()
+            .claim::<TypedVariable<i32>>("OWN:HELD", "other")
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, GetDowncastErrorKind::Held));
+        assert_eq!(shared().owner("OWN:HELD").unwrap(), None);
+        registry
+            .remove_downcast::<TypedVariable<i32>>("OWN:HELD")
+            .unwrap();
+    }
+
+    #[test]
+    fn claim_released() {
+        drop(add_shared("OWN:RELEASED"));
+        shared()
+            .claim::<TypedVariable<i32>>("OWN:RELEASED", "other")
+            .unwrap();
+        let err = shared()
+            .claim::<TypedVariable<i32>>("OWN:RELEASED", "another")
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, GetDowncastErrorKind::Claimed(owner) if owner == "other"));
+    }
+
+    #[test]
+    fn claim_removed() {
+        let mut registry = add_shared("OWN:REMOVED");
+        assert!(registry.remove("OWN:REMOVED"));
+        assert!(!registry.remove("OWN:REMOVED"));
+        shared()
+            .claim::<TypedVariable<i32>>("OWN:REMOVED", "other")
+            .unwrap();
+    }
+
+    #[test]
+    fn take_claimed() {
+        let mut registry = add_shared("OWN:CLAIMED");
+        // Pretend that registry doesn't hold the variable anymore.
+        shared().release_held("OWN:CLAIMED");
+        shared()
+            .claim::<TypedVariable<i32>>("OWN:CLAIMED", "other")
+            .unwrap();
+        let err = registry
+            .remove_downcast::<TypedVariable<i32>>("OWN:CLAIMED")
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, GetDowncastErrorKind::Claimed(owner) if owner == "other"));
+        assert!(registry.contains_key("OWN:CLAIMED"));
+    }
+
+    #[test]
+    fn invalid_variables() {
+        let add = |var: FakeVar| try_add_variable(var.leak().var());
+        let var = |name: &str| FakeVar::new(name, Type::U16, 0, Direction::Input);
+        add(var("DIAG:OK")).unwrap();
+        assert_eq!(
+            add(var("DIAG:OK")),
+            Err(Diagnostic::DuplicateName("DIAG:OK".into()))
+        );
+        assert_eq!(
+            add(var("DIAG:X").raw_name(b"DIAG:\xff")),
+            Err(Diagnostic::InvalidName("DIAG:\u{fffd}".into()))
+        );
+        assert_eq!(
+            add(var("DIAG:TYPE").raw_type(100)),
+            Err(Diagnostic::UnsupportedType {
+                name: "DIAG:TYPE".into(),
+                type_: 100
+            })
+        );
+        assert_eq!(
+            add(var("DIAG:DIR").raw_dir(7)),
+            Err(Diagnostic::UnsupportedDirection {
+                name: "DIAG:DIR".into(),
+                dir: 7
+            })
+        );
+        assert!(shared().contains("DIAG:OK"));
+        assert!(!shared().contains("DIAG:TYPE"));
+    }
+
+    #[test]
+    fn validate() {
+        let mut registry = Registry::default();
+        for (name, type_, dir) in [
+            ("VAL:OK", Type::F64, Direction::Input),
+            ("VAL:TYPE", Type::I32, Direction::Input),
+            ("VAL:DIR", Type::F64, Direction::Output),
+            ("VAL:EXTRA", Type::F64, Direction::Input),
+        ] {
+            let var = FakeVar::new(name, type_, 0, dir).leak();
+            registry.insert(name.into(), var.var());
+        }
+        let manifest = Manifest::new()
+            .scalar::<f64>("VAL:OK", Direction::Input)
+            .scalar::<f64>("VAL:TYPE", Direction::Input)
+            .scalar::<f64>("VAL:DIR", Direction::Input)
+            .array::<f64>("VAL:MISSING", 4, Direction::Input);
+        let report = registry.validate(&manifest).unwrap_err();
+        assert_eq!(report.missing, ["VAL:MISSING"]);
+        assert_eq!(report.extra, ["VAL:EXTRA"]);
+        assert_eq!(report.wrong_type[0].name, "VAL:TYPE");
+        assert_eq!(report.wrong_type[0].actual, Type::I32);
+        assert_eq!(report.wrong_direction[0].name, "VAL:DIR");
+        assert!(report.wrong_len.is_empty());
+
+        registry.remove("VAL:EXTRA");
+        let manifest = Manifest::new()
+            .scalar::<f64>("VAL:OK", Direction::Input)
+            .scalar::<i32>("VAL:TYPE", Direction::Input)
+            .scalar::<f64>("VAL:DIR", Direction::Output);
+        registry.validate(&manifest).unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut registry = registry(&["SERDE:A"]);
+        let err = registry
+            .remove_downcast::<TypedVariable<i32>>("SERDE:A")
+            .err()
+            .unwrap();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["name"], "SERDE:A");
+        assert_eq!(
+            json["kind"]["WrongType"],
+            serde_json::json!({"type_": "F64", "max_len": 0})
+        );
+        let parsed: GetDowncastError = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.to_string(), err.to_string());
+    }
+}
@@ -0,0 +1,273 @@
+#[cfg(feature = "events")]
+use super::Subscription;
+use super::{GetDowncastError, GetDowncastErrorKind};
+use crate::{
+    dynamic::DynValue,
+    typed::{Type, Value},
+    variable::Stage,
+    Downcast, TypedVariable, Variable,
+};
+use derive_more::Deref;
+use futures::future::poll_fn;
+use lazy_static::lazy_static;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+    task::{Poll, Waker},
+};
+
+struct Entry {
+    var: Variable,
+    owner: Option<String>,
+    /// Variable is still in some [`Registry`](`super::Registry`) and may be taken from there.
+    held: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    vars: HashMap<String, Entry>,
+    waiters: HashMap<String, Vec<Waker>>,
+}
+
+/// Process-wide registry that keeps all variables and allows runtime lookups.
+///
+/// Unlike [`Registry`](`super::Registry`) lookups are not destructive.
+/// Variables can be either observed by any number of components
+/// or claimed for exclusive use by a single component.
+#[derive(Default)]
+pub struct SharedRegistry {
+    inner: RwLock<Inner>,
+}
+
+lazy_static! {
+    static ref SHARED: SharedRegistry = SharedRegistry::default();
+}
+
+/// Shared registry of the process.
+pub fn shared() -> &'static SharedRegistry {
+    &SHARED
+}
+
+impl SharedRegistry {
+    pub(crate) fn add_variable(&self, var: Variable) {
+        let mut inner = self.inner.write().unwrap();
+        let name = String::from(var.name());
+        if let Some(waiters) = inner.waiters.remove(&name) {
+            waiters.into_iter().for_each(Waker::wake);
+        }
+        inner.vars.insert(
+            name,
+            Entry {
+                var,
+                owner: None,
+                held: true,
+            },
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.inner.read().unwrap().vars.contains_key(name)
+    }
+    pub fn names(&self) -> Vec<String> {
+        self.inner.read().unwrap().vars.keys().cloned().collect()
+    }
+
+    /// Wait until variable with `name` is initialized.
+    pub async fn wait_for(&self, name: &str) {
+        poll_fn(|cx| {
+            let mut inner = self.inner.write().unwrap();
+            if inner.vars.contains_key(name) {
+                Poll::Ready(())
+            } else {
+                let waiters = inner.waiters.entry(name.into()).or_default();
+                if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+                    waiters.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Get observer of the variable without taking it.
+    pub fn get_downcast<V: Value + ?Sized>(
+        &self,
+        name: &str,
+    ) -> Result<Observer<V>, GetDowncastError>
+    where
+        Variable: Downcast<TypedVariable<V>>,
+    {
+        let var = self.with_entry(name, |entry| Ok(unsafe { entry.var.clone_unchecked() }))?;
+        let info = var.info();
+        match var.downcast() {
+            Some(base) => Ok(Observer { base }),
+            None => Err(GetDowncastError {
+                name: name.into(),
+                kind: GetDowncastErrorKind::WrongType(info),
+            }),
+        }
+    }
+
+    /// Take exclusive handle of the variable on behalf of `owner`.
+    ///
+    /// Fails if the variable is already claimed by someone
+    /// or is still held by application [`Registry`](`super::Registry`).
+    pub fn claim<V>(&self, name: &str, owner: &str) -> Result<V, GetDowncastError>
+    where
+        Variable: Downcast<V>,
+    {
+        let mut inner = self.inner.write().unwrap();
+        let entry = match inner.vars.get_mut(name) {
+            Some(entry) => entry,
+            None => {
+                return Err(GetDowncastError {
+                    name: name.into(),
+                    kind: GetDowncastErrorKind::NotFound,
+                })
+            }
+        };
+        if let Some(prev) = &entry.owner {
+            return Err(GetDowncastError {
+                name: name.into(),
+                kind: GetDowncastErrorKind::Claimed(prev.clone()),
+            });
+        }
+        if entry.held {
+            return Err(GetDowncastError {
+                name: name.into(),
+                kind: GetDowncastErrorKind::Held,
+            });
+        }
+        let var = unsafe { entry.var.clone_unchecked() };
+        let info = var.info();
+        match var.downcast() {
+            Some(var) => {
+                log::debug!("{} claimed: {}", owner, name);
+                entry.owner = Some(owner.into());
+                Ok(var)
+            }
+            None => Err(GetDowncastError {
+                name: name.into(),
+                kind: GetDowncastErrorKind::WrongType(info),
+            }),
+        }
+    }
+
+    /// Record that variable is taken from registry by `owner`.
+    ///
+    /// Fails if the variable is already claimed by someone.
+    pub(crate) fn take_held(&self, name: &str, owner: &str) -> Result<(), GetDowncastError> {
+        if let Some(entry) = self.inner.write().unwrap().vars.get_mut(name) {
+            if let Some(prev) = &entry.owner {
+                return Err(GetDowncastError {
+                    name: name.into(),
+                    kind: GetDowncastErrorKind::Claimed(prev.clone()),
+                });
+            }
+            entry.owner = Some(owner.into());
+            entry.held = false;
+        }
+        Ok(())
+    }
+    /// Record that variable is dropped from registry without being taken, so it can be claimed.
+    pub(crate) fn release_held(&self, name: &str) {
+        if let Some(entry) = self.inner.write().unwrap().vars.get_mut(name) {
+            entry.held = false;
+        }
+    }
+
+    /// Subscribe to values committed to variables `names`.
+    #[cfg(feature = "events")]
+    pub fn subscribe(&self, names: &[&str]) -> Result<Subscription, GetDowncastError> {
+        let mut subscription = Subscription::new();
+        for name in names {
+            subscription.add(name)?;
+        }
+        Ok(subscription)
+    }
+
+    /// Owner of the variable, if claimed.
+    pub fn owner(&self, name: &str) -> Result<Option<String>, GetDowncastError> {
+        self.with_entry(name, |entry| Ok(entry.owner.clone()))
+    }
+    /// Owners of all variables by variable names.
+    pub fn owners(&self) -> BTreeMap<String, Option<String>> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .vars
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.owner.clone()))
+            .collect()
+    }
+
+    #[cfg(any(feature = "events", feature = "metrics"))]
+    pub(crate) fn with_var<R, F: FnOnce(&Variable) -> R>(
+        &self,
+        name: &str,
+        f: F,
+    ) -> Result<R, GetDowncastError> {
+        self.with_entry(name, |entry| Ok(f(&entry.var)))
+    }
+    fn with_entry<R, F: FnOnce(&Entry) -> Result<R, GetDowncastError>>(
+        &self,
+        name: &str,
+        f: F,
+    ) -> Result<R, GetDowncastError> {
+        match self.inner.read().unwrap().vars.get(name) {
+            Some(entry) => f(entry),
+            None => Err(GetDowncastError {
+                name: name.into(),
+                kind: GetDowncastErrorKind::NotFound,
+            }),
+        }
+    }
+}
+
+/// Read-only handle to the variable.
+///
+/// There may be any number of observers of the same variable.
+#[derive(Deref)]
+pub struct Observer<V: Value + ?Sized> {
+    base: TypedVariable<V>,
+}
+
+impl<V: Value + ?Sized> Clone for Observer<V> {
+    fn clone(&self) -> Self {
+        Self {
+            base: unsafe { TypedVariable::new_unchecked(self.base.clone_unchecked()) },
+        }
+    }
+}
+
+impl<V: Value + ?Sized> Observer<V> {
+    /// Call `f` while variable is locked.
+    ///
+    /// Returns `None` if variable is being processed because its value may be modified.
+    fn with_locked<R, F: FnOnce(&TypedVariable<V>) -> R>(&self, f: F) -> Option<R> {
+        let _locked = self.base.lock();
+        if self.base.state().stage() == Stage::Processing {
+            None
+        } else {
+            Some(f(&self.base))
+        }
+    }
+}
+
+impl<T: Type> Observer<T> {
+    /// Current value, `None` if variable is being processed right now.
+    pub fn load(&self) -> Option<T> {
+        self.with_locked(|var| unsafe { *var.value_ref() })
+    }
+}
+impl<T: Type> Observer<[T]> {
+    /// Current value, `None` if variable is being processed right now.
+    pub fn load(&self) -> Option<Vec<T>> {
+        self.with_locked(|var| unsafe { var.value_ref() }.as_slice().to_vec())
+    }
+}
+impl Observer<DynValue> {
+    /// Current value, `None` if variable is being processed right now.
+    pub fn load(&self) -> Option<DynValue> {
+        self.with_locked(|var| unsafe { var.load_value() })
+    }
+}
@@ -0,0 +1,437 @@
+use derive_more::{Display, Error, From};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Default port of SCPI raw socket.
+pub const PORT: u16 = 5025;
+
+/// Max number of errors read from error queue at once.
+const MAX_ERRORS: usize = 32;
+
+#[derive(Debug, Display, Error, From)]
+pub enum Error {
+    Io(io::Error),
+    #[display(fmt = "Unexpected reply '{}'", "_0")]
+    #[from(ignore)]
+    Reply(#[error(not(source))] String),
+}
+
+impl Error {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Io(err) if matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock))
+    }
+}
+
+/// Entry of instrument error queue.
+#[derive(Clone, Debug, PartialEq, Eq, Display)]
+#[display(fmt = "{},\"{}\"", "code", "message")]
+pub struct DeviceError {
+    pub code: i32,
+    pub message: String,
+}
+
+/// Response to `*IDN?`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+}
+
+/// Value that can be parsed from SCPI response.
+pub trait FromScpi: Sized {
+    fn from_scpi(text: &str) -> Option<Self>;
+}
+
+/// Value that can be passed as SCPI command parameter.
+pub trait ToScpi {
+    fn to_scpi(&self) -> String;
+}
+
+macro_rules! impl_int {
+    ($($type:ty),*) => {$(
+        impl FromScpi for $type {
+            fn from_scpi(text: &str) -> Option<Self> {
+                let text = text.trim();
+                text.parse().ok().or_else(|| {
+                    // Some instruments return integers in exponential notation.
+                    let x: f64 = text.parse().ok()?;
+                    (x.fract() == 0.0 && x >= <$type>::MIN as f64 && x <= <$type>::MAX as f64)
+                        .then_some(x as $type)
+                })
+            }
+        }
+        impl ToScpi for $type {
+            fn to_scpi(&self) -> String {
+                self.to_string()
+            }
+        }
+    )*};
+}
+impl_int!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+macro_rules! impl_float {
+    ($($type:ty),*) => {$(
+        impl FromScpi for $type {
+            fn from_scpi(text: &str) -> Option<Self> {
+                match text.trim().to_ascii_uppercase().as_str() {
+                    "NAN" | "+NAN" | "-NAN" => Some(<$type>::NAN),
+                    "INF" | "+INF" => Some(<$type>::INFINITY),
+                    "-INF" | "NINF" => Some(<$type>::NEG_INFINITY),
+                    text => text.parse().ok(),
+                }
+            }
+        }
+        impl ToScpi for $type {
+            fn to_scpi(&self) -> String {
+                if self.is_nan() {
+                    String::from("NAN")
+                } else if self.is_infinite() {
+                    String::from(if *self > 0.0 { "INF" } else { "NINF" })
+                } else {
+                    self.to_string()
+                }
+            }
+        }
+    )*};
+}
+impl_float!(f32, f64);
+
+impl FromScpi for bool {
+    fn from_scpi(text: &str) -> Option<Self> {
+        match text.trim().to_ascii_uppercase().as_str() {
+            "1" | "ON" => Some(true),
+            "0" | "OFF" => Some(false),
+            _ => None,
+        }
+    }
+}
+impl ToScpi for bool {
+    fn to_scpi(&self) -> String {
+        String::from(if *self { "1" } else { "0" })
+    }
+}
+
+/// Quoted string (`"` or `'`) with doubled quotes inside, or bare response.
+impl FromScpi for String {
+    fn from_scpi(text: &str) -> Option<Self> {
+        let text = text.trim();
+        match text.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = text.strip_prefix(quote)?.strip_suffix(quote)?;
+                let doubled = String::from_iter([quote, quote]);
+                Some(inner.replace(&doubled, &quote.to_string()))
+            }
+            _ => Some(text.into()),
+        }
+    }
+}
+impl ToScpi for str {
+    fn to_scpi(&self) -> String {
+        format!("\"{}\"", self.replace('"', "\"\""))
+    }
+}
+impl ToScpi for String {
+    fn to_scpi(&self) -> String {
+        self.as_str().to_scpi()
+    }
+}
+
+/// Comma-separated list.
+impl<T: FromScpi> FromScpi for Vec<T> {
+    fn from_scpi(text: &str) -> Option<Self> {
+        if text.trim().is_empty() {
+            return Some(Vec::new());
+        }
+        text.split(',').map(T::from_scpi).collect()
+    }
+}
+impl<T: ToScpi> ToScpi for [T] {
+    fn to_scpi(&self) -> String {
+        self.iter().map(T::to_scpi).collect::<Vec<_>>().join(",")
+    }
+}
+impl<T: ToScpi> ToScpi for Vec<T> {
+    fn to_scpi(&self) -> String {
+        self.as_slice().to_scpi()
+    }
+}
+
+/// Parse `CODE,"MESSAGE"` reply to `SYST:ERR?`, `None` if code is zero.
+fn parse_error(reply: String) -> Result<Option<DeviceError>, Error> {
+    let (code, message) = match reply.split_once(',') {
+        Some((code, message)) => (code, message),
+        None => (reply.as_str(), ""),
+    };
+    let code = match i32::from_scpi(code) {
+        Some(0) => return Ok(None),
+        Some(code) => code,
+        None => return Err(Error::Reply(reply)),
+    };
+    Ok(Some(DeviceError {
+        code,
+        message: String::from_scpi(message).unwrap_or_default(),
+    }))
+}
+
+/// Blocking SCPI client over raw TCP socket.
+///
+/// Messages are terminated with `\n`, trailing `\r` in responses is ignored.
+pub struct Client {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A, timeout: Duration) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address"))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            buf: Vec::new(),
+        })
+    }
+
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut chunk = [0; 256];
+        loop {
+            if let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(line.trim_end_matches(['\n', '\r']).into());
+            }
+            match self.stream.read(&mut chunk)? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                len => self.buf.extend(&chunk[..len]),
+            }
+        }
+    }
+
+    /// Send command without response.
+    pub fn command(&mut self, command: &str) -> Result<(), Error> {
+        self.buf.clear();
+        let mut data = Vec::with_capacity(command.len() + 1);
+        data.extend(command.as_bytes());
+        data.push(b'\n');
+        Ok(self.stream.write_all(&data)?)
+    }
+    /// Send command with a single parameter, e.g. `set("SOUR:VOLT", &1.5)` sends `SOUR:VOLT 1.5`.
+    pub fn set<T: ToScpi + ?Sized>(&mut self, header: &str, value: &T) -> Result<(), Error> {
+        self.command(&format!("{} {}", header, value.to_scpi()))
+    }
+
+    /// Send query and receive response.
+    pub fn query(&mut self, query: &str) -> Result<String, Error> {
+        self.command(query)?;
+        self.read_line()
+    }
+    /// Send query and parse response.
+    pub fn query_as<T: FromScpi>(&mut self, query: &str) -> Result<T, Error> {
+        let reply = self.query(query)?;
+        T::from_scpi(&reply).ok_or(Error::Reply(reply))
+    }
+
+    pub fn identify(&mut self) -> Result<Identity, Error> {
+        let reply = self.query("*IDN?")?;
+        let mut fields = reply.splitn(4, ',').map(|field| field.trim().to_string());
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(manufacturer), Some(model), Some(serial), Some(firmware)) => Ok(Identity {
+                manufacturer,
+                model,
+                serial,
+                firmware,
+            }),
+            _ => Err(Error::Reply(reply)),
+        }
+    }
+    /// Reset instrument (`*RST`).
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.command("*RST")
+    }
+    /// Clear status and error queue (`*CLS`).
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.command("*CLS")
+    }
+
+    /// Read next entry of error queue (`SYST:ERR?`), `None` if queue is empty.
+    pub fn next_error(&mut self) -> Result<Option<DeviceError>, Error> {
+        parse_error(self.query("SYST:ERR?")?)
+    }
+    /// Read all entries of error queue.
+    pub fn errors(&mut self) -> Result<Vec<DeviceError>, Error> {
+        let mut errors = Vec::new();
+        while errors.len() < MAX_ERRORS {
+            match self.next_error()? {
+                Some(error) => errors.push(error),
+                None => break,
+            }
+        }
+        Ok(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scpi::fake::FakeInstrument;
+    use std::fmt::Debug;
+
+    fn round_trip<T: FromScpi + ToScpi + PartialEq + Debug>(value: T, text: &str) {
+        assert_eq!(value.to_scpi(), text);
+        assert_eq!(T::from_scpi(text), Some(value));
+    }
+
+    #[test]
+    fn values() {
+        round_trip(-42i32, "-42");
+        round_trip(255u8, "255");
+        round_trip(1.5f64, "1.5");
+        round_trip(f32::INFINITY, "INF");
+        round_trip(f64::NEG_INFINITY, "NINF");
+        round_trip(true, "1");
+        round_trip(false, "0");
+        round_trip(String::from("say \"hi\""), "\"say \"\"hi\"\"\"");
+        round_trip(vec![1i64, -2, 3], "1,-2,3");
+        round_trip(vec![0.5f64, 2.0], "0.5,2");
+        round_trip(Vec::<i32>::new(), "");
+        assert!(f64::from_scpi("NAN").unwrap().is_nan());
+        assert_eq!(f64::NAN.to_scpi(), "NAN");
+
+        assert_eq!(i32::from_scpi(" +1.00000E+03 "), Some(1000));
+        assert_eq!(i32::from_scpi("1.5"), None);
+        assert_eq!(u8::from_scpi("256"), None);
+        assert_eq!(u8::from_scpi("-1E0"), None);
+        assert_eq!(f64::from_scpi("9.9E37"), Some(9.9e37));
+        assert_eq!(f64::from_scpi("-inf"), Some(f64::NEG_INFINITY));
+        assert_eq!(bool::from_scpi("on"), Some(true));
+        assert_eq!(bool::from_scpi("OFF"), Some(false));
+        assert_eq!(bool::from_scpi("2"), None);
+        assert_eq!(String::from_scpi("'it''s'"), Some(String::from("it's")));
+        assert_eq!(String::from_scpi("\"open"), None);
+        assert_eq!(String::from_scpi(" bare "), Some(String::from("bare")));
+        assert_eq!(Vec::<i32>::from_scpi("1,x"), None);
+        assert_eq!("a".to_scpi(), "\"a\"");
+        assert_eq!([1u16, 2].to_scpi(), "1,2");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |code, message: &str| {
+            Some(DeviceError {
+                code,
+                message: message.into(),
+            })
+        };
+        assert_eq!(parse_error("0,\"No error\"".into()).unwrap(), None);
+        assert_eq!(parse_error("+0".into()).unwrap(), None);
+        assert_eq!(
+            parse_error("-113,\"Undefined header\"".into()).unwrap(),
+            error(-113, "Undefined header")
+        );
+        assert_eq!(
+            parse_error("-222,\"Data out of range; \"\"X\"\"\"".into()).unwrap(),
+            error(-222, "Data out of range; \"X\"")
+        );
+        assert_eq!(parse_error("+100".into()).unwrap(), error(100, ""));
+        assert!(matches!(
+            parse_error("Error".into()),
+            Err(Error::Reply(reply)) if reply == "Error"
+        ));
+        assert_eq!(
+            error(-100, "Command error").unwrap().to_string(),
+            "-100,\"Command error\""
+        );
+    }
+
+    #[test]
+    fn instrument() {
+        let fake = FakeInstrument::bind("127.0.0.1:0")
+            .unwrap()
+            .identity("Acme,PS-1,123,2.0")
+            .reply("MEAS:CURR?", "+1.25E-01")
+            .setting("OUTP", "0")
+            .setting_in_range("SOUR:VOLT", "0", 0.0, 30.0);
+        let addr = fake.local_addr().unwrap();
+        let state = fake.state();
+        fake.spawn();
+
+        let mut client = Client::connect(addr, Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            client.identify().unwrap(),
+            Identity {
+                manufacturer: "Acme".into(),
+                model: "PS-1".into(),
+                serial: "123".into(),
+                firmware: "2.0".into(),
+            }
+        );
+        assert_eq!(client.query_as::<f64>("MEAS:CURR?").unwrap(), 0.125);
+        assert!(matches!(
+            client.query_as::<i32>("MEAS:CURR?"),
+            Err(Error::Reply(reply)) if reply == "+1.25E-01"
+        ));
+
+        client.set("SOUR:VOLT", &12.5).unwrap();
+        client.set("OUTP", &true).unwrap();
+        assert_eq!(client.query_as::<f64>("SOUR:VOLT?").unwrap(), 12.5);
+        assert!(client.query_as::<bool>("OUTP?").unwrap());
+        assert_eq!(client.next_error().unwrap(), None);
+
+        // Trailing `;` is not an empty command.
+        client.command("SOUR:VOLT 40;SOUR:VOLT X;BOGUS 1;").unwrap();
+        assert_eq!(
+            client.errors().unwrap(),
+            [
+                DeviceError {
+                    code: -222,
+                    message: "Data out of range".into()
+                },
+                DeviceError {
+                    code: -104,
+                    message: "Data type error".into()
+                },
+                DeviceError {
+                    code: -113,
+                    message: "Undefined header".into()
+                },
+            ]
+        );
+        assert_eq!(state.lock().unwrap().setting("SOUR:VOLT"), Some("12.5"));
+        assert!(client.errors().unwrap().is_empty());
+
+        // Error queue is read at most `MAX_ERRORS` at once.
+        for _ in 0..MAX_ERRORS + 1 {
+            state.lock().unwrap().push_error(-350, "Queue overflow");
+        }
+        assert_eq!(client.errors().unwrap().len(), MAX_ERRORS);
+        assert_eq!(client.errors().unwrap().len(), 1);
+
+        state.lock().unwrap().push_error(-100, "Command error");
+        client.clear().unwrap();
+        assert!(client.errors().unwrap().is_empty());
+
+        client.reset().unwrap();
+        assert_eq!(client.query_as::<f64>("SOUR:VOLT?").unwrap(), 0.0);
+        assert!(!client.query_as::<bool>("OUTP?").unwrap());
+    }
+
+    #[test]
+    fn timeout() {
+        let fake = FakeInstrument::bind("127.0.0.1:0").unwrap();
+        let addr = fake.local_addr().unwrap();
+        fake.spawn();
+        let mut client = Client::connect(addr, Duration::from_millis(50)).unwrap();
+        // Commands have no response.
+        assert!(client.query("SOUR:VOLT 1").unwrap_err().is_timeout());
+    }
+}
@@ -0,0 +1,160 @@
+use derive_more::{Display, Error};
+use std::time::Duration;
+
+/// How the variable is bound to instrument.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Query which response is written to input variable, e.g. `MEAS:VOLT?`.
+    Query(String),
+    /// Command sent when output variable is processed.
+    ///
+    /// Value is substituted in place of `{}`, or appended after a space if there is no placeholder.
+    Command(String),
+}
+
+/// Mapping of SCPI query or command to a variable.
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub name: String,
+    pub access: Access,
+    /// Polling period of input variables.
+    pub period: Duration,
+}
+
+impl Mapping {
+    pub fn query(name: &str, query: &str) -> Self {
+        Self::new(name, Access::Query(query.into()))
+    }
+    pub fn command(name: &str, command: &str) -> Self {
+        Self::new(name, Access::Command(command.into()))
+    }
+    fn new(name: &str, access: Access) -> Self {
+        Self {
+            name: name.into(),
+            access,
+            period: Duration::from_secs(1),
+        }
+    }
+    pub fn period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+}
+
+/// Build command text from template and formatted value.
+pub(super) fn substitute(template: &str, value: &str) -> String {
+    match template.contains("{}") {
+        true => template.replacen("{}", value, 1),
+        false => format!("{} {}", template, value),
+    }
+}
+
+#[derive(Clone, Debug, Display, Error)]
+#[display(fmt = "Line {}: {}", "line", "message")]
+pub struct ParseError {
+    pub line: usize,
+    #[error(not(source))]
+    pub message: String,
+}
+
+fn parse_mapping(line: &str) -> Result<Mapping, String> {
+    let mut tokens = line.split_whitespace();
+    let mut next = |what: &str| tokens.next().ok_or_else(|| format!("Missing {}", what));
+    let name = next("name")?;
+    let kind = next("access")?;
+    let text = next("query or command")?;
+    let mut mapping = match kind {
+        "query" => Mapping::query(name, text),
+        "command" => Mapping::command(name, text),
+        other => return Err(format!("Unknown access '{}'", other)),
+    };
+    for option in tokens {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| format!("Option 'key=value' expected, got '{}'", option))?;
+        match key {
+            "period_ms" => {
+                let millis = value
+                    .parse()
+                    .map_err(|_| format!("Invalid period '{}'", value))?;
+                mapping.period = Duration::from_millis(millis);
+            }
+            other => return Err(format!("Unknown option '{}'", other)),
+        }
+    }
+    Ok(mapping)
+}
+
+/// Parse mapping table.
+///
+/// Each line is `NAME query QUERY [period_ms=PERIOD]` or `NAME command COMMAND`,
+/// where `QUERY` and `COMMAND` must not contain whitespace.
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_mappings(text: &str) -> Result<Vec<Mapping>, ParseError> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| parse_mapping(text).map_err(|message| ParseError { line, message }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mappings = parse_mappings(
+            "# Power supply\n\
+             \n\
+             PS:VOLT query MEAS:VOLT? period_ms=200\n\
+             \tPS:SET command SOUR:VOLT\n",
+        )
+        .unwrap();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[0].name, "PS:VOLT");
+        assert_eq!(mappings[0].access, Access::Query("MEAS:VOLT?".into()));
+        assert_eq!(mappings[0].period, Duration::from_millis(200));
+        assert_eq!(mappings[1].name, "PS:SET");
+        assert_eq!(mappings[1].access, Access::Command("SOUR:VOLT".into()));
+        assert_eq!(mappings[1].period, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line, message) in [
+            ("", 0, ""),
+            ("A", 1, "Missing access"),
+            ("A query", 1, "Missing query or command"),
+            ("A read X?", 1, "Unknown access 'read'"),
+            (
+                "# A\nA query X? 200",
+                2,
+                "Option 'key=value' expected, got '200'",
+            ),
+            ("A query X? period_ms=-1", 1, "Invalid period '-1'"),
+            (
+                "A query X?\n\nB query Y? rate=1",
+                3,
+                "Unknown option 'rate'",
+            ),
+        ] {
+            match parse_mappings(text) {
+                Ok(mappings) => assert!(line == 0 && mappings.is_empty()),
+                Err(err) => {
+                    assert_eq!((err.line, err.message.as_str()), (line, message));
+                    assert_eq!(err.to_string(), format!("Line {}: {}", line, message));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(substitute("SOUR:VOLT", "1.5"), "SOUR:VOLT 1.5");
+        assert_eq!(substitute("CH{}:STAT ON", "2"), "CH2:STAT ON");
+        assert_eq!(substitute("A {} {}", "1"), "A 1 {}");
+    }
+}
@@ -0,0 +1,191 @@
+//! Scripted SCPI instrument for testing.
+
+use super::client::{DeviceError, FromScpi};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Setting that can be both set by command and read by query.
+#[derive(Clone, Debug)]
+struct Setting {
+    value: String,
+    initial: String,
+    limits: Option<(f64, f64)>,
+}
+
+/// State of fake instrument, shared between clients.
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    identity: String,
+    replies: HashMap<String, String>,
+    settings: HashMap<String, Setting>,
+    errors: VecDeque<DeviceError>,
+    /// All received commands and queries.
+    pub log: Vec<String>,
+}
+
+impl State {
+    /// Set fixed response to query.
+    pub fn set_reply(&mut self, query: &str, reply: &str) {
+        self.replies
+            .insert(query.to_ascii_uppercase(), reply.into());
+    }
+    /// Current value of setting.
+    pub fn setting(&self, header: &str) -> Option<&str> {
+        self.settings
+            .get(&header.to_ascii_uppercase())
+            .map(|setting| setting.value.as_str())
+    }
+    /// Append entry to error queue.
+    pub fn push_error(&mut self, code: i32, message: &str) {
+        self.errors.push_back(DeviceError {
+            code,
+            message: message.into(),
+        });
+    }
+
+    fn handle(&mut self, message: &str) -> Option<String> {
+        self.log.push(message.into());
+        let (header, param) = match message.split_once(char::is_whitespace) {
+            Some((header, param)) => (header.to_ascii_uppercase(), param.trim()),
+            None => (message.to_ascii_uppercase(), ""),
+        };
+        match header.as_str() {
+            "*IDN?" => return Some(self.identity.clone()),
+            "*CLS" => self.errors.clear(),
+            "*RST" => self
+                .settings
+                .values_mut()
+                .for_each(|setting| setting.value = setting.initial.clone()),
+            "SYST:ERR?" | "SYST:ERR:NEXT?" | "SYSTEM:ERROR?" | "SYSTEM:ERROR:NEXT?" => {
+                return Some(match self.errors.pop_front() {
+                    Some(error) => error.to_string(),
+                    None => String::from("0,\"No error\""),
+                })
+            }
+            _ => {
+                if let Some(reply) = self.replies.get(&header) {
+                    return Some(reply.clone());
+                }
+                if let Some(setting) = header
+                    .strip_suffix('?')
+                    .and_then(|header| self.settings.get(header))
+                {
+                    return Some(setting.value.clone());
+                }
+                match self.settings.get_mut(&header) {
+                    Some(setting) => match setting.limits {
+                        Some((min, max)) => match f64::from_scpi(param) {
+                            Some(x) if x >= min && x <= max => setting.value = param.into(),
+                            Some(_) => self.push_error(-222, "Data out of range"),
+                            None => self.push_error(-104, "Data type error"),
+                        },
+                        None => setting.value = param.into(),
+                    },
+                    None => self.push_error(-113, "Undefined header"),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Fake instrument serving each client in a separate thread.
+///
+/// Supports `*IDN?`, `*RST`, `*CLS`, `SYST:ERR?`, scripted query responses and settings.
+/// Unknown headers are put into error queue as in real instruments.
+/// Headers are matched in their exact (case-insensitive) form, short and long forms are not expanded.
+pub struct FakeInstrument {
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+}
+
+fn serve(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        for message in line.trim_end_matches('\r').split(';') {
+            // Trailing `;` is allowed.
+            let message = message.trim();
+            if message.is_empty() {
+                continue;
+            }
+            let reply = state.lock().unwrap().handle(message);
+            if let Some(reply) = reply {
+                writer.write_all(format!("{}\n", reply).as_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl FakeInstrument {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            state: Arc::new(Mutex::new(State {
+                identity: String::from("Ferrite,Fake Instrument,0,1.0"),
+                ..State::default()
+            })),
+        })
+    }
+
+    /// Response to `*IDN?`.
+    pub fn identity(self, identity: &str) -> Self {
+        self.state.lock().unwrap().identity = identity.into();
+        self
+    }
+    /// Fixed response to `query`.
+    pub fn reply(self, query: &str, reply: &str) -> Self {
+        self.state.lock().unwrap().set_reply(query, reply);
+        self
+    }
+    /// Setting changed by `HEADER value` command and read by `HEADER?` query.
+    pub fn setting(self, header: &str, initial: &str) -> Self {
+        self.add_setting(header, initial, None)
+    }
+    /// Numeric setting, values out of `min..=max` are rejected with `-222` error.
+    pub fn setting_in_range(self, header: &str, initial: &str, min: f64, max: f64) -> Self {
+        self.add_setting(header, initial, Some((min, max)))
+    }
+    fn add_setting(self, header: &str, initial: &str, limits: Option<(f64, f64)>) -> Self {
+        self.state.lock().unwrap().settings.insert(
+            header.to_ascii_uppercase(),
+            Setting {
+                value: initial.into(),
+                initial: initial.into(),
+                limits,
+            },
+        );
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    /// Shared state, can be used to change responses or inspect settings while running.
+    pub fn state(&self) -> Arc<Mutex<State>> {
+        self.state.clone()
+    }
+
+    /// Accept clients in a separate thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name("fake-scpi".into())
+            .spawn(move || {
+                for stream in self.listener.incoming().flatten() {
+                    let state = self.state.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, &state) {
+                            log::debug!("Fake instrument client disconnected: {}", err);
+                        }
+                    });
+                }
+            })
+            .unwrap()
+    }
+}
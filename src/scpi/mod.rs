@@ -0,0 +1,397 @@
+//! SCPI instrument driver over raw TCP socket.
+//!
+//! Variables are bound to SCPI queries and commands according to a table of [`Mapping`]s.
+//! Input records (e.g. `ai`) are polled periodically with queries, output records (e.g. `ao`) send commands when processed.
+//! After each operation instrument error queue is read with `SYST:ERR?`,
+//! errors and communication failures reject processing with `SCPI` or `COMM` message, so that the record goes into alarm.
+
+mod client;
+mod config;
+pub mod fake;
+
+pub use client::{Client, DeviceError, Error, FromScpi, Identity, ToScpi, PORT};
+pub use config::{parse_mappings, Access, Mapping, ParseError};
+
+use crate::{
+    driver::{self, comm_message, Polled, CONVERSION},
+    dynamic::{Conversion, DynArray, DynValue},
+    registry::GetDowncastError,
+    variable::{Direction, Type},
+    Info, Registry,
+};
+use derive_more::{Display, Error, From};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[derive(Debug, Display, Error, From)]
+pub enum DriverError {
+    Var(GetDowncastError),
+    #[display(fmt = "PV '{}': Query cannot be bound to output record", "_0")]
+    #[from(ignore)]
+    QueryToOutput(#[error(not(source))] String),
+    #[display(fmt = "PV '{}': Command cannot be bound to input record", "_0")]
+    #[from(ignore)]
+    CommandToInput(#[error(not(source))] String),
+    #[display(fmt = "Cannot resolve address '{}'", "_0")]
+    #[from(ignore)]
+    Address(#[error(not(source))] String),
+}
+
+/// Shape of variable value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shape {
+    Scalar,
+    Array,
+    /// Array of `u8` holding a string.
+    String,
+}
+
+impl Shape {
+    fn of(info: Info) -> Self {
+        match (info.max_len, info.type_) {
+            (0, _) => Shape::Scalar,
+            (_, Type::U8) => Shape::String,
+            _ => Shape::Array,
+        }
+    }
+}
+
+fn parse_value(reply: &str, shape: Shape) -> Option<DynValue> {
+    match shape {
+        Shape::String => Some(DynValue::Array(DynArray::U8(
+            String::from_scpi(reply)?.into_bytes(),
+        ))),
+        Shape::Array => Some(DynValue::Array(match Vec::<i64>::from_scpi(reply) {
+            Some(ints) => DynArray::I64(ints),
+            None => DynArray::F64(Vec::from_scpi(reply)?),
+        })),
+        Shape::Scalar => Some(match (i64::from_scpi(reply), bool::from_scpi(reply)) {
+            (Some(x), _) => DynValue::I64(x),
+            (None, Some(x)) => DynValue::I64(x as i64),
+            (None, None) => DynValue::F64(f64::from_scpi(reply)?),
+        }),
+    }
+}
+
+fn format_value(value: &DynValue, shape: Shape) -> String {
+    let float = matches!(value.type_(), Type::F32 | Type::F64);
+    match (shape, value) {
+        (Shape::String, _) => {
+            let bytes = value.get_array::<u8>(Conversion::LOSSY).unwrap();
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).to_scpi()
+        }
+        (_, DynValue::Array(_)) if float => {
+            value.get_array::<f64>(Conversion::LOSSY).unwrap().to_scpi()
+        }
+        (_, DynValue::Array(_)) => value.get_array::<i64>(CONVERSION).unwrap().to_scpi(),
+        _ if float => value
+            .get_scalar::<f64>(Conversion::LOSSY)
+            .unwrap()
+            .to_scpi(),
+        _ => value.get_scalar::<i64>(CONVERSION).unwrap().to_scpi(),
+    }
+}
+
+/// Connection that is re-established after communication errors.
+struct Connection {
+    addr: SocketAddr,
+    timeout: Duration,
+    check_errors: bool,
+    client: Option<Client>,
+}
+
+impl Connection {
+    /// Run operation and then read error queue.
+    ///
+    /// Returns message to reject processing with on failure.
+    fn run<R, F: FnOnce(&mut Client) -> Result<R, Error>>(&mut self, f: F) -> Result<R, String> {
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => self
+                .client
+                .insert(Client::connect(self.addr, self.timeout).map_err(|err| comm(err.into()))?),
+        };
+        let result = f(client).and_then(|value| {
+            let errors = match self.check_errors {
+                true => client.errors()?,
+                false => Vec::new(),
+            };
+            Ok((value, errors))
+        });
+        match result {
+            Ok((value, errors)) if errors.is_empty() => Ok(value),
+            Ok((_, errors)) => {
+                for error in &errors {
+                    log::warn!("SCPI error {}", error);
+                }
+                let errors: Vec<String> = errors.iter().map(DeviceError::to_string).collect();
+                Err(format!("SCPI: {}", errors.join("; ")))
+            }
+            Err(err) => {
+                if matches!(err, Error::Io(_)) {
+                    self.client = None;
+                }
+                Err(comm(err))
+            }
+        }
+    }
+}
+
+fn comm(err: Error) -> String {
+    comm_message(&err, err.is_timeout())
+}
+
+/// SCPI driver configuration.
+pub struct Driver {
+    addr: String,
+    timeout: Duration,
+    check_errors: bool,
+    mappings: Vec<Mapping>,
+}
+
+impl Driver {
+    /// Instrument address, [`PORT`] is used if it is not specified.
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.into(),
+            timeout: Duration::from_secs(1),
+            check_errors: true,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Response timeout, 1 second by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Whether to read error queue after each operation, enabled by default.
+    pub fn check_errors(mut self, check_errors: bool) -> Self {
+        self.check_errors = check_errors;
+        self
+    }
+    pub fn mapping(mut self, mapping: Mapping) -> Self {
+        self.mappings.push(mapping);
+        self
+    }
+    pub fn mappings<I: IntoIterator<Item = Mapping>>(mut self, mappings: I) -> Self {
+        self.mappings.extend(mappings);
+        self
+    }
+
+    /// Take mapped variables from `registry` and start polling and writing in separate threads.
+    ///
+    /// Queries must be bound to input records, commands to output records.
+    pub fn start(
+        self,
+        registry: &mut Registry,
+    ) -> Result<Vec<thread::JoinHandle<()>>, DriverError> {
+        let addr = driver::resolve(&self.addr, Some(PORT))
+            .ok_or_else(|| DriverError::Address(self.addr.clone()))?;
+        // Check all mappings first, so that registry is left intact on error.
+        for mapping in &self.mappings {
+            let name = &mapping.name;
+            match (&mapping.access, registry.try_get(name)?.direction()) {
+                (Access::Query(_), Direction::Output) => {
+                    return Err(DriverError::QueryToOutput(registry.full_name(name)))
+                }
+                (Access::Command(_), Direction::Input) => {
+                    return Err(DriverError::CommandToInput(registry.full_name(name)))
+                }
+                _ => (),
+            }
+        }
+        let mut queries = Vec::new();
+        let mut commands = Vec::new();
+        for mapping in self.mappings {
+            let name = mapping.name;
+            match mapping.access {
+                Access::Query(query) => queries.push(Polled {
+                    source: query,
+                    period: mapping.period,
+                    var: registry.remove_downcast(&name)?,
+                }),
+                Access::Command(template) => {
+                    commands.push((template, registry.remove_downcast(&name)?))
+                }
+            }
+        }
+
+        let conn = Arc::new(Mutex::new(Connection {
+            addr,
+            timeout: self.timeout,
+            check_errors: self.check_errors,
+            client: None,
+        }));
+        let mut handles = Vec::new();
+        if !queries.is_empty() {
+            let conn = conn.clone();
+            handles.push(driver::spawn("scpi-poll", move || {
+                driver::poll(queries, |query, info, _| {
+                    let shape = Shape::of(info);
+                    conn.lock()
+                        .unwrap()
+                        .run(|client| {
+                            let reply = client.query(query)?;
+                            parse_value(&reply, shape).ok_or(Error::Reply(reply))
+                        })
+                        .map(Some)
+                })
+            }));
+        }
+        for (template, var) in commands {
+            let conn = conn.clone();
+            handles.push(driver::spawn("scpi-write", move || {
+                driver::write(var, |info, value| {
+                    let command =
+                        config::substitute(&template, &format_value(&value, Shape::of(info)));
+                    conn.lock()
+                        .unwrap()
+                        .run(|client| client.command(&command))?;
+                    Ok(None)
+                })
+            }));
+        }
+        Ok(handles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::wait_for, testing::FakeVar};
+    use fake::FakeInstrument;
+
+    #[test]
+    fn values() {
+        let bytes = |text: &str| DynValue::Array(DynArray::U8(text.into()));
+        assert_eq!(
+            parse_value("+1E+3", Shape::Scalar),
+            Some(DynValue::I64(1000))
+        );
+        assert_eq!(parse_value("ON", Shape::Scalar), Some(DynValue::I64(1)));
+        assert_eq!(parse_value("0.5", Shape::Scalar), Some(DynValue::F64(0.5)));
+        assert_eq!(parse_value("x", Shape::Scalar), None);
+        assert_eq!(
+            parse_value("1,2", Shape::Array),
+            Some(DynValue::Array(DynArray::I64(vec![1, 2])))
+        );
+        assert_eq!(
+            parse_value("1,2.5", Shape::Array),
+            Some(DynValue::Array(DynArray::F64(vec![1.0, 2.5])))
+        );
+        assert_eq!(
+            parse_value("\"a\"\"b\"", Shape::String),
+            Some(bytes("a\"b"))
+        );
+
+        assert_eq!(format_value(&DynValue::F64(2.5), Shape::Scalar), "2.5");
+        assert_eq!(format_value(&DynValue::I32(-3), Shape::Scalar), "-3");
+        assert_eq!(
+            format_value(&DynValue::Array(DynArray::U16(vec![1, 2])), Shape::Array),
+            "1,2"
+        );
+        assert_eq!(
+            format_value(&DynValue::Array(DynArray::F32(vec![0.5])), Shape::Array),
+            "0.5"
+        );
+        assert_eq!(
+            format_value(&bytes("a\"b\0\0"), Shape::String),
+            "\"a\"\"b\""
+        );
+    }
+
+    #[test]
+    fn driver() {
+        let fake = FakeInstrument::bind("127.0.0.1:0")
+            .unwrap()
+            .reply("MEAS:VOLT?", "1.5")
+            .reply("MEAS:BAD?", "abc")
+            .setting("OUTP", "0")
+            .setting_in_range("SOUR:VOLT", "0", 0.0, 30.0);
+        let addr = fake.local_addr().unwrap().to_string();
+        let state = fake.state();
+        fake.spawn();
+
+        let mut registry = Registry::default();
+        let mut add = |name: &str, var: FakeVar| {
+            let var = var.leak();
+            registry.insert(name.into(), var.var());
+            var
+        };
+        let input = |name| FakeVar::new(name, Type::F64, 0, Direction::Input);
+        let volt = add("PS:VOLT", input("PS:VOLT").auto_process());
+        let stuck = add("PS:STUCK", input("PS:STUCK"));
+        let bad = add("PS:BAD", input("PS:BAD").auto_process());
+        let set = add(
+            "PS:SET",
+            FakeVar::new("PS:SET", Type::F64, 0, Direction::Output)
+                .scalar(40.0f64)
+                .auto_process(),
+        );
+        let output = add(
+            "PS:OUTP",
+            FakeVar::new("PS:OUTP", Type::I32, 0, Direction::Output)
+                .scalar(1i32)
+                .auto_process(),
+        );
+
+        let period = Duration::from_millis(10);
+        // Registry is left intact if any mapping is invalid.
+        assert!(matches!(
+            Driver::new(&addr)
+                .mapping(Mapping::query("PS:VOLT", "MEAS:VOLT?"))
+                .mapping(Mapping::query("PS:SET", "SOUR:VOLT?"))
+                .start(&mut registry),
+            Err(DriverError::QueryToOutput(_))
+        ));
+        assert_eq!(registry.len(), 5);
+        assert!(matches!(
+            Driver::new(&addr)
+                .mapping(Mapping::command("PS:VOLT", "SOUR:VOLT"))
+                .start(&mut registry),
+            Err(DriverError::CommandToInput(_))
+        ));
+        Driver::new(&addr)
+            .mappings([
+                Mapping::query("PS:VOLT", "MEAS:VOLT?").period(period),
+                Mapping::query("PS:STUCK", "MEAS:VOLT?").period(period),
+                Mapping::query("PS:BAD", "MEAS:BAD?").period(period),
+                Mapping::command("PS:SET", "SOUR:VOLT"),
+                Mapping::command("PS:OUTP", "OUTP {};"),
+            ])
+            .start(&mut registry)
+            .unwrap();
+
+        wait_for(|| volt.load() == DynValue::F64(1.5));
+        wait_for(|| !bad.commits.lock().unwrap().is_empty());
+        assert_eq!(
+            bad.commits.lock().unwrap()[0],
+            Err("COMM: Unexpected reply 'abc'".into())
+        );
+
+        // Instrument errors reject processing.
+        set.process();
+        wait_for(|| !set.commits.lock().unwrap().is_empty());
+        assert_eq!(
+            set.commits.lock().unwrap()[0],
+            Err("SCPI: -222,\"Data out of range\"".into())
+        );
+        assert_eq!(state.lock().unwrap().setting("SOUR:VOLT"), Some("0"));
+
+        output.process();
+        wait_for(|| !output.commits.lock().unwrap().is_empty());
+        assert_eq!(output.commits.lock().unwrap()[0], Ok(()));
+        assert_eq!(state.lock().unwrap().setting("OUTP"), Some("1"));
+
+        // Record that is never processed doesn't stall other inputs.
+        wait_for(|| stuck.requests.load(std::sync::atomic::Ordering::SeqCst) > 0);
+        state.lock().unwrap().set_reply("MEAS:VOLT?", "2.5");
+        wait_for(|| volt.load() == DynValue::F64(2.5));
+    }
+}
@@ -0,0 +1,596 @@
+//! Capturing, comparing and restoring sets of variable values.
+
+use crate::{
+    dynamic::{dispatch_type, Conversion, ConvertError, DynValue, DynVariable, Num, Scalar},
+    pattern::glob_match,
+    registry::{shared, Observer},
+    variable::{Info, Type},
+    Registry, TypedVariable,
+};
+use derive_more::{Display, Error, From};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Captured value of the variable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub info: Info,
+    pub value: DynValue,
+}
+
+/// Values of a set of variables by full names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Allowed absolute difference of floating-point values.
+#[derive(Clone, Debug, Default)]
+pub struct Tolerances {
+    default: f64,
+    names: HashMap<String, f64>,
+}
+
+impl Tolerances {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Tolerance for variables not set explicitly, zero by default.
+    pub fn fallback(mut self, tolerance: f64) -> Self {
+        self.default = tolerance;
+        self
+    }
+    pub fn set(mut self, name: &str, tolerance: f64) -> Self {
+        self.names.insert(name.into(), tolerance);
+        self
+    }
+    pub fn get(&self, name: &str) -> f64 {
+        self.names.get(name).copied().unwrap_or(self.default)
+    }
+}
+
+/// Difference between expected and actual snapshot.
+#[derive(Clone, Debug, Display)]
+pub enum Difference {
+    #[display(fmt = "PV '{}': Missing", "_0")]
+    Missing(String),
+    #[display(fmt = "PV '{}': Unexpected", "_0")]
+    Extra(String),
+    #[display(fmt = "PV '{}': {:?} expected, got {:?}", "name", "expected", "actual")]
+    Info {
+        name: String,
+        expected: Info,
+        actual: Info,
+    },
+    #[display(fmt = "PV '{}': {:?} expected, got {:?}", "name", "expected", "actual")]
+    Value {
+        name: String,
+        expected: DynValue,
+        actual: DynValue,
+    },
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum SnapshotError {
+    Json(serde_json::Error),
+    TomlSer(toml::ser::Error),
+    TomlDe(toml::de::Error),
+    #[display(fmt = "PV '{}': {}", "name", "message")]
+    #[from(ignore)]
+    Invalid {
+        name: String,
+        #[error(not(source))]
+        message: String,
+    },
+}
+
+#[derive(Debug, Display, Error)]
+pub enum RestoreError {
+    #[display(fmt = "PV '{}': Not in snapshot", "_0")]
+    NotInSnapshot(#[error(not(source))] String),
+    #[display(fmt = "PV '{}': Not found in registry", "_0")]
+    NotFound(#[error(not(source))] String),
+    #[display(fmt = "PV '{}': {}", "name", "error")]
+    Convert { name: String, error: ConvertError },
+}
+
+/// How long single capture waits for variables that are being processed, in total.
+const LOAD_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Load value retrying until `deadline` while variable is being processed.
+fn load_value(observer: &Observer<DynValue>, deadline: Instant) -> Option<DynValue> {
+    loop {
+        if let Some(value) = observer.load() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capture values of all variables.
+    pub fn capture() -> Self {
+        Self::capture_filter(|_| true)
+    }
+    /// Capture values of variables matching glob `pattern`.
+    pub fn capture_matching(pattern: &str) -> Self {
+        Self::capture_filter(|name| glob_match(pattern, name))
+    }
+    /// Capture values of variables which names satisfy `filter`.
+    ///
+    /// *Variables that are being processed for too long are skipped,
+    /// the whole capture waits for them no longer than 100 ms.*
+    pub fn capture_filter<F: FnMut(&str) -> bool>(mut filter: F) -> Self {
+        let deadline = Instant::now() + LOAD_TIMEOUT;
+        let mut snapshot = Self::new();
+        for name in shared().names() {
+            if !filter(&name) {
+                continue;
+            }
+            let observer: Observer<DynValue> = match shared().get_downcast(&name) {
+                Ok(observer) => observer,
+                Err(_) => continue,
+            };
+            match load_value(&observer, deadline) {
+                Some(value) => snapshot.insert(
+                    name,
+                    Entry {
+                        info: observer.info(),
+                        value,
+                    },
+                ),
+                None => log::warn!("PV '{}': Skipped, processing takes too long", name),
+            }
+        }
+        snapshot
+    }
+
+    pub fn insert(&mut self, name: String, entry: Entry) {
+        self.entries.insert(name, entry);
+    }
+    pub fn remove(&mut self, name: &str) -> Option<Entry> {
+        self.entries.remove(name)
+    }
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut filter: F) {
+        self.entries.retain(|name, _| filter(name))
+    }
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Compare `self` as expected state with `actual` one.
+    pub fn diff(&self, actual: &Snapshot, tolerances: &Tolerances) -> Vec<Difference> {
+        let mut diffs = Vec::new();
+        for (name, expected) in &self.entries {
+            let actual = match actual.entries.get(name) {
+                Some(actual) => actual,
+                None => {
+                    diffs.push(Difference::Missing(name.clone()));
+                    continue;
+                }
+            };
+            if expected.info != actual.info {
+                diffs.push(Difference::Info {
+                    name: name.clone(),
+                    expected: expected.info,
+                    actual: actual.info,
+                });
+            } else if !values_match(&expected.value, &actual.value, tolerances.get(name)) {
+                diffs.push(Difference::Value {
+                    name: name.clone(),
+                    expected: expected.value.clone(),
+                    actual: actual.value.clone(),
+                });
+            }
+        }
+        for name in actual.entries.keys() {
+            if !self.entries.contains_key(name) {
+                diffs.push(Difference::Extra(name.clone()));
+            }
+        }
+        diffs
+    }
+    /// Compare `self` with current values of the same variables.
+    pub fn diff_live(&self, tolerances: &Tolerances) -> Vec<Difference> {
+        self.diff(
+            &Self::capture_filter(|name| self.entries.contains_key(name)),
+            tolerances,
+        )
+    }
+
+    /// Write values of variables `names` to `registry` one by one in specified order.
+    ///
+    /// Stops at first error. Returns number of restored variables.
+    ///
+    /// Variables stay in `registry`, it is borrowed mutably so that no one else accesses them meanwhile.
+    pub async fn restore<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        registry: &mut Registry,
+        names: I,
+    ) -> Result<usize, RestoreError> {
+        let mut count = 0;
+        for name in names {
+            let entry = self
+                .get(name)
+                .ok_or_else(|| RestoreError::NotInSnapshot(name.into()))?;
+            let var = name
+                .strip_prefix(registry.prefix())
+                .and_then(|name| registry.get(name))
+                .ok_or_else(|| RestoreError::NotFound(name.into()))?;
+            // Temporary handle is dropped before `registry` is released.
+            // Variable can't be claimed meanwhile because it is held by `registry`.
+            let mut var: DynVariable =
+                unsafe { TypedVariable::new_unchecked(var.clone_unchecked()) };
+            var.request()
+                .await
+                .write(&entry.value, Conversion::LOSSLESS)
+                .map_err(|error| RestoreError::Convert {
+                    name: name.into(),
+                    error,
+                })?
+                .await;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string_pretty(&self.to_repr())?)
+    }
+    pub fn from_json(text: &str) -> Result<Self, SnapshotError> {
+        Self::from_repr(serde_json::from_str(text)?)
+    }
+    pub fn to_toml(&self) -> Result<String, SnapshotError> {
+        Ok(toml::to_string(&self.to_repr())?)
+    }
+    pub fn from_toml(text: &str) -> Result<Self, SnapshotError> {
+        Self::from_repr(toml::from_str(text)?)
+    }
+
+    fn to_repr(&self) -> BTreeMap<&str, EntryRepr> {
+        self.iter()
+            .map(|(name, entry)| (name, EntryRepr::new(entry)))
+            .collect()
+    }
+    fn from_repr(repr: BTreeMap<String, EntryRepr>) -> Result<Self, SnapshotError> {
+        let mut snapshot = Self::new();
+        for (name, repr) in repr {
+            match repr.entry() {
+                Ok(entry) => snapshot.insert(name, entry),
+                Err(message) => return Err(SnapshotError::Invalid { name, message }),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+fn values_match(expected: &DynValue, actual: &DynValue, tolerance: f64) -> bool {
+    fn floats(value: &DynValue) -> Vec<f64> {
+        match value {
+            DynValue::Array(_) => value.get_array(Conversion::LOSSLESS).unwrap(),
+            _ => vec![value.get_scalar(Conversion::LOSSLESS).unwrap()],
+        }
+    }
+    let is_float = matches!(expected.type_(), Type::F32 | Type::F64);
+    if !is_float || expected.type_() != actual.type_() || expected.is_array() != actual.is_array() {
+        return expected == actual;
+    }
+    let (expected, actual) = (floats(expected), floats(actual));
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .zip(&actual)
+            .all(|(x, y)| (x - y).abs() <= tolerance || (x.is_nan() && y.is_nan()))
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryRepr {
+    #[serde(rename = "type")]
+    type_: Type,
+    max_len: usize,
+    value: ValueRepr,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ValueRepr {
+    Scalar(NumRepr),
+    Array(Vec<NumRepr>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum NumRepr {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    /// Not all formats can represent these floats as numbers (e.g. JSON).
+    NonFinite(NonFinite),
+    /// Integer that doesn't fit into `i64`, not all formats support it (e.g. TOML).
+    Text(String),
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum NonFinite {
+    #[serde(rename = "NaN")]
+    Nan,
+    #[serde(rename = "inf")]
+    Inf,
+    #[serde(rename = "-inf")]
+    NegInf,
+}
+
+impl From<Num> for NumRepr {
+    fn from(num: Num) -> Self {
+        match num {
+            Num::Int(x) => match i64::try_from(x) {
+                Ok(x) => NumRepr::Int(x),
+                Err(_) => NumRepr::Text(x.to_string()),
+            },
+            Num::Float(x) if x.is_nan() => NumRepr::NonFinite(NonFinite::Nan),
+            Num::Float(x) if x == f64::INFINITY => NumRepr::NonFinite(NonFinite::Inf),
+            Num::Float(x) if x == f64::NEG_INFINITY => NumRepr::NonFinite(NonFinite::NegInf),
+            Num::Float(x) => NumRepr::Float(x),
+        }
+    }
+}
+impl TryFrom<NumRepr> for Num {
+    type Error = String;
+    fn try_from(repr: NumRepr) -> Result<Self, String> {
+        Ok(match repr {
+            NumRepr::Int(x) => Num::Int(x.into()),
+            NumRepr::UInt(x) => Num::Int(x.into()),
+            NumRepr::Float(x) => Num::Float(x),
+            NumRepr::NonFinite(NonFinite::Nan) => Num::Float(f64::NAN),
+            NumRepr::NonFinite(NonFinite::Inf) => Num::Float(f64::INFINITY),
+            NumRepr::NonFinite(NonFinite::NegInf) => Num::Float(f64::NEG_INFINITY),
+            NumRepr::Text(text) => Num::Int(
+                text.parse()
+                    .map_err(|_| format!("Invalid number '{}'", text))?,
+            ),
+        })
+    }
+}
+
+impl EntryRepr {
+    fn new(entry: &Entry) -> Self {
+        fn value<T: Scalar>(value: &DynValue) -> ValueRepr {
+            let repr = |x: T| NumRepr::from(x.into_num());
+            match value {
+                DynValue::Array(_) => ValueRepr::Array(
+                    value
+                        .get_array::<T>(Conversion::LOSSLESS)
+                        .unwrap()
+                        .into_iter()
+                        .map(repr)
+                        .collect(),
+                ),
+                _ => ValueRepr::Scalar(repr(value.get_scalar(Conversion::LOSSLESS).unwrap())),
+            }
+        }
+        Self {
+            type_: entry.info.type_,
+            max_len: entry.info.max_len,
+            value: dispatch_type!(entry.value.type_(), value, &entry.value),
+        }
+    }
+
+    fn entry(self) -> Result<Entry, String> {
+        fn value<T: Scalar>(repr: ValueRepr) -> Result<DynValue, String> {
+            let conv = |x: NumRepr| {
+                T::from_num(x.try_into()?, Conversion::LOSSLESS).map_err(|err| err.to_string())
+            };
+            Ok(match repr {
+                ValueRepr::Scalar(x) => conv(x)?.into_dyn(),
+                ValueRepr::Array(xs) => DynValue::Array(T::into_dyn_array(
+                    xs.into_iter().map(conv).collect::<Result<_, _>>()?,
+                )),
+            })
+        }
+        let type_ = self.type_;
+        let is_array = matches!(self.value, ValueRepr::Array(_));
+        if is_array != (self.max_len != 0) {
+            return Err("Value shape doesn't match max_len".into());
+        }
+        Ok(Entry {
+            info: Info {
+                type_,
+                max_len: self.max_len,
+            },
+            value: dispatch_type!(type_, value, self.value)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeVar, variable::Direction};
+    use futures::executor::block_on;
+
+    fn entry(max_len: usize, value: DynValue) -> Entry {
+        Entry {
+            info: Info {
+                type_: value.type_(),
+                max_len,
+            },
+            value,
+        }
+    }
+
+    fn sample() -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("A".into(), entry(0, DynValue::from(-1.5f64)));
+        snapshot.insert("B".into(), entry(0, DynValue::from(u64::MAX)));
+        snapshot.insert("C".into(), entry(0, DynValue::from(i64::MIN)));
+        snapshot.insert("D".into(), entry(4, DynValue::from(vec![1u8, 2, 3])));
+        snapshot.insert("E".into(), entry(0, DynValue::from(f32::INFINITY)));
+        snapshot.insert(
+            "F".into(),
+            entry(2, DynValue::from(vec![f64::NEG_INFINITY, 0.25])),
+        );
+        snapshot
+    }
+
+    #[test]
+    fn json() {
+        let snapshot = sample();
+        let text = snapshot.to_json().unwrap();
+        assert!(text.contains("\"inf\""));
+        assert_eq!(Snapshot::from_json(&text).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn toml() {
+        let snapshot = sample();
+        assert_eq!(
+            Snapshot::from_toml(&snapshot.to_toml().unwrap()).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn nan() {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("N".into(), entry(0, DynValue::from(f64::NAN)));
+        let text = snapshot.to_json().unwrap();
+        assert!(text.contains("\"NaN\""));
+        let value = Snapshot::from_json(&text)
+            .unwrap()
+            .get("N")
+            .unwrap()
+            .value
+            .clone();
+        assert!(value
+            .get_scalar::<f64>(Conversion::LOSSLESS)
+            .unwrap()
+            .is_nan());
+    }
+
+    #[test]
+    fn invalid() {
+        let err = Snapshot::from_json(r#"{"X": {"type": "U8", "max_len": 0, "value": 256}}"#);
+        assert!(matches!(err, Err(SnapshotError::Invalid { name, .. }) if name == "X"));
+        let err = Snapshot::from_json(r#"{"X": {"type": "U8", "max_len": 0, "value": [1]}}"#);
+        assert!(matches!(err, Err(SnapshotError::Invalid { .. })));
+        let err = Snapshot::from_json(r#"{"X": {"type": "F64", "max_len": 0, "value": "x"}}"#);
+        assert!(matches!(err, Err(SnapshotError::Invalid { .. })));
+        let err = Snapshot::from_json(r#"{"X": {"type": "F64", "max_len": 0, "value": {}}}"#);
+        assert!(matches!(err, Err(SnapshotError::Json(_))));
+    }
+
+    #[test]
+    fn diff() {
+        let expected = sample();
+        let mut actual = sample();
+        actual.insert("A".into(), entry(0, DynValue::from(-1.45f64)));
+        actual.insert("D".into(), entry(4, DynValue::from(vec![1u8, 2, 4])));
+        actual.remove("E");
+        actual.insert("G".into(), entry(0, DynValue::from(1i32)));
+        actual.insert("F".into(), entry(3, DynValue::from(vec![0.0f64; 3])));
+
+        let tolerances = Tolerances::new().set("A", 0.1);
+        let diffs = expected.diff(&actual, &tolerances);
+        let names: Vec<_> = diffs
+            .iter()
+            .map(|diff| match diff {
+                Difference::Missing(name) => format!("missing {}", name),
+                Difference::Extra(name) => format!("extra {}", name),
+                Difference::Info { name, .. } => format!("info {}", name),
+                Difference::Value { name, .. } => format!("value {}", name),
+            })
+            .collect();
+        assert_eq!(names, ["value D", "missing E", "info F", "extra G"]);
+        assert_eq!(
+            expected.diff(&actual, &Tolerances::new()).len(),
+            diffs.len() + 1
+        );
+    }
+
+    #[test]
+    fn capture_skips_processing() {
+        let idle = FakeVar::new("SNAPSHOT:IDLE", Type::I32, 0, Direction::Input)
+            .scalar(5i32)
+            .leak();
+        let busy: Vec<_> = (0..3)
+            .map(|i| {
+                let var = FakeVar::new(
+                    &format!("SNAPSHOT:BUSY{}", i),
+                    Type::I32,
+                    0,
+                    Direction::Output,
+                )
+                .leak();
+                var.process();
+                var
+            })
+            .collect();
+        for var in [idle].iter().chain(&busy) {
+            shared().add_variable(var.var());
+        }
+        let start = Instant::now();
+        let snapshot = Snapshot::capture_matching("SNAPSHOT:*");
+        assert!(start.elapsed() < LOAD_TIMEOUT * 2);
+        assert_eq!(snapshot.names().collect::<Vec<_>>(), ["SNAPSHOT:IDLE"]);
+        assert_eq!(
+            snapshot.get("SNAPSHOT:IDLE").unwrap().value,
+            DynValue::from(5i32)
+        );
+    }
+
+    #[test]
+    fn restore() {
+        let mut registry = Registry::default();
+        let vars = ["A", "B"].map(|name| {
+            let fake = FakeVar::new(name, Type::F64, 0, Direction::Output)
+                .auto_process()
+                .leak();
+            registry.insert(name.into(), fake.var());
+            fake
+        });
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("A".into(), entry(0, DynValue::from(1.5f64)));
+        snapshot.insert("B".into(), entry(0, DynValue::from(-2.0f64)));
+
+        assert_eq!(
+            block_on(snapshot.restore(&mut registry, ["B", "A"])).unwrap(),
+            2
+        );
+        assert_eq!(vars[0].load(), DynValue::from(1.5f64));
+        assert_eq!(vars[1].load(), DynValue::from(-2.0f64));
+        assert!(matches!(
+            block_on(snapshot.restore(&mut registry, ["C"])),
+            Err(RestoreError::NotInSnapshot(_))
+        ));
+        snapshot.insert("C".into(), entry(0, DynValue::from(0.0f64)));
+        assert!(matches!(
+            block_on(snapshot.restore(&mut registry, ["A", "C"])),
+            Err(RestoreError::NotFound(_))
+        ));
+    }
+}
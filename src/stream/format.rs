@@ -0,0 +1,644 @@
+use crate::dynamic::{Conversion, DynArray, DynValue, Rounding};
+
+/// Conversion of values to integers for output.
+const ROUND: Conversion = Conversion {
+    rounding: Rounding::Nearest,
+    ..Conversion::LOSSY
+};
+
+/// Conversion flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// `-`
+    pub left: bool,
+    /// `0`
+    pub zero: bool,
+    /// `+`
+    pub plus: bool,
+    /// ` `
+    pub space: bool,
+    /// `#`
+    pub alt: bool,
+}
+
+/// Conversion specification like `%-8.3f`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spec {
+    pub flags: Flags,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    /// One of `d`, `i`, `u`, `x`, `X`, `o`, `c`, `f`, `e`, `E`, `g`, `G`, `s`.
+    pub conv: char,
+    /// `%*d`, input is parsed but ignored.
+    pub skip: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Piece {
+    Literal(String),
+    Value(Spec),
+}
+
+/// Printf-like format with at most one value conversion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Format {
+    pub pieces: Vec<Piece>,
+}
+
+fn number(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits.parse().ok()
+}
+
+fn pad(body: String, spec: &Spec, numeric: bool) -> String {
+    let width = spec.width.unwrap_or(0);
+    let len = body.chars().count();
+    if len >= width {
+        return body;
+    }
+    let fill = width - len;
+    if spec.flags.left {
+        body + &" ".repeat(fill)
+    } else if spec.flags.zero && numeric {
+        let sign_len = body.starts_with(['+', '-', ' ']) as usize;
+        let (sign, digits) = body.split_at(sign_len);
+        format!("{}{}{}", sign, "0".repeat(fill), digits)
+    } else {
+        " ".repeat(fill) + &body
+    }
+}
+
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.flags.plus {
+        "+"
+    } else if spec.flags.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// C-like exponent notation, e.g. `1.500000e+03`.
+fn exp(x: f64, precision: usize, upper: bool) -> String {
+    let text = format!("{:.*e}", precision, x);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let e = if upper { 'E' } else { 'e' };
+    format!(
+        "{}{}{}{:02}",
+        mantissa,
+        e,
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+fn general(x: f64, precision: usize, upper: bool, alt: bool) -> String {
+    let precision = precision.max(1);
+    let exponent: i32 = format!("{:.*e}", precision - 1, x)
+        .split_once('e')
+        .unwrap()
+        .1
+        .parse()
+        .unwrap();
+    let text = if exponent < -4 || exponent >= precision as i32 {
+        exp(x, precision - 1, upper)
+    } else {
+        format!("{:.*}", (precision as i32 - 1 - exponent) as usize, x)
+    };
+    if alt {
+        return text;
+    }
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(pos) => text.split_at(pos),
+        None => (text.as_str(), ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+fn format_float(x: f64, spec: &Spec) -> String {
+    let precision = spec.precision.unwrap_or(6);
+    let body = if x.is_nan() {
+        String::from("nan")
+    } else if x.is_infinite() {
+        String::from("inf")
+    } else {
+        match spec.conv {
+            'f' => format!("{:.*}", precision, x.abs()),
+            'e' | 'E' => exp(x.abs(), precision, spec.conv == 'E'),
+            _ => general(x.abs(), precision, spec.conv == 'G', spec.flags.alt),
+        }
+    };
+    pad(
+        format!(
+            "{}{}",
+            sign(spec, x.is_sign_negative() && !x.is_nan()),
+            body
+        ),
+        spec,
+        x.is_finite(),
+    )
+}
+
+fn format_int(x: i64, spec: &Spec) -> String {
+    let body = match spec.conv {
+        'd' | 'i' => format!("{}{}", sign(spec, x < 0), x.unsigned_abs()),
+        'u' => format!("{}", x as u64),
+        'x' => format!("{}{:x}", if spec.flags.alt { "0x" } else { "" }, x as u64),
+        'X' => format!("{}{:X}", if spec.flags.alt { "0X" } else { "" }, x as u64),
+        'o' => format!("{}{:o}", if spec.flags.alt { "0" } else { "" }, x as u64),
+        _ => char::from_u32(x as u32).unwrap_or('?').to_string(),
+    };
+    pad(body, spec, spec.conv != 'c')
+}
+
+/// Input cursor.
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+    /// Take longest prefix (limited by `width`) which chars satisfy `f(index, char)`.
+    fn take<F: FnMut(usize, char) -> bool>(&mut self, width: Option<usize>, mut f: F) -> &'a str {
+        let rest = self.rest();
+        let mut len = 0;
+        for (i, c) in rest.chars().enumerate() {
+            if width.is_some_and(|width| i >= width) || !f(i, c) {
+                break;
+            }
+            len += c.len_utf8();
+        }
+        self.pos += len;
+        &rest[..len]
+    }
+}
+
+/// Parsed element.
+enum Element {
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl Spec {
+    fn format(&self, value: &DynValue, separator: &str) -> Result<String, String> {
+        if self.conv == 's' {
+            let bytes = match value {
+                DynValue::Array(_) => value.get_array::<u8>(Conversion::LOSSY).unwrap(),
+                _ => {
+                    let x: f64 = value.get_scalar(Conversion::LOSSY).unwrap();
+                    return Ok(pad(x.to_string(), self, false));
+                }
+            };
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            let text = String::from_utf8_lossy(&bytes[..end]);
+            let text: String = match self.precision {
+                Some(precision) => text.chars().take(precision).collect(),
+                None => text.into_owned(),
+            };
+            return Ok(pad(text, self, false));
+        }
+        let elements: Vec<String> = if "feEgG".contains(self.conv) {
+            let values: Vec<f64> = match value {
+                DynValue::Array(_) => value.get_array(Conversion::LOSSY).unwrap(),
+                _ => vec![value.get_scalar(Conversion::LOSSY).unwrap()],
+            };
+            values.into_iter().map(|x| format_float(x, self)).collect()
+        } else {
+            let values: Vec<i64> = match value {
+                DynValue::Array(_) => value.get_array(ROUND).unwrap(),
+                _ => vec![value.get_scalar(ROUND).unwrap()],
+            };
+            values.into_iter().map(|x| format_int(x, self)).collect()
+        };
+        Ok(elements.join(separator))
+    }
+
+    fn scan(&self, scanner: &mut Scanner) -> Result<Element, String> {
+        if self.conv != 'c' {
+            scanner.skip_whitespace();
+        }
+        let width = self.width;
+        let token = match self.conv {
+            'd' | 'i' => scanner.take(width, |i, c| {
+                c.is_ascii_digit() || (i == 0 && "+-".contains(c))
+            }),
+            'u' => scanner.take(width, |_, c| c.is_ascii_digit()),
+            'x' | 'X' => {
+                let rest = scanner.rest();
+                if rest.starts_with("0x") || rest.starts_with("0X") {
+                    scanner.pos += 2;
+                }
+                scanner.take(width, |_, c| c.is_ascii_hexdigit())
+            }
+            'o' => scanner.take(width, |_, c| ('0'..='7').contains(&c)),
+            'c' => scanner.take(Some(1), |_, _| true),
+            's' => scanner.take(width, |_, c| !c.is_whitespace()),
+            _ => {
+                let mut prev = ' ';
+                scanner.take(width, |i, c| {
+                    let ok = c.is_ascii_digit()
+                        || c == '.'
+                        || "eE".contains(c)
+                        || ("+-".contains(c) && (i == 0 || "eE".contains(prev)))
+                        || "naifNAIF".contains(c);
+                    prev = c;
+                    ok
+                })
+            }
+        };
+        let invalid = || format!("Cannot parse '{}' as %{}", token, self.conv);
+        if token.is_empty() {
+            return Err(format!("Expected %{} at '{}'", self.conv, scanner.rest()));
+        }
+        Ok(match self.conv {
+            'd' | 'i' => Element::Int(token.parse().map_err(|_| invalid())?),
+            'u' => Element::Int(token.parse::<u64>().map_err(|_| invalid())? as i64),
+            'x' | 'X' => {
+                Element::Int(u64::from_str_radix(token, 16).map_err(|_| invalid())? as i64)
+            }
+            'o' => Element::Int(u64::from_str_radix(token, 8).map_err(|_| invalid())? as i64),
+            'c' => Element::Int(token.chars().next().unwrap() as i64),
+            's' => Element::Str(token.into()),
+            _ => Element::Float(token.parse().map_err(|_| invalid())?),
+        })
+    }
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                literal.push(c);
+                continue;
+            }
+            if chars.next_if_eq(&'%').is_some() {
+                literal.push('%');
+                continue;
+            }
+            let skip = chars.next_if_eq(&'*').is_some();
+            let mut flags = Flags::default();
+            while let Some(c) = chars.next_if(|c| "-0+ #".contains(*c)) {
+                match c {
+                    '-' => flags.left = true,
+                    '0' => flags.zero = true,
+                    '+' => flags.plus = true,
+                    ' ' => flags.space = true,
+                    _ => flags.alt = true,
+                }
+            }
+            let width = number(&mut chars);
+            let precision = chars
+                .next_if_eq(&'.')
+                .map(|_| number(&mut chars).unwrap_or(0));
+            let conv = match chars.next() {
+                Some(c) if "diuxXocfeEgGs".contains(c) => c,
+                Some(c) => return Err(format!("Unknown conversion '%{}'", c)),
+                None => return Err("Incomplete conversion".into()),
+            };
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(Piece::Value(Spec {
+                flags,
+                width,
+                precision,
+                conv,
+                skip,
+            }));
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        let values = pieces
+            .iter()
+            .filter(|piece| matches!(piece, Piece::Value(spec) if !spec.skip))
+            .count();
+        if values > 1 {
+            return Err("Only one value conversion is allowed".into());
+        }
+        Ok(Self { pieces })
+    }
+
+    /// Whether format refers to variable value.
+    pub fn has_value(&self) -> bool {
+        self.pieces
+            .iter()
+            .any(|piece| matches!(piece, Piece::Value(spec) if !spec.skip))
+    }
+
+    /// Format `value`, array elements are joined with `separator`.
+    pub fn format(&self, value: Option<&DynValue>, separator: &str) -> Result<String, String> {
+        let mut text = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => text.push_str(literal),
+                Piece::Value(spec) if spec.skip => (),
+                Piece::Value(spec) => {
+                    let value = value.ok_or("No value to format")?;
+                    text.push_str(&spec.format(value, separator)?);
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    /// Parse input `text` matching the whole format.
+    ///
+    /// If `array` is set then value is parsed as a list of elements separated by `separator` (or whitespace if empty).
+    pub fn scan(
+        &self,
+        text: &str,
+        separator: &str,
+        array: bool,
+    ) -> Result<Option<DynValue>, String> {
+        let mut scanner = Scanner { text, pos: 0 };
+        let mut value = None;
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => {
+                    if !scanner.rest().starts_with(literal.as_str()) {
+                        return Err(format!("Expected '{}', got '{}'", literal, scanner.rest()));
+                    }
+                    scanner.pos += literal.len();
+                }
+                Piece::Value(spec) if spec.skip => {
+                    spec.scan(&mut scanner)?;
+                }
+                Piece::Value(spec) if spec.conv == 's' => {
+                    let element = match spec.scan(&mut scanner)? {
+                        Element::Str(text) => text,
+                        _ => unreachable!(),
+                    };
+                    value = Some(DynValue::Array(DynArray::U8(element.into_bytes())));
+                }
+                Piece::Value(spec) => {
+                    let mut elements = vec![spec.scan(&mut scanner)?];
+                    if array {
+                        loop {
+                            let save = scanner.pos;
+                            if !separator.is_empty() {
+                                if !scanner.rest().starts_with(separator) {
+                                    break;
+                                }
+                                scanner.pos += separator.len();
+                            }
+                            match spec.scan(&mut scanner) {
+                                Ok(element) => elements.push(element),
+                                Err(_) => {
+                                    scanner.pos = save;
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    value = Some(elements_value(elements, array));
+                }
+            }
+        }
+        if !scanner.rest().is_empty() {
+            return Err(format!("Surplus input '{}'", scanner.rest()));
+        }
+        Ok(value)
+    }
+}
+
+fn elements_value(elements: Vec<Element>, array: bool) -> DynValue {
+    let (ints, floats): (Vec<_>, Vec<_>) = elements
+        .into_iter()
+        .map(|element| match element {
+            Element::Int(x) => (Some(x), x as f64),
+            Element::Float(x) => (None, x),
+            Element::Str(_) => unreachable!(),
+        })
+        .unzip();
+    match (ints.into_iter().collect::<Option<Vec<_>>>(), array) {
+        (Some(ints), true) => DynValue::Array(DynArray::I64(ints)),
+        (Some(ints), false) => DynValue::I64(ints[0]),
+        (None, true) => DynValue::Array(DynArray::F64(floats)),
+        (None, false) => DynValue::F64(floats[0]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: &str, value: DynValue) -> String {
+        Format::parse(format)
+            .unwrap()
+            .format(Some(&value), ",")
+            .unwrap()
+    }
+    fn scan(format: &str, text: &str, separator: &str, array: bool) -> Result<DynValue, String> {
+        Format::parse(format)
+            .unwrap()
+            .scan(text, separator, array)
+            .map(Option::unwrap)
+    }
+
+    #[test]
+    fn parse() {
+        let format = Format::parse("V%-+08.3f%%").unwrap();
+        assert_eq!(
+            format.pieces,
+            [
+                Piece::Literal("V".into()),
+                Piece::Value(Spec {
+                    flags: Flags {
+                        left: true,
+                        zero: true,
+                        plus: true,
+                        ..Flags::default()
+                    },
+                    width: Some(8),
+                    precision: Some(3),
+                    conv: 'f',
+                    skip: false,
+                }),
+                Piece::Literal("%".into()),
+            ]
+        );
+        assert!(format.has_value());
+        assert!(!Format::parse("A%*d").unwrap().has_value());
+        assert!(Format::parse("%*d %f").is_ok());
+
+        assert_eq!(Format::parse("%q").unwrap_err(), "Unknown conversion '%q'");
+        assert_eq!(Format::parse("A%5").unwrap_err(), "Incomplete conversion");
+        assert_eq!(
+            Format::parse("%d %f").unwrap_err(),
+            "Only one value conversion is allowed"
+        );
+    }
+
+    #[test]
+    fn format_int() {
+        assert_eq!(format("%d", DynValue::I32(-42)), "-42");
+        assert_eq!(format("%+d", DynValue::I32(42)), "+42");
+        assert_eq!(format("% d", DynValue::I32(42)), " 42");
+        assert_eq!(format("%05d", DynValue::I32(-42)), "-0042");
+        assert_eq!(format("[%-5d]", DynValue::I32(42)), "[42   ]");
+        assert_eq!(format("[%5d]", DynValue::I32(42)), "[   42]");
+        assert_eq!(format("%d", DynValue::F64(2.5)), "3");
+        assert_eq!(format("%x", DynValue::U16(255)), "ff");
+        assert_eq!(format("%#X", DynValue::U16(255)), "0XFF");
+        assert_eq!(format("%#o", DynValue::U8(8)), "010");
+        assert_eq!(format("%u", DynValue::I64(7)), "7");
+        assert_eq!(format("%c", DynValue::U8(b'A')), "A");
+    }
+
+    #[test]
+    fn format_float() {
+        assert_eq!(format("%f", DynValue::F64(1.5)), "1.500000");
+        assert_eq!(format("%.2f", DynValue::F64(-0.125)), "-0.12");
+        assert_eq!(format("%08.2f", DynValue::F64(-1.5)), "-0001.50");
+        assert_eq!(format("%+.1f", DynValue::I32(3)), "+3.0");
+        assert_eq!(format("%e", DynValue::F64(1500.0)), "1.500000e+03");
+        assert_eq!(format("%.2E", DynValue::F64(0.00123)), "1.23E-03");
+        assert_eq!(format("%g", DynValue::F64(0.0001)), "0.0001");
+        assert_eq!(format("%g", DynValue::F64(1e-5)), "1e-05");
+        assert_eq!(format("%g", DynValue::F64(123456.0)), "123456");
+        assert_eq!(format("%g", DynValue::F64(1234567.0)), "1.23457e+06");
+        assert_eq!(format("%#.3g", DynValue::F64(1.0)), "1.00");
+        assert_eq!(format("%5f", DynValue::F64(f64::NAN)), "  nan");
+        assert_eq!(format("%05f", DynValue::F64(f64::NEG_INFINITY)), " -inf");
+    }
+
+    #[test]
+    fn format_array() {
+        let value = DynValue::from(vec![1.0f64, -2.5]);
+        assert_eq!(format("A %.1f;", value.clone()), "A 1.0,-2.5;");
+        assert_eq!(
+            Format::parse("%g")
+                .unwrap()
+                .format(Some(&value), " ")
+                .unwrap(),
+            "1 -2.5"
+        );
+        assert_eq!(format("%d", DynValue::from(vec![1i32, 2, 3])), "1,2,3");
+    }
+
+    #[test]
+    fn format_string() {
+        let text = DynValue::from(b"hello\0junk".to_vec());
+        assert_eq!(format("'%s'", text.clone()), "'hello'");
+        assert_eq!(format("'%.3s'", text.clone()), "'hel'");
+        assert_eq!(format("'%-7s'", text), "'hello  '");
+        assert_eq!(format("%s", DynValue::F64(1.5)), "1.5");
+    }
+
+    #[test]
+    fn format_without_value() {
+        let format = Format::parse("*IDN?%*d").unwrap();
+        assert_eq!(format.format(None, "").unwrap(), "*IDN?");
+        assert_eq!(
+            Format::parse("%d").unwrap().format(None, "").unwrap_err(),
+            "No value to format"
+        );
+    }
+
+    #[test]
+    fn scan_scalar() {
+        assert_eq!(scan("%d", " -12", "", false), Ok(DynValue::I64(-12)));
+        assert_eq!(
+            scan("V=%f V", "V=1.5e3 V", "", false),
+            Ok(DynValue::F64(1500.0))
+        );
+        assert_eq!(scan("%f", "7", "", false), Ok(DynValue::F64(7.0)));
+        assert_eq!(scan("%x", "0x1F", "", false), Ok(DynValue::I64(31)));
+        assert_eq!(scan("%o", "17", "", false), Ok(DynValue::I64(15)));
+        assert_eq!(scan("%u", "42", "", false), Ok(DynValue::I64(42)));
+        assert_eq!(scan("%c", "A", "", false), Ok(DynValue::I64(65)));
+        assert_eq!(scan("%*2d%d", "1234", "", false), Ok(DynValue::I64(34)));
+        assert!(scan("%f", "nan", "", false)
+            .is_ok_and(|value| value.get_scalar::<f64>(Conversion::LOSSY).unwrap().is_nan()));
+    }
+
+    #[test]
+    fn scan_skip() {
+        assert_eq!(
+            scan("%*d,%f,%*s", "10,2.5,OK", "", false),
+            Ok(DynValue::F64(2.5))
+        );
+        assert_eq!(
+            Format::parse("OK%*d").unwrap().scan("OK 12", "", false),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn scan_array() {
+        assert_eq!(
+            scan("[%d]", "[1,2,3]", ",", true),
+            Ok(DynValue::from(vec![1i64, 2, 3]))
+        );
+        assert_eq!(
+            scan("%f", "1 2.5  3", "", true),
+            Ok(DynValue::from(vec![1.0f64, 2.5, 3.0]))
+        );
+        assert_eq!(
+            scan("%f;", "1, 2.5;", ", ", true),
+            Ok(DynValue::from(vec![1.0f64, 2.5]))
+        );
+        assert_eq!(scan("%d", "5", ",", true), Ok(DynValue::from(vec![5i64])));
+        // Separator is not consumed if no element follows it.
+        assert_eq!(
+            scan("%d,", "1,2,", ",", true),
+            Ok(DynValue::from(vec![1i64, 2]))
+        );
+        assert_eq!(
+            scan("%d", "1,2", ",", false),
+            Err("Surplus input ',2'".into())
+        );
+    }
+
+    #[test]
+    fn scan_string() {
+        assert_eq!(
+            scan("ID %s", "ID dev-1", "", false),
+            Ok(DynValue::from(b"dev-1".to_vec()))
+        );
+        assert_eq!(
+            scan("%3s%*s", "abcdef", "", false),
+            Ok(DynValue::from(b"abc".to_vec()))
+        );
+    }
+
+    #[test]
+    fn scan_errors() {
+        assert_eq!(
+            scan("V=%f", "I=1", "", false),
+            Err("Expected 'V=', got 'I=1'".into())
+        );
+        assert_eq!(scan("%d", "x", "", false), Err("Expected %d at 'x'".into()));
+        assert_eq!(
+            scan("%d", "+", "", false),
+            Err("Cannot parse '+' as %d".into())
+        );
+        assert_eq!(
+            scan("%d", "1.5", "", false),
+            Err("Surplus input '.5'".into())
+        );
+    }
+}
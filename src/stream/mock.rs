@@ -0,0 +1,81 @@
+//! Mock line-based instrument for testing.
+
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+};
+
+type Handler = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// TCP server that calls handler for each received line and sends back its reply (if any).
+pub struct MockInstrument {
+    listener: TcpListener,
+    terminator: Vec<u8>,
+    handler: Arc<Handler>,
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+fn serve(mut stream: TcpStream, terminator: &[u8], handler: &Handler) -> io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 256];
+    loop {
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            return Ok(());
+        }
+        buf.extend(&chunk[..len]);
+        while let Some(pos) = find(&buf, terminator) {
+            let line = String::from_utf8_lossy(&buf[..pos]).into_owned();
+            buf.drain(..(pos + terminator.len()));
+            if let Some(reply) = handler(&line) {
+                stream.write_all(reply.as_bytes())?;
+                stream.write_all(terminator)?;
+            }
+        }
+    }
+}
+
+impl MockInstrument {
+    pub fn bind<A, F>(addr: A, handler: F) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            terminator: b"\n".to_vec(),
+            handler: Arc::new(handler),
+        })
+    }
+    /// Line terminator for both directions, `\n` by default.
+    pub fn terminator(mut self, terminator: &[u8]) -> Self {
+        self.terminator = terminator.to_vec();
+        self
+    }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept clients in a separate thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name("mock-instrument".into())
+            .spawn(move || {
+                for stream in self.listener.incoming().flatten() {
+                    let (terminator, handler) = (self.terminator.clone(), self.handler.clone());
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, &terminator, &*handler) {
+                            log::debug!("Mock instrument client disconnected: {}", err);
+                        }
+                    });
+                }
+            })
+            .unwrap()
+    }
+}
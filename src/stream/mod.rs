@@ -0,0 +1,467 @@
+//! Line protocol engine over TCP, similar to EPICS StreamDevice.
+//!
+//! Protocols (see [`parse_protocols`]) are bound to variables and executed when variable is processed:
+//! output records (e.g. `ao`) run protocol when written by IOC and take value parsed by `in` command (if any, e.g. readback),
+//! input records (e.g. `ai`) are processed periodically and take value parsed by `in` command.
+//! Protocol failure rejects processing with error message.
+
+mod format;
+pub mod mock;
+mod protocol;
+
+pub use format::{Flags, Format, Piece, Spec};
+pub use protocol::{parse_protocols, Command, Protocol, Protocols, Settings};
+
+use crate::{
+    driver::{self, Polled},
+    dynamic::DynValue,
+    registry::GetDowncastError,
+    variable::Direction,
+    Registry,
+};
+use derive_more::{Display, Error, From};
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[derive(Debug, Display, Error, From)]
+pub enum StreamError {
+    #[display(fmt = "COMM: {}", "_0")]
+    Io(io::Error),
+    #[display(fmt = "COMM: Timeout")]
+    Timeout,
+    #[display(fmt = "COMM: Disconnected")]
+    Disconnected,
+    #[display(fmt = "Format: {}", "_0")]
+    #[from(ignore)]
+    Format(#[error(not(source))] String),
+    #[display(fmt = "Mismatch: {}", "_0")]
+    #[from(ignore)]
+    Mismatch(#[error(not(source))] String),
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum DriverError {
+    Var(GetDowncastError),
+    #[display(fmt = "PV '{}': Unknown protocol '{}'", "name", "protocol")]
+    #[from(ignore)]
+    UnknownProtocol {
+        name: String,
+        protocol: String,
+    },
+    #[display(fmt = "Cannot resolve address '{}'", "_0")]
+    #[from(ignore)]
+    Address(#[error(not(source))] String),
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+/// Connection to device that is re-established after errors.
+pub struct Connection {
+    addr: SocketAddr,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(addr: SocketAddr, timeout: Duration) -> Self {
+        Self {
+            addr,
+            timeout,
+            stream: None,
+            buf: Vec::new(),
+        }
+    }
+
+    fn stream(&mut self) -> io::Result<&mut TcpStream> {
+        match self.stream {
+            Some(ref mut stream) => Ok(stream),
+            None => {
+                let stream = TcpStream::connect_timeout(&self.addr, self.timeout)?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                stream.set_nodelay(true)?;
+                Ok(self.stream.insert(stream))
+            }
+        }
+    }
+
+    /// Discard unread input, e.g. late reply to a request that has timed out.
+    fn discard(&mut self) -> Result<(), StreamError> {
+        self.buf.clear();
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => return Ok(()),
+        };
+        let mut chunk = [0; 256];
+        stream.set_nonblocking(true)?;
+        let result = loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => break Err(StreamError::Disconnected),
+                Ok(_) => continue,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(err) => break Err(err.into()),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        result
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), StreamError> {
+        Ok(self.stream()?.write_all(data)?)
+    }
+
+    /// Receive a single reply terminated with `in_terminator`.
+    ///
+    /// If terminator is empty, then reply ends when there is no more input within `read_timeout`.
+    fn receive(&mut self, settings: &Settings) -> Result<String, StreamError> {
+        let terminator = &settings.in_terminator;
+        let mut chunk = [0; 256];
+        let mut received = false;
+        loop {
+            if !terminator.is_empty() {
+                if let Some(pos) = find(&self.buf, terminator) {
+                    let line = String::from_utf8_lossy(&self.buf[..pos]).into_owned();
+                    self.buf.drain(..(pos + terminator.len()));
+                    return Ok(line);
+                }
+            }
+            let timeout = match received {
+                false => settings.reply_timeout,
+                true => settings.read_timeout,
+            };
+            let stream = self.stream()?;
+            stream.set_read_timeout(Some(timeout))?;
+            match stream.read(&mut chunk) {
+                Ok(0) => return Err(StreamError::Disconnected),
+                Ok(len) => {
+                    self.buf.extend(&chunk[..len]);
+                    received = true;
+                }
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if received && terminator.is_empty() {
+                        let line = String::from_utf8_lossy(&self.buf).into_owned();
+                        self.buf.clear();
+                        return Ok(line);
+                    }
+                    return Err(StreamError::Timeout);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn run_once(
+        &mut self,
+        protocol: &Protocol,
+        value: Option<&DynValue>,
+        array: bool,
+    ) -> Result<Option<DynValue>, StreamError> {
+        let settings = &protocol.settings;
+        let mut result = None;
+        for command in &protocol.commands {
+            match command {
+                Command::Out(format) => {
+                    let mut data = format
+                        .format(value, &settings.separator)
+                        .map_err(StreamError::Format)?
+                        .into_bytes();
+                    data.extend(&settings.out_terminator);
+                    self.discard()?;
+                    self.send(&data)?;
+                }
+                Command::In(format) => {
+                    let line = self.receive(settings)?;
+                    let parsed = format
+                        .scan(&line, &settings.separator, array)
+                        .map_err(StreamError::Mismatch)?;
+                    result = parsed.or(result);
+                }
+                Command::Wait(duration) => thread::sleep(*duration),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Execute protocol, repeating it on failure up to `max_retries` times.
+    ///
+    /// `value` is used by `out` formats, returns value parsed by `in` commands (if any).
+    /// If `array` is set then values are parsed as arrays.
+    pub fn run(
+        &mut self,
+        protocol: &Protocol,
+        value: Option<&DynValue>,
+        array: bool,
+    ) -> Result<Option<DynValue>, StreamError> {
+        let mut attempt = 0;
+        loop {
+            match self.run_once(protocol, value, array) {
+                Ok(result) => return Ok(result),
+                Err(err) => {
+                    if matches!(err, StreamError::Io(_) | StreamError::Disconnected) {
+                        self.stream = None;
+                    }
+                    if attempt >= protocol.settings.max_retries {
+                        return Err(err);
+                    }
+                    log::debug!("Protocol failed, retrying: {}", err);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Binding of protocol to variable.
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub name: String,
+    pub protocol: String,
+    /// Processing period of input variables.
+    pub period: Duration,
+}
+
+/// Stream driver of a single device.
+pub struct Driver {
+    addr: String,
+    timeout: Duration,
+    protocols: Protocols,
+    bindings: Vec<Binding>,
+}
+
+impl Driver {
+    pub fn new(addr: &str, protocols: Protocols) -> Self {
+        Self {
+            addr: addr.into(),
+            timeout: Duration::from_secs(1),
+            protocols,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Connection and write timeout, 1 second by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Bind `protocol` to variable `name`, input variables are processed every second.
+    pub fn bind(self, name: &str, protocol: &str) -> Self {
+        self.bind_periodic(name, protocol, Duration::from_secs(1))
+    }
+    /// Bind `protocol` to variable `name`, input variables are processed with `period`.
+    pub fn bind_periodic(mut self, name: &str, protocol: &str, period: Duration) -> Self {
+        self.bindings.push(Binding {
+            name: name.into(),
+            protocol: protocol.into(),
+            period,
+        });
+        self
+    }
+
+    /// Take bound variables from `registry` and start polling and writing in separate threads.
+    pub fn start(
+        self,
+        registry: &mut Registry,
+    ) -> Result<Vec<thread::JoinHandle<()>>, DriverError> {
+        let addr = driver::resolve(&self.addr, None)
+            .ok_or_else(|| DriverError::Address(self.addr.clone()))?;
+        // Check all bindings first, so that registry is left intact on error.
+        let mut bindings = Vec::new();
+        for binding in self.bindings {
+            let protocol = match self.protocols.get(&binding.protocol) {
+                Some(protocol) => protocol.clone(),
+                None => {
+                    return Err(DriverError::UnknownProtocol {
+                        name: registry.full_name(&binding.name),
+                        protocol: binding.protocol,
+                    })
+                }
+            };
+            let direction = registry.try_get(&binding.name)?.direction();
+            bindings.push((binding, protocol, direction));
+        }
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for (binding, protocol, direction) in bindings {
+            match direction {
+                Direction::Output => {
+                    outputs.push((protocol, registry.remove_downcast(&binding.name)?))
+                }
+                Direction::Input => inputs.push(Polled {
+                    source: protocol,
+                    period: binding.period,
+                    var: registry.remove_downcast(&binding.name)?,
+                }),
+            }
+        }
+
+        let conn = Arc::new(Mutex::new(Connection::new(addr, self.timeout)));
+        let mut handles = Vec::new();
+        if !inputs.is_empty() {
+            let conn = conn.clone();
+            handles.push(driver::spawn("stream-poll", move || {
+                driver::poll(inputs, |protocol, info, value| {
+                    let array = info.max_len != 0;
+                    let result = conn.lock().unwrap().run(protocol, Some(&value), array);
+                    result.map_err(|err| err.to_string())
+                })
+            }));
+        }
+        for (protocol, var) in outputs {
+            let conn = conn.clone();
+            handles.push(driver::spawn("stream-write", move || {
+                driver::write(var, |info, value| {
+                    let array = info.max_len != 0;
+                    let result = conn.lock().unwrap().run(&protocol, Some(&value), array);
+                    result.map_err(|err| err.to_string())
+                })
+            }));
+        }
+        Ok(handles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        driver::wait_for,
+        testing::FakeVar,
+        variable::{Direction, Type},
+    };
+    use mock::MockInstrument;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn instrument(terminator: &[u8], silent: Arc<AtomicUsize>) -> SocketAddr {
+        let mock = MockInstrument::bind("127.0.0.1:0", move |line| match line {
+            "VOLT?" => Some("1.25".into()),
+            "*IDN?" => Some("MOCK".into()),
+            "SLOW?" => {
+                thread::sleep(Duration::from_millis(100));
+                Some("2.5".into())
+            }
+            "SILENT" => {
+                silent.fetch_add(1, Ordering::SeqCst);
+                None
+            }
+            _ => {
+                // Voltage is limited to 2 V.
+                let value: f64 = line.strip_prefix("VOLT ")?.parse().ok()?;
+                Some(format!("VOLT {}", value.min(2.0)))
+            }
+        })
+        .unwrap()
+        .terminator(terminator);
+        let addr = mock.local_addr().unwrap();
+        mock.spawn();
+        addr
+    }
+
+    #[test]
+    fn connection() {
+        let silent = Arc::new(AtomicUsize::new(0));
+        let addr = instrument(b"\r\n", silent.clone());
+        let protocols = parse_protocols(
+            r#"
+            Terminator = CR LF;
+            ReplyTimeout = 50;
+            get { out "VOLT?"; in "%f"; }
+            set { out "VOLT %.1f"; in "VOLT %*f"; }
+            mismatch { out "VOLT?"; in "V=%f"; }
+            silent { MaxRetries = 1; out "SILENT"; in "%f"; }
+            slow { out "SLOW?"; in "%f"; }
+            "#,
+        )
+        .unwrap();
+        let mut conn = Connection::new(addr, Duration::from_secs(10));
+
+        let result = conn.run(protocols.get("get").unwrap(), None, false);
+        assert_eq!(result.unwrap(), Some(DynValue::F64(1.25)));
+        let result = conn.run(protocols.get("get").unwrap(), None, true);
+        assert_eq!(result.unwrap(), Some(DynValue::from(vec![1.25f64])));
+        let value = DynValue::F64(1.0);
+        let result = conn.run(protocols.get("set").unwrap(), Some(&value), false);
+        assert_eq!(result.unwrap(), None);
+
+        let result = conn.run(protocols.get("mismatch").unwrap(), None, false);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Mismatch: Expected 'V=', got '1.25'"
+        );
+        let result = conn.run(protocols.get("silent").unwrap(), None, false);
+        assert!(matches!(result, Err(StreamError::Timeout)));
+        assert_eq!(silent.load(Ordering::SeqCst), 2);
+
+        // Connection is still usable after errors.
+        let result = conn.run(protocols.get("get").unwrap(), None, false);
+        assert_eq!(result.unwrap(), Some(DynValue::F64(1.25)));
+
+        // Late reply is not taken as reply to the next request.
+        let result = conn.run(protocols.get("slow").unwrap(), None, false);
+        assert!(matches!(result, Err(StreamError::Timeout)));
+        thread::sleep(Duration::from_millis(200));
+        let result = conn.run(protocols.get("get").unwrap(), None, false);
+        assert_eq!(result.unwrap(), Some(DynValue::F64(1.25)));
+    }
+
+    #[test]
+    fn driver() {
+        let addr = instrument(b"\n", Arc::default());
+        let protocols = parse_protocols(
+            r#"
+            getVolt { out "VOLT?"; in "%f"; }
+            setVolt { out "VOLT %.2f"; in "VOLT %f"; }
+            getIdn { out "*IDN?"; in "%s"; }
+            "#,
+        )
+        .unwrap();
+
+        let mut registry = Registry::default();
+        let mut add = |var: FakeVar| {
+            let var = var.auto_process().leak();
+            registry.insert(var.var().name().into(), var.var());
+            var
+        };
+        let volt = add(FakeVar::new("ST:VOLT", Type::F64, 0, Direction::Input));
+        let idn = add(FakeVar::new("ST:IDN", Type::U8, 16, Direction::Input));
+        let set = add(FakeVar::new("ST:SET", Type::F64, 0, Direction::Output).scalar(2.5f64));
+
+        // Registry is left intact if any binding is invalid.
+        assert!(matches!(
+            Driver::new(&addr.to_string(), protocols.clone())
+                .bind("ST:IDN", "getIdn")
+                .bind("ST:VOLT", "getCurr")
+                .start(&mut registry),
+            Err(DriverError::UnknownProtocol { .. })
+        ));
+        assert_eq!(registry.len(), 3);
+        let period = Duration::from_millis(10);
+        Driver::new(&addr.to_string(), protocols)
+            .bind_periodic("ST:VOLT", "getVolt", period)
+            .bind_periodic("ST:IDN", "getIdn", period)
+            .bind("ST:SET", "setVolt")
+            .start(&mut registry)
+            .unwrap();
+
+        wait_for(|| volt.load() == DynValue::F64(1.25));
+        wait_for(|| idn.load() == DynValue::from(b"MOCK".to_vec()));
+
+        // Value parsed from reply is stored to output record.
+        set.process();
+        wait_for(|| !set.commits.lock().unwrap().is_empty());
+        assert_eq!(set.commits.lock().unwrap()[0], Ok(()));
+        assert_eq!(set.load(), DynValue::F64(2.0));
+    }
+}
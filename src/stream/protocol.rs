@@ -0,0 +1,299 @@
+use super::format::Format;
+use crate::db::{ParseError, Parser, Token};
+use std::{collections::HashMap, time::Duration};
+
+/// Communication settings, may be set globally or inside protocol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub in_terminator: Vec<u8>,
+    pub out_terminator: Vec<u8>,
+    /// Timeout of the first byte of reply.
+    pub reply_timeout: Duration,
+    /// Timeout between bytes of reply.
+    pub read_timeout: Duration,
+    /// Number of protocol repetitions after failure.
+    pub max_retries: u32,
+    /// Separator of array elements.
+    pub separator: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            in_terminator: b"\n".to_vec(),
+            out_terminator: b"\n".to_vec(),
+            reply_timeout: Duration::from_millis(1000),
+            read_timeout: Duration::from_millis(100),
+            max_retries: 0,
+            separator: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Send formatted value.
+    Out(Format),
+    /// Receive and parse value.
+    In(Format),
+    Wait(Duration),
+}
+
+/// Sequence of commands executed on processing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Protocol {
+    pub settings: Settings,
+    pub commands: Vec<Command>,
+}
+
+/// Protocols by names.
+#[derive(Clone, Debug, Default)]
+pub struct Protocols(HashMap<String, Protocol>);
+
+impl Protocols {
+    pub fn get(&self, name: &str) -> Option<&Protocol> {
+        self.0.get(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+/// Replace escape sequences (`\r`, `\n`, `\t`, `\xHH` etc.) in quoted string.
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('e') => bytes.push(0x1b),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(
+                    u8::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("Invalid escape '\\x{}'", hex))?,
+                );
+            }
+            Some(c) => return Err(format!("Unknown escape '\\{}'", c)),
+            None => return Err("Trailing backslash".into()),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Byte sequence given by quoted strings and bare names of control characters.
+fn bytes(parser: &mut Parser) -> Result<Vec<u8>, ParseError> {
+    let mut bytes = Vec::new();
+    loop {
+        match parser.next()? {
+            Some(Token::Punct(';')) => return Ok(bytes),
+            Some(Token::Word(word)) => match word.as_str() {
+                "CR" => bytes.push(b'\r'),
+                "LF" | "NL" => bytes.push(b'\n'),
+                "NUL" => bytes.push(0),
+                "STX" => bytes.push(0x02),
+                "ETX" => bytes.push(0x03),
+                "ESC" => bytes.push(0x1b),
+                _ => bytes.extend(word.as_bytes()),
+            },
+            Some(Token::Quoted(text)) => {
+                bytes.extend(unescape(&text).map_err(|message| parser.error(message))?)
+            }
+            other => return Err(parser.unexpected(other, "string or ';'")),
+        }
+    }
+}
+
+fn string(parser: &mut Parser) -> Result<String, ParseError> {
+    let bytes = bytes(parser)?;
+    String::from_utf8(bytes).map_err(|_| parser.error("Invalid UTF-8".into()))
+}
+
+fn millis(parser: &mut Parser) -> Result<Duration, ParseError> {
+    let text = string(parser)?;
+    let ms = text
+        .parse()
+        .map_err(|_| parser.error(format!("Invalid number '{}'", text)))?;
+    Ok(Duration::from_millis(ms))
+}
+
+/// Parse `NAME = VALUE;` assignment, `name` is already consumed.
+fn setting(parser: &mut Parser, settings: &mut Settings, name: &str) -> Result<(), ParseError> {
+    parser.expect_punct('=')?;
+    match name {
+        "Terminator" => {
+            settings.in_terminator = bytes(parser)?;
+            settings.out_terminator = settings.in_terminator.clone();
+        }
+        "InTerminator" => settings.in_terminator = bytes(parser)?,
+        "OutTerminator" => settings.out_terminator = bytes(parser)?,
+        "ReplyTimeout" => settings.reply_timeout = millis(parser)?,
+        "ReadTimeout" => settings.read_timeout = millis(parser)?,
+        "MaxRetries" => {
+            let text = string(parser)?;
+            settings.max_retries = text
+                .parse()
+                .map_err(|_| parser.error(format!("Invalid number '{}'", text)))?;
+        }
+        "Separator" => settings.separator = string(parser)?,
+        _ => return Err(parser.error(format!("Unknown setting '{}'", name))),
+    }
+    Ok(())
+}
+
+fn format(parser: &mut Parser) -> Result<Format, ParseError> {
+    let text = string(parser)?;
+    Format::parse(&text).map_err(|message| parser.error(message))
+}
+
+fn protocol(parser: &mut Parser, settings: Settings) -> Result<Protocol, ParseError> {
+    let mut protocol = Protocol {
+        settings,
+        commands: Vec::new(),
+    };
+    loop {
+        let word = match parser.next()? {
+            Some(Token::Punct('}')) => return Ok(protocol),
+            Some(Token::Word(word)) => word,
+            other => return Err(parser.unexpected(other, "command or '}'")),
+        };
+        let command = match word.as_str() {
+            "out" => Command::Out(format(parser)?),
+            "in" => Command::In(format(parser)?),
+            "wait" => Command::Wait(millis(parser)?),
+            _ => {
+                setting(parser, &mut protocol.settings, &word)?;
+                continue;
+            }
+        };
+        protocol.commands.push(command);
+    }
+}
+
+/// Parse protocol file.
+///
+/// ```text
+/// Terminator = CR LF;
+/// getVolt { out "VOLT?"; in "%f"; }
+/// setVolt { ReplyTimeout = 500; out "VOLT %.3f"; in "OK"; }
+/// ```
+///
+/// Supported commands are `out`, `in` and `wait` (in milliseconds),
+/// settings are `Terminator`, `InTerminator`, `OutTerminator`, `ReplyTimeout`, `ReadTimeout`, `MaxRetries` and `Separator`.
+pub fn parse_protocols(text: &str) -> Result<Protocols, ParseError> {
+    let mut parser = Parser::new(text, "{};=").keep_quotes();
+    let mut settings = Settings::default();
+    let mut protocols = Protocols::default();
+    while let Some(token) = parser.next()? {
+        let name = match token {
+            Token::Word(name) => name,
+            other => return Err(parser.unexpected(Some(other), "protocol or setting name")),
+        };
+        match parser.peek()? {
+            Some(Token::Punct('{')) => {
+                parser.next()?;
+                let protocol = protocol(&mut parser, settings.clone())?;
+                protocols.0.insert(name, protocol);
+            }
+            _ => setting(&mut parser, &mut settings, &name)?,
+        }
+    }
+    Ok(protocols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let protocols = parse_protocols(
+            r#"
+            # Global settings apply to protocols defined after them.
+            Terminator = CR LF;
+            getVolt { out "VOLT?"; in "%f"; }
+            ReplyTimeout = 200;
+            setVolt {
+                OutTerminator = "\x03";
+                MaxRetries = 2;
+                Separator = ", ";
+                out "VOLT %.3f"; wait 10; in "OK";
+            }
+            "#,
+        )
+        .unwrap();
+        let mut names: Vec<_> = protocols.names().collect();
+        names.sort();
+        assert_eq!(names, ["getVolt", "setVolt"]);
+
+        let get = protocols.get("getVolt").unwrap();
+        assert_eq!(
+            get.settings,
+            Settings {
+                in_terminator: b"\r\n".to_vec(),
+                out_terminator: b"\r\n".to_vec(),
+                ..Settings::default()
+            }
+        );
+        assert_eq!(
+            get.commands,
+            [
+                Command::Out(Format::parse("VOLT?").unwrap()),
+                Command::In(Format::parse("%f").unwrap()),
+            ]
+        );
+
+        let set = protocols.get("setVolt").unwrap();
+        assert_eq!(set.settings.in_terminator, b"\r\n");
+        assert_eq!(set.settings.out_terminator, [0x03]);
+        assert_eq!(set.settings.reply_timeout, Duration::from_millis(200));
+        assert_eq!(set.settings.max_retries, 2);
+        assert_eq!(set.settings.separator, ", ");
+        assert_eq!(set.commands[1], Command::Wait(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn control_names() {
+        let protocols = parse_protocols(
+            r#"
+            Terminator = "CR" CR "\n" ESC;
+            p { out "LF"; }
+            "#,
+        )
+        .unwrap();
+        let p = protocols.get("p").unwrap();
+        assert_eq!(p.settings.in_terminator, b"CR\r\n\x1b");
+        assert_eq!(p.commands, [Command::Out(Format::parse("LF").unwrap())]);
+    }
+
+    #[test]
+    fn unescape_strings() {
+        assert_eq!(unescape(r"a\r\n\t\0\e\x41").unwrap(), b"a\r\n\t\0\x1bA");
+        assert_eq!(unescape(r"\xZZ").unwrap_err(), r"Invalid escape '\xZZ'");
+        assert_eq!(unescape(r"\q").unwrap_err(), r"Unknown escape '\q'");
+        assert_eq!(unescape("\\").unwrap_err(), "Trailing backslash");
+    }
+
+    #[test]
+    fn errors() {
+        for text in [
+            "Timeout = 1;",
+            "ReplyTimeout = fast;",
+            "p { out \"%q\"; }",
+            "p { in \"%d\" }",
+            "p { out \"\\q\"; }",
+            "p { out \"A\";",
+            "{",
+        ] {
+            assert!(parse_protocols(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Fake C side of variables for unit tests.
+
+use crate::{
+    dynamic::DynValue,
+    import::{FerVar, FerVarRawInfo, FerVarStatus, FerVarValue},
+    variable::{Direction, SystemVariable, Type},
+    Variable,
+};
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, c_void, CString},
+    slice,
+    str::from_utf8,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// Variable as it is stored on C side.
+pub(crate) struct FakeVar {
+    name: CString,
+    info: FerVarRawInfo,
+    dir: u32,
+    rtyp: CString,
+    value: Box<[u64]>,
+    fields: HashMap<String, (u32, Box<[u64]>)>,
+    user_data: AtomicPtr<c_void>,
+    locked: AtomicBool,
+    auto_process: bool,
+    pub requests: AtomicUsize,
+    pub commits: Mutex<Vec<Result<(), String>>>,
+}
+
+impl FakeVar {
+    pub fn new(name: &str, type_: Type, max_len: usize, dir: Direction) -> Self {
+        let rtyp = match (dir, max_len) {
+            (Direction::Input, 0) => "ai",
+            (Direction::Output, 0) => "ao",
+            (Direction::Input, _) => "aai",
+            (Direction::Output, _) => "aao",
+        };
+        Self {
+            name: CString::new(name).unwrap(),
+            info: FerVarRawInfo {
+                type_: type_ as u32,
+                max_len,
+            },
+            dir: dir as u32,
+            rtyp: CString::new(rtyp).unwrap(),
+            // Enough for array header and elements of any type.
+            value: vec![0; max_len + 2].into_boxed_slice(),
+            fields: HashMap::new(),
+            user_data: AtomicPtr::new(std::ptr::null_mut()),
+            locked: AtomicBool::new(false),
+            auto_process: false,
+            requests: AtomicUsize::new(0),
+            commits: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn raw_name(mut self, name: &[u8]) -> Self {
+        self.name = CString::new(name).unwrap();
+        self
+    }
+    pub fn raw_type(mut self, raw: u32) -> Self {
+        self.info.type_ = raw;
+        self
+    }
+    pub fn raw_dir(mut self, raw: u32) -> Self {
+        self.dir = raw;
+        self
+    }
+    pub fn field<T: Copy>(mut self, name: &str, type_: Type, value: T) -> Self {
+        let mut data = vec![0u64].into_boxed_slice();
+        unsafe { (data.as_mut_ptr() as *mut T).write(value) };
+        self.fields.insert(name.into(), (type_ as u32, data));
+        self
+    }
+    pub fn raw_field_type(mut self, name: &str, raw: u32) -> Self {
+        self.fields.get_mut(name).unwrap().0 = raw;
+        self
+    }
+    pub fn field_value<T: Copy>(&self, name: &str) -> T {
+        unsafe { (self.fields[name].1.as_ptr() as *const T).read() }
+    }
+    pub fn record_type(mut self, rtyp: &[u8]) -> Self {
+        self.rtyp = CString::new(rtyp).unwrap();
+        self
+    }
+
+    /// Set initial value of scalar variable.
+    pub fn scalar<T: Copy>(mut self, value: T) -> Self {
+        unsafe { (self.value.as_mut_ptr() as *mut T).write(value) };
+        self
+    }
+
+    /// Process record on request and complete processing on commit, as IOC does.
+    pub fn auto_process(mut self) -> Self {
+        self.auto_process = true;
+        self
+    }
+
+    /// Leak variable and initialize it as IOC does.
+    pub fn leak(self) -> &'static Self {
+        let this = Box::leak(Box::new(self));
+        unsafe { SystemVariable::from_raw(this.raw()).initialize() };
+        this
+    }
+    fn raw(&self) -> *mut FerVar {
+        self as *const Self as *mut FerVar
+    }
+    fn from_raw(raw: *mut FerVar) -> &'static Self {
+        unsafe { &*(raw as *const Self) }
+    }
+
+    /// New handle to the variable.
+    pub fn var(&'static self) -> Variable {
+        unsafe { Variable::from_raw(self.raw()) }
+    }
+
+    /// Current value.
+    pub fn load(&'static self) -> DynValue {
+        let var = self.var();
+        let _guard = var.lock();
+        unsafe { var.load_dyn() }
+    }
+
+    /// Begin record processing from IOC side (e.g. on CA put).
+    #[cfg(any(
+        feature = "snapshot",
+        feature = "stream",
+        feature = "modbus",
+        feature = "scpi"
+    ))]
+    pub fn process(&'static self) {
+        self.locked(|var| unsafe { var.proc_begin() });
+    }
+    fn locked(&self, f: impl FnOnce(&mut SystemVariable)) {
+        fer_var_lock(self.raw());
+        f(&mut unsafe { SystemVariable::from_raw(self.raw()) });
+        fer_var_unlock(self.raw());
+    }
+    /// Run `f` in another thread, as IOC callbacks are not called from inside of calls to IOC.
+    fn defer(&'static self, f: fn(&mut SystemVariable)) {
+        thread::spawn(move || self.locked(f));
+    }
+}
+
+#[no_mangle]
+extern "C" fn fer_app_exit(_code: c_int) {}
+
+#[no_mangle]
+extern "C" fn fer_var_request(var: *mut FerVar) {
+    let this = FakeVar::from_raw(var);
+    this.requests.fetch_add(1, Ordering::SeqCst);
+    if this.auto_process {
+        this.defer(|var| unsafe { var.proc_begin() });
+    }
+}
+#[no_mangle]
+extern "C" fn fer_var_commit(
+    var: *mut FerVar,
+    st: FerVarStatus,
+    msg: *const c_char,
+    msg_len: usize,
+) {
+    let status = match st {
+        FerVarStatus::Ok => Ok(()),
+        FerVarStatus::Error => {
+            let msg = unsafe { slice::from_raw_parts(msg as *const u8, msg_len) };
+            Err(from_utf8(msg).unwrap().into())
+        }
+    };
+    let this = FakeVar::from_raw(var);
+    this.commits.lock().unwrap().push(status);
+    if this.auto_process {
+        this.defer(|var| unsafe { var.proc_end() });
+    }
+}
+
+#[no_mangle]
+extern "C" fn fer_var_lock(var: *mut FerVar) {
+    let locked = &FakeVar::from_raw(var).locked;
+    while locked
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        std::hint::spin_loop();
+    }
+}
+#[no_mangle]
+extern "C" fn fer_var_unlock(var: *mut FerVar) {
+    FakeVar::from_raw(var)
+        .locked
+        .store(false, Ordering::Release);
+}
+
+#[no_mangle]
+extern "C" fn fer_var_name(var: *mut FerVar) -> *const c_char {
+    FakeVar::from_raw(var).name.as_ptr()
+}
+#[no_mangle]
+extern "C" fn fer_var_info(var: *mut FerVar) -> FerVarRawInfo {
+    FakeVar::from_raw(var).info
+}
+#[no_mangle]
+extern "C" fn fer_var_dir(var: *mut FerVar) -> u32 {
+    FakeVar::from_raw(var).dir
+}
+#[no_mangle]
+extern "C" fn fer_var_rtyp(var: *mut FerVar) -> *const c_char {
+    FakeVar::from_raw(var).rtyp.as_ptr()
+}
+#[no_mangle]
+extern "C" fn fer_var_value(var: *mut FerVar) -> *mut FerVarValue {
+    FakeVar::from_raw(var).value.as_ptr() as *mut FerVarValue
+}
+#[no_mangle]
+extern "C" fn fer_var_field(
+    var: *mut FerVar,
+    name: *const c_char,
+    name_len: usize,
+    type_: *mut u32,
+) -> *mut c_void {
+    let name = unsafe { slice::from_raw_parts(name as *const u8, name_len) };
+    match FakeVar::from_raw(var).fields.get(from_utf8(name).unwrap()) {
+        Some((raw, value)) => {
+            unsafe { *type_ = *raw };
+            value.as_ptr() as *mut c_void
+        }
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+extern "C" fn fer_var_user_data(var: *mut FerVar) -> *mut c_void {
+    FakeVar::from_raw(var).user_data.load(Ordering::Acquire)
+}
+#[no_mangle]
+extern "C" fn fer_var_set_user_data(var: *mut FerVar, user_data: *mut c_void) {
+    FakeVar::from_raw(var)
+        .user_data
+        .store(user_data, Ordering::Release);
+}
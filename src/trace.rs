@@ -0,0 +1,175 @@
+//! Tracing spans of variable processing cycles.
+
+use crate::variable::{Status, Variable};
+use std::sync::Mutex;
+use tracing::{field::Empty, Level, Span};
+
+/// Span of the current processing cycle of a variable.
+///
+/// Cycle starts either on request or on processing begin (if processing is initiated by IOC),
+/// and ends when processing is complete.
+#[derive(Default)]
+pub(crate) struct Cycle {
+    span: Mutex<Option<Span>>,
+}
+
+fn new_span(var: &Variable) -> Span {
+    let info = var.info();
+    tracing::span!(
+        Level::DEBUG,
+        "processing",
+        name = var.name(),
+        type_ = ?info.type_,
+        max_len = info.max_len,
+        status = Empty,
+        message = Empty,
+    )
+}
+
+impl Cycle {
+    fn with_span<F: FnOnce(&Span)>(&self, var: &Variable, f: F) {
+        let mut span = self.span.lock().unwrap();
+        f(span.get_or_insert_with(|| new_span(var)));
+    }
+
+    pub fn on_request(&self, var: &Variable) {
+        self.with_span(
+            var,
+            |span| tracing::event!(parent: span, Level::DEBUG, "request"),
+        );
+    }
+    pub fn on_proc_begin(&self, var: &Variable) {
+        self.with_span(
+            var,
+            |span| tracing::event!(parent: span, Level::DEBUG, "proc_begin"),
+        );
+    }
+    pub fn on_commit(&self, var: &Variable, status: Status<'_>) {
+        self.with_span(var, |span| {
+            match status {
+                Ok(()) => span.record("status", "ok"),
+                Err(message) => span.record("status", "error").record("message", message),
+            };
+            tracing::event!(parent: span, Level::DEBUG, "commit")
+        });
+    }
+    pub fn on_proc_end(&self, var: &Variable) {
+        if let Some(span) = self.span.lock().unwrap().take() {
+            tracing::event!(parent: &span, Level::DEBUG, "proc_end");
+        } else {
+            tracing::warn!(name = var.name(), "proc_end without processing cycle");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::FakeVar,
+        variable::{Direction, Type},
+    };
+    use std::{
+        fmt::{self, Write},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    /// Records spans and events as text lines.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+        last_id: Arc<AtomicU64>,
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            write!(self.0, " {}={:?}", field, value).unwrap();
+        }
+    }
+
+    impl Recorder {
+        fn push(&self, kind: &str, record: impl FnOnce(&mut Fields)) {
+            let mut fields = Fields(kind.into());
+            record(&mut fields);
+            self.log.lock().unwrap().push(fields.0);
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.push(span.metadata().name(), |fields| span.record(fields));
+            Id::from_u64(self.last_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            self.push("record", |fields| values.record(fields));
+        }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let kind = match event.parent() {
+                Some(_) => "event",
+                None => "orphan",
+            };
+            self.push(kind, |fields| event.record(fields));
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+        fn try_close(&self, _: Id) -> bool {
+            self.push("close", |_| ());
+            true
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let var = FakeVar::new("TRACE:VAR", Type::U16, 0, Direction::Output)
+            .leak()
+            .var();
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let cycle = Cycle::default();
+            cycle.on_request(&var);
+            cycle.on_proc_begin(&var);
+            cycle.on_commit(&var, Err("Failed"));
+            cycle.on_proc_end(&var);
+            // Cycle initiated by IOC.
+            cycle.on_proc_begin(&var);
+            cycle.on_commit(&var, Ok(()));
+            cycle.on_proc_end(&var);
+            cycle.on_proc_end(&var);
+        });
+        let new_span = r#"processing name="TRACE:VAR" type_=U16 max_len=0"#;
+        assert_eq!(
+            *recorder.log.lock().unwrap(),
+            [
+                new_span,
+                "event message=request",
+                "event message=proc_begin",
+                r#"record status="error""#,
+                r#"record message="Failed""#,
+                "event message=commit",
+                "event message=proc_end",
+                "close",
+                new_span,
+                "event message=proc_begin",
+                r#"record status="ok""#,
+                "event message=commit",
+                "event message=proc_end",
+                "close",
+                r#"orphan message=proc_end without processing cycle name="TRACE:VAR""#,
+            ]
+        );
+    }
+}
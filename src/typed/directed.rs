@@ -0,0 +1,82 @@
+use super::{Acquire, Type, TypedVariable, Value};
+use crate::Variable;
+use futures::stream::FusedStream;
+use std::ops::Deref;
+
+/// Variable that receives values from IOC, backed by an output record (e.g. `ao`).
+///
+/// Application can only wait for the record to be processed and read the value.
+#[repr(transparent)]
+pub struct Input<V: Value + ?Sized> {
+    base: TypedVariable<V>,
+}
+
+/// Variable that sends values to IOC, backed by an input record (e.g. `ai`).
+///
+/// Application can only request record processing and write the value.
+#[repr(transparent)]
+pub struct Output<V: Value + ?Sized> {
+    base: TypedVariable<V>,
+}
+
+impl<V: Value + ?Sized> Input<V> {
+    pub(crate) fn new(base: TypedVariable<V>) -> Self {
+        Self { base }
+    }
+    /// Passively wait for variable being processed.
+    pub fn wait(&mut self) -> Acquire<'_, V> {
+        self.base.wait()
+    }
+}
+
+impl<T: Type> Input<T> {
+    pub fn into_stream(self) -> impl FusedStream<Item = T> {
+        self.base.into_stream()
+    }
+}
+
+impl<T: Type> Input<[T]> {
+    pub fn max_len(&self) -> usize {
+        self.base.max_len()
+    }
+}
+
+impl<V: Value + ?Sized> Output<V> {
+    pub(crate) fn new(base: TypedVariable<V>) -> Self {
+        Self { base }
+    }
+    /// Actively request variable processing.
+    pub fn request(&mut self) -> Acquire<'_, V> {
+        self.base.request()
+    }
+}
+
+impl<T: Type> Output<T> {
+    /// Request processing and write value.
+    pub async fn write(&mut self, value: T) {
+        self.request().await.write(value).await
+    }
+}
+
+impl<T: Type> Output<[T]> {
+    pub fn max_len(&self) -> usize {
+        self.base.max_len()
+    }
+    /// Request processing and write values from slice, truncating it to [`Self::max_len`].
+    pub async fn write_from_slice(&mut self, slice: &[T]) {
+        self.request().await.write_from_slice(slice).await
+    }
+}
+
+impl<V: Value + ?Sized> Deref for Input<V> {
+    type Target = Variable;
+    fn deref(&self) -> &Variable {
+        &self.base
+    }
+}
+impl<V: Value + ?Sized> Deref for Output<V> {
+    type Target = Variable;
+    fn deref(&self) -> &Variable {
+        &self.base
+    }
+}
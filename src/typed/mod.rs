@@ -1,7 +1,9 @@
 mod array;
+mod directed;
 mod scalar;
 
 pub use array::FlatVec;
+pub use directed::{Input, Output};
 pub use scalar::Type;
 
 use crate::{
@@ -65,6 +67,7 @@ impl<V: Value + ?Sized> Unpin for Acquire<'_, V> {}
 impl<'a, V: Value + ?Sized> Future for Acquire<'a, V> {
     type Output = ValueGuard<'a, V>;
 
+    #[allow(clippy::collapsible_match)]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let owner = self.owner.take().unwrap();
         let state = owner.state();
@@ -2,17 +2,20 @@ use atomig::{Atom, Atomic};
 use derive_more::{Deref, DerefMut};
 use futures::task::AtomicWaker;
 use std::{
+    borrow::Cow,
     ffi::CStr,
     mem::ManuallyDrop,
     os::raw::{c_char, c_void},
     ptr,
-    str::from_utf8,
+    str::{from_utf8, Utf8Error},
     sync::atomic::Ordering,
     task::Waker,
 };
 
 use super::import::*;
-pub use super::import::{FerVarInfo as Info, FerVarType as Type, FerVarValue as Value};
+pub use super::import::{
+    FerVarDir as Direction, FerVarInfo as Info, FerVarType as Type, FerVarValue as Value,
+};
 
 pub type Status<'a> = Result<(), &'a str>;
 
@@ -31,18 +34,79 @@ impl Variable {
     pub(crate) unsafe fn from_raw(raw: *mut FerVar) -> Self {
         Self { raw }
     }
+    /// Create another instance of the same variable.
+    pub(crate) unsafe fn clone_unchecked(&self) -> Self {
+        Self::from_raw(self.raw)
+    }
 
+    /// Variable name.
+    ///
+    /// *Variables with invalid names are never passed to application, see [`Diagnostic`](`crate::Diagnostic`).*
     pub fn name(&self) -> &str {
-        from_utf8(unsafe { CStr::from_ptr(fer_var_name(self.raw)) }.to_bytes()).unwrap()
+        self.try_name().unwrap()
+    }
+    pub(crate) fn try_name(&self) -> Result<&str, Utf8Error> {
+        from_utf8(self.raw_name())
     }
+    pub(crate) fn raw_name(&self) -> &[u8] {
+        unsafe { CStr::from_ptr(fer_var_name(self.raw)) }.to_bytes()
+    }
+
+    /// Variable type and size.
+    ///
+    /// *Variables of unsupported types are never passed to application, see [`Diagnostic`](`crate::Diagnostic`).*
     pub fn info(&self) -> Info {
-        unsafe { fer_var_info(self.raw) }
+        self.try_info().unwrap()
+    }
+    pub(crate) fn try_info(&self) -> Result<Info, u32> {
+        let raw = unsafe { fer_var_info(self.raw) };
+        match Type::from_raw(raw.type_) {
+            Some(type_) => Ok(Info {
+                type_,
+                max_len: raw.max_len,
+            }),
+            None => Err(raw.type_),
+        }
+    }
+
+    /// Direction of the backing record.
+    pub fn direction(&self) -> Direction {
+        self.try_direction().unwrap()
+    }
+    pub(crate) fn try_direction(&self) -> Result<Direction, u32> {
+        let raw = unsafe { fer_var_dir(self.raw) };
+        Direction::from_raw(raw).ok_or(raw)
+    }
+
+    /// Type of the backing record (e.g. `ai`).
+    ///
+    /// Invalid UTF-8 sequences are replaced with `U+FFFD`.
+    pub fn record_type(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(unsafe { CStr::from_ptr(fer_var_rtyp(self.raw)) }.to_bytes())
     }
 
     pub(crate) fn value_ptr(&self) -> *mut Value {
         unsafe { fer_var_value(self.raw) }
     }
 
+    /// Pointer to record field and its raw type, `None` if field doesn't exist.
+    pub(crate) fn field_ptr(&self, name: &str) -> Option<(*mut c_void, u32)> {
+        let mut type_ = 0;
+        let ptr = unsafe {
+            fer_var_field(
+                self.raw,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                &mut type_,
+            )
+        };
+        if ptr.is_null() {
+            None
+        } else {
+            Some((ptr, type_))
+        }
+    }
+
     fn user_data(&self) -> *mut c_void {
         unsafe { fer_var_user_data(self.raw) }
     }
@@ -86,12 +150,20 @@ impl SystemVariable {
         let state = self.state();
         let prev = state.swap_stage(Stage::Processing);
         debug_assert!(prev == Stage::Idle || prev == Stage::Requested);
+        #[cfg(feature = "metrics")]
+        state.metrics.on_proc_begin();
+        #[cfg(feature = "tracing")]
+        state.cycle.on_proc_begin(self);
         state.waker.wake();
     }
     pub unsafe fn proc_end(&mut self) {
         let state = self.state();
         let prev = state.swap_stage(Stage::Idle);
         debug_assert_eq!(prev, Stage::Committed);
+        #[cfg(feature = "metrics")]
+        state.metrics.on_proc_end();
+        #[cfg(feature = "tracing")]
+        state.cycle.on_proc_end(self);
         state.waker.wake();
     }
 }
@@ -112,12 +184,27 @@ impl<'a> LockedVariable<'a> {
     pub unsafe fn request_proc(&mut self) {
         let prev = self.state().swap_stage(Stage::Requested);
         debug_assert_eq!(prev, Stage::Idle);
+        #[cfg(feature = "metrics")]
+        self.state().metrics.on_request();
+        #[cfg(feature = "tracing")]
+        self.state().cycle.on_request(self);
         fer_var_request(self.raw);
     }
     pub unsafe fn commit(&mut self, status: Status<'_>) {
         let prev = self.state().swap_stage(Stage::Committed);
         debug_assert_eq!(prev, Stage::Processing);
 
+        #[cfg(feature = "metrics")]
+        self.state().metrics.on_commit(self, status);
+        #[cfg(feature = "tracing")]
+        self.state().cycle.on_commit(self, status);
+        #[cfg(feature = "events")]
+        self.state().subscribers.publish(|| crate::registry::Event {
+            name: self.name().into(),
+            value: self.load_dyn(),
+            status: status.map_err(String::from),
+            time: std::time::SystemTime::now(),
+        });
         match status {
             Ok(()) => fer_var_commit(self.raw, FerVarStatus::Ok, ptr::null(), 0),
             Err(message) => fer_var_commit(
@@ -148,6 +235,12 @@ pub(crate) enum Stage {
 pub(crate) struct SharedState {
     stage: Atomic<Stage>,
     waker: AtomicWaker,
+    #[cfg(feature = "events")]
+    pub subscribers: crate::registry::Subscribers,
+    #[cfg(feature = "metrics")]
+    pub metrics: crate::metrics::VarMetrics,
+    #[cfg(feature = "tracing")]
+    pub cycle: crate::trace::Cycle,
 }
 
 impl SharedState {
@@ -156,6 +249,12 @@ impl SharedState {
         Self {
             stage: Atomic::new(Stage::Idle),
             waker: AtomicWaker::new(),
+            #[cfg(feature = "events")]
+            subscribers: crate::registry::Subscribers::default(),
+            #[cfg(feature = "metrics")]
+            metrics: crate::metrics::VarMetrics::new(),
+            #[cfg(feature = "tracing")]
+            cycle: crate::trace::Cycle::default(),
         }
     }
 